@@ -0,0 +1,88 @@
+//! Memory per stored transaction record for the available `TransactionStore`s.
+//!
+//! Run with `cargo bench --bench memory`. A counting global allocator measures the bytes
+//! held by each store after inserting the same deposits.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use dashmap::DashMap;
+use payments_engine::compact_store::CompactTransactionStore;
+use payments_engine::storage::TransactionStore;
+use payments_engine::structures::{
+    Currency, TransactionRecord, TransactionState, TransactionType, TransactionsMap,
+};
+use rust_decimal::Decimal;
+
+/// Global allocator keeping track of the number of live heap bytes.
+struct CountingAllocator;
+
+static LIVE_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LIVE_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE_BYTES.fetch_sub(layout.size(), Ordering::Relaxed);
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        LIVE_BYTES.fetch_add(new_size, Ordering::Relaxed);
+        LIVE_BYTES.fetch_sub(layout.size(), Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+const RECORDS: u32 = 1_000_000;
+
+/// Inserts `RECORDS` deposits with IDs produced by `tx_id` and returns the bytes retained.
+fn measure<S: TransactionStore>(make_store: impl FnOnce() -> S, tx_id: fn(u32) -> u32) -> usize {
+    let before = LIVE_BYTES.load(Ordering::Relaxed);
+    let store = make_store();
+    for i in 0..RECORDS {
+        let record = TransactionRecord {
+            client_id: (i % u32::from(u16::MAX)) as u16,
+            amount: Decimal::new(i64::from(i % 100_000), 2),
+            state: TransactionState::Processed,
+            tx_type: TransactionType::Deposit,
+            destination: None,
+            currency: Currency::Eur,
+        };
+        store.insert_record(tx_id(i), record).unwrap();
+    }
+    assert_eq!(store.record_count().unwrap(), RECORDS as usize);
+    let retained = LIVE_BYTES.load(Ordering::Relaxed) - before;
+    drop(store);
+    retained
+}
+
+fn report(name: &str, ids: &str, bytes: usize) {
+    println!(
+        "{:<28} {:<10} {:>10.1} MiB {:>8.1} B/tx",
+        name,
+        ids,
+        bytes as f64 / (1024.0 * 1024.0),
+        bytes as f64 / f64::from(RECORDS)
+    );
+}
+
+fn main() {
+    let dense: fn(u32) -> u32 = |i| i + 1;
+    let scattered: fn(u32) -> u32 = |i| i.wrapping_mul(2_654_435_761);
+
+    println!("{RECORDS} deposit records");
+    for (ids, tx_id) in [("dense", dense), ("scattered", scattered)] {
+        let map = measure(|| -> TransactionsMap { Arc::new(DashMap::new()) }, tx_id);
+        report("TransactionsMap (DashMap)", ids, map);
+        let compact = measure(CompactTransactionStore::new, tx_id);
+        report("CompactTransactionStore", ids, compact);
+    }
+}
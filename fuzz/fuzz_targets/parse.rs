@@ -0,0 +1,40 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use payments_engine::producer::process_reader;
+use payments_engine::structures::{InputFormat, TransactionMessage};
+use tokio::sync::mpsc;
+
+/// Parses `data` in `format` and returns the messages produced before the first error.
+fn parse(data: &[u8], format: InputFormat) -> Vec<TransactionMessage> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        let (tx, mut rx) = mpsc::channel(64);
+        let producer = process_reader(std::io::Cursor::new(data.to_vec()), format, tx);
+        let consumer = async {
+            let mut messages = Vec::new();
+            while let Some(msg) = rx.recv().await {
+                messages.push(msg);
+            }
+            messages
+        };
+        // Malformed input may end with an error; only what was produced until then matters
+        let (_, messages) = tokio::join!(producer, consumer);
+        messages
+    })
+}
+
+fuzz_target!(|data: &[u8]| {
+    for format in [InputFormat::Auto, InputFormat::Csv, InputFormat::Ndjson] {
+        let messages = parse(data, format);
+
+        // Line numbers identify rows in reports, so they must be known and increasing
+        let lines: Vec<u64> = messages
+            .iter()
+            .map(|msg| msg.line.expect("parsed messages carry a line number"))
+            .collect();
+        assert!(lines.windows(2).all(|pair| pair[0] < pair[1]), "{lines:?}");
+    }
+});
@@ -0,0 +1,63 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use payments_engine::compact_store::CompactTransactionStore;
+use payments_engine::engine::Engine;
+use payments_engine::producer::process_reader;
+use payments_engine::structures::{
+    ClientsMap, EngineConfig, InputFormat, Outcome, TransactionMessage, TransactionType,
+};
+use rust_decimal::Decimal;
+use tokio::sync::mpsc;
+
+/// Parses `data`, detecting its format, and returns the messages produced before the
+/// first error.
+fn parse(data: &[u8]) -> Vec<TransactionMessage> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        let (tx, mut rx) = mpsc::channel(64);
+        let producer = process_reader(std::io::Cursor::new(data.to_vec()), InputFormat::Auto, tx);
+        let consumer = async {
+            let mut messages = Vec::new();
+            while let Some(msg) = rx.recv().await {
+                messages.push(msg);
+            }
+            messages
+        };
+        let (_, messages) = tokio::join!(producer, consumer);
+        messages
+    })
+}
+
+// Applies the parsed messages to an engine on the default in-memory stores and, as a
+// differential check, to one on the compact transaction index: both must agree on every
+// outcome and on the final accounts.
+fuzz_target!(|data: &[u8]| {
+    let config = EngineConfig {
+        check_invariants: true,
+        ..EngineConfig::default()
+    };
+    let mut engine = Engine::new().with_config(config.clone());
+    let mut compact = Engine::with_stores(ClientsMap::default(), CompactTransactionStore::new())
+        .with_config(config);
+
+    for msg in parse(data) {
+        let (client, tx_type) = (msg.client, msg.tx_type.clone());
+        let currency = engine.currency_of(&msg);
+        let result = engine.apply(msg.clone());
+        assert_eq!(compact.apply(msg), result);
+
+        if tx_type == TransactionType::Withdrawal && result == Ok(Outcome::Applied) {
+            let account = engine
+                .account_in(client, currency)
+                .expect("withdrawal opened the account");
+            assert!(account.available >= Decimal::ZERO, "{account:?}");
+        }
+    }
+
+    assert_eq!(engine.invariant_violation(), None);
+    assert_eq!(compact.invariant_violation(), None);
+    assert_eq!(engine.accounts(), compact.accounts());
+});
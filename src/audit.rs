@@ -0,0 +1,178 @@
+use std::io::SeekFrom;
+
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{self, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tracing::info;
+
+use crate::reports::{csv_row, report_amount};
+use crate::statements::status;
+use crate::structures::{AuditEntry, ProcessedTransaction};
+
+/// Builds the audit trail entry of a processed lock, unlock or adjustment.
+///
+/// Returns `None` for other transaction types. Balances are those of the client's account
+/// right after the message was processed, so a rejected message shows the unchanged state.
+pub fn audit_entry(processed: &ProcessedTransaction) -> Option<AuditEntry> {
+    let message = &processed.message;
+    if !message.tx_type.is_admin() {
+        return None;
+    }
+    let (status, rejection) = status(&processed.result);
+    let account = processed.account.clone().unwrap_or_default();
+
+    Some(AuditEntry {
+        operator: message.operator.clone(),
+        tx_type: message.tx_type.clone(),
+        client: message.client,
+        tx: message.tx,
+        amount: message.amount,
+        currency: processed.currency,
+        reason: message.reason.clone(),
+        input: message.source,
+        line: message.line,
+        status,
+        rejection,
+        available: report_amount(account.available),
+        held: report_amount(account.held),
+        total: report_amount(account.total),
+        locked: account.locked,
+    })
+}
+
+/// Column names of the audit trail.
+const AUDIT_HEADER: [&str; 15] = [
+    "operator",
+    "type",
+    "client",
+    "tx",
+    "amount",
+    "currency",
+    "reason",
+    "input",
+    "line",
+    "status",
+    "rejection",
+    "available",
+    "held",
+    "total",
+    "locked",
+];
+
+/// Opens the audit trail at `path` for appending, so the trails of consecutive runs
+/// accumulate in one file.
+///
+/// The file is created if needed, and the header row is written only if the file is
+/// empty, so a new trail is a valid CSV file even before any entry is written.
+pub async fn open_audit_trail(path: &str) -> io::Result<File> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    if file.metadata().await?.len() == 0 {
+        file.write_all(format!("{}\n", AUDIT_HEADER.join(",")).as_bytes())
+            .await?;
+        file.flush().await?;
+    }
+    Ok(file)
+}
+
+/// Returns the entries of `recovered` that are missing from the audit trail at `path`.
+///
+/// `recovered` are the entries of the messages replayed from the write-ahead log, in
+/// processing order. The interrupted run wrote a prefix of them to the trail, so the
+/// entries up to the one equal to the trail's last row are dropped; if the last row is
+/// none of them, the interrupted run wrote no entry and all are returned.
+pub async fn unrecorded_audit_entries(
+    path: &str,
+    mut recovered: Vec<AuditEntry>,
+) -> io::Result<Vec<AuditEntry>> {
+    if recovered.is_empty() {
+        return Ok(recovered);
+    }
+    let Some(last) = last_row(path).await? else {
+        return Ok(recovered);
+    };
+    let mut recorded = None;
+    for (i, entry) in recovered.iter().enumerate().rev() {
+        if csv_row(entry)? == last {
+            recorded = Some(i);
+            break;
+        }
+    }
+    Ok(match recorded {
+        Some(i) => recovered.split_off(i + 1),
+        None => recovered,
+    })
+}
+
+/// Returns the last row of the file at `path`, with its line terminator, or `None` if the
+/// file is missing or empty.
+async fn last_row(path: &str) -> io::Result<Option<Vec<u8>>> {
+    let mut file = match File::open(path).await {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let len = file.metadata().await?.len();
+    // Read a growing tail of the file until it holds the whole last row
+    let mut tail_len = 4096;
+    loop {
+        let start = len.saturating_sub(tail_len);
+        file.seek(SeekFrom::Start(start)).await?;
+        let mut tail = Vec::new();
+        file.read_to_end(&mut tail).await?;
+        let row_end = tail.len().saturating_sub(1);
+        match tail[..row_end].iter().rposition(|&b| b == b'\n') {
+            Some(i) => return Ok(Some(tail.split_off(i + 1))),
+            None if start == 0 => return Ok((!tail.is_empty()).then_some(tail)),
+            None => tail_len *= 2,
+        }
+    }
+}
+
+/// Appends `entries` to the audit trail `out`, flushing after each one.
+///
+/// # Returns
+/// The number of entries written, or an I/O error if they cannot be written.
+pub async fn append_audit_entries<W: AsyncWrite + Unpin>(
+    entries: impl IntoIterator<Item = AuditEntry>,
+    out: &mut W,
+) -> io::Result<usize> {
+    let mut written = 0;
+    for entry in entries {
+        out.write_all(&csv_row(&entry)?).await?;
+        out.flush().await?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+/// Appends the audit trail of the administrative messages received on the outcomes
+/// channel to `out`, typically opened with `open_audit_trail`, until the channel is
+/// closed.
+///
+/// Every lock, unlock and adjustment is written in processing order, applied or rejected,
+/// with its operator and the account state after it. Each entry is flushed as it is
+/// written, so the trail is complete up to the last processed message even if the run is
+/// interrupted.
+///
+/// # Arguments
+/// - `outcomes`: Receiving end of the channel passed to `process_transaction`.
+/// - `out`: Destination of the trail.
+///
+/// # Returns
+/// The number of entries written, or an I/O error if they cannot be written.
+pub async fn write_audit_report<W: AsyncWrite + Unpin>(
+    mut outcomes: mpsc::Receiver<ProcessedTransaction>,
+    mut out: W,
+) -> io::Result<usize> {
+    let mut written = 0;
+    while let Some(processed) = outcomes.recv().await {
+        written += append_audit_entries(audit_entry(&processed), &mut out).await?;
+    }
+
+    info!("Audit trail written: {} entries", written);
+    Ok(written)
+}
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use std::io;
+
+use crate::storage::TransactionStore;
+use crate::structures::{EngineConfig, TransactionState, TransactionType};
+
+/// An authorization still holding funds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Pending {
+    tx: u32,
+    /// Number of transactions of the client seen since the authorization.
+    seen: u64,
+    /// Timestamp of the authorization, or of the client's first timestamped transaction
+    /// after it.
+    since: Option<u64>,
+}
+
+/// Tracks the pending authorizations of every client and decides when they expire.
+///
+/// An authorization stays open for the next `EngineConfig::authorization_ttl` transactions
+/// of its client and expires before the one after, or before the first transaction of its
+/// client timestamped more than `EngineConfig::authorization_window` after it. An
+/// authorization without a timestamp is timed from the client's next timestamped
+/// transaction. Expiry is only evaluated when the client has another transaction, so it
+/// depends on the input alone and sharding does not change it.
+///
+/// Pending authorizations are loaded from the transaction store on first use, which covers
+/// state restored from a snapshot; their transaction counts restart at zero then.
+#[derive(Debug, Default)]
+pub struct AuthorizationTracker {
+    pending: Option<HashMap<u16, Vec<Pending>>>,
+}
+
+impl AuthorizationTracker {
+    /// Creates a tracker that loads pending authorizations on first use.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts a transaction of `client` at `timestamp` and returns the tx IDs of the
+    /// client's authorizations that expire before it is applied.
+    ///
+    /// Nothing expires unless `config` sets an authorization TTL or window. Expired
+    /// authorizations are no longer tracked.
+    pub fn expire_due<T: TransactionStore>(
+        &mut self,
+        transactions: &T,
+        config: &EngineConfig,
+        client: u16,
+        timestamp: Option<u64>,
+    ) -> io::Result<Vec<u32>> {
+        if config.authorization_ttl.is_none() && config.authorization_window.is_none() {
+            return Ok(Vec::new());
+        }
+        if self.pending.is_none() {
+            self.pending = Some(pending_by_client(transactions)?);
+        }
+        let pending = self.pending.as_mut().expect("loaded above");
+        let Some(authorizations) = pending.get_mut(&client) else {
+            return Ok(Vec::new());
+        };
+
+        let mut expired = Vec::new();
+        authorizations.retain_mut(|auth| {
+            if auth.since.is_none() {
+                auth.since = timestamp;
+            }
+            let stale = config.authorization_ttl.is_some_and(|ttl| auth.seen >= ttl);
+            let late = match (config.authorization_window, auth.since, timestamp) {
+                (Some(window), Some(since), Some(now)) => now.saturating_sub(since) > window,
+                _ => false,
+            };
+            if stale || late {
+                expired.push(auth.tx);
+                false
+            } else {
+                auth.seen += 1;
+                true
+            }
+        });
+        if authorizations.is_empty() {
+            pending.remove(&client);
+        }
+        Ok(expired)
+    }
+
+    /// Starts tracking authorization `tx` of `client`, applied at `timestamp`.
+    pub fn insert(&mut self, client: u16, tx: u32, timestamp: Option<u64>) {
+        if let Some(pending) = self.pending.as_mut() {
+            pending.entry(client).or_default().push(Pending {
+                tx,
+                seen: 0,
+                since: timestamp,
+            });
+        }
+    }
+
+    /// Stops tracking authorization `tx` of `client`, e.g. once it was captured or voided.
+    pub fn remove(&mut self, client: u16, tx: u32) {
+        if let Some(pending) = self.pending.as_mut()
+            && let Some(authorizations) = pending.get_mut(&client)
+        {
+            authorizations.retain(|auth| auth.tx != tx);
+            if authorizations.is_empty() {
+                pending.remove(&client);
+            }
+        }
+    }
+}
+
+/// Collects the authorizations in the store that still hold funds, keyed by client.
+fn pending_by_client<T: TransactionStore>(
+    transactions: &T,
+) -> io::Result<HashMap<u16, Vec<Pending>>> {
+    let mut pending: HashMap<u16, Vec<Pending>> = HashMap::new();
+    transactions.for_each_record(&mut |tx, record| {
+        if record.tx_type == TransactionType::Authorize
+            && record.state == TransactionState::Processed
+        {
+            pending.entry(record.client_id).or_default().push(Pending {
+                tx,
+                seen: 0,
+                since: None,
+            });
+        }
+        Ok(())
+    })?;
+    // Stores may visit records in any order
+    for authorizations in pending.values_mut() {
+        authorizations.sort_unstable_by_key(|auth| auth.tx);
+    }
+    Ok(pending)
+}
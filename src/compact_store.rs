@@ -0,0 +1,380 @@
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use rust_decimal::Decimal;
+
+use crate::storage::TransactionStore;
+use crate::structures::{Currency, TransactionRecord, TransactionState, TransactionType};
+
+/// Number of low tx id bits addressing a slot inside a chunk.
+const CHUNK_BITS: u32 = 8;
+
+/// Number of slots per chunk.
+const CHUNK_SLOTS: usize = 1 << CHUNK_BITS;
+
+/// Number of entries at which a sparse chunk (12 bytes per entry) is converted to a dense
+/// one (11 bytes per slot), i.e. roughly where the dense layout becomes smaller.
+const DENSE_THRESHOLD: usize = CHUNK_SLOTS * 11 / 12;
+
+/// Largest amount scale stored inline; amounts with a larger scale go to the overflow map.
+const MAX_INLINE_SCALE: u32 = 7;
+
+/// Marker in `Chunk::amounts` for an amount kept in `Slab::overflow`.
+const OVERFLOW_AMOUNT: i64 = i64::MIN;
+
+/// Memory-compact `TransactionStore` for `u32` transaction IDs.
+///
+/// Records are grouped into chunks of 256 consecutive tx IDs. A densely used chunk stores
+/// each slot in 11 bytes in struct-of-arrays layout: the amount's mantissa as `i64`, the
+/// client ID as `u16`, and one tag byte packing the transaction type, lifecycle state and
+/// the amount's scale. Chunks with few records keep a sorted list of 12-byte entries
+/// instead, so widely scattered tx IDs do not allocate whole chunks. Amounts that
+/// do not fit inline (more than 7 decimal places or a mantissa beyond `i64`) are kept
+/// exactly in a small overflow map, as are the destination clients of transfers, the
+/// currencies of records not in `Currency::Eur` and the type and state of records the tag
+/// cannot encode (e.g. a deposit marked captured).
+///
+/// Cloning is cheap and yields a handle to the same store.
+#[derive(Debug, Clone, Default)]
+pub struct CompactTransactionStore {
+    slab: Arc<Mutex<Slab>>,
+}
+
+#[derive(Debug, Default)]
+struct Slab {
+    chunks: HashMap<u32, Chunk>,
+    overflow: HashMap<u32, Decimal>,
+    destinations: HashMap<u32, u16>,
+    currencies: HashMap<u32, Currency>,
+    kinds: HashMap<u32, (TransactionType, TransactionState)>,
+    len: usize,
+}
+
+/// One packed record: amount mantissa, client ID and tag byte (`0` marks an empty slot).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Slot {
+    amount: i64,
+    client: u16,
+    tag: u8,
+}
+
+/// Entry of a sparse chunk, packed to 12 bytes.
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed(4))]
+struct SparseSlot {
+    amount: i64,
+    client: u16,
+    tag: u8,
+    index: u8,
+}
+
+impl SparseSlot {
+    fn new(index: usize, slot: Slot) -> Self {
+        Self {
+            amount: slot.amount,
+            client: slot.client,
+            tag: slot.tag,
+            index: index as u8,
+        }
+    }
+
+    fn slot(self) -> Slot {
+        Slot {
+            amount: self.amount,
+            client: self.client,
+            tag: self.tag,
+        }
+    }
+}
+
+/// Struct-of-arrays storage of a fully allocated chunk.
+#[derive(Debug)]
+struct DenseChunk {
+    amounts: [i64; CHUNK_SLOTS],
+    clients: [u16; CHUNK_SLOTS],
+    tags: [u8; CHUNK_SLOTS],
+}
+
+#[derive(Debug)]
+enum Chunk {
+    /// Entries sorted by slot index.
+    Sparse(Vec<SparseSlot>),
+    Dense(Box<DenseChunk>),
+}
+
+impl Chunk {
+    fn get(&self, index: usize) -> Option<Slot> {
+        match self {
+            Chunk::Sparse(entries) => entries
+                .binary_search_by_key(&index, |entry| usize::from(entry.index))
+                .ok()
+                .map(|pos| entries[pos].slot()),
+            Chunk::Dense(dense) => (dense.tags[index] != 0).then(|| Slot {
+                amount: dense.amounts[index],
+                client: dense.clients[index],
+                tag: dense.tags[index],
+            }),
+        }
+    }
+
+    /// Stores `slot` at `index` and returns whether the slot was previously empty.
+    fn set(&mut self, index: usize, slot: Slot) -> bool {
+        match self {
+            Chunk::Sparse(entries) => {
+                match entries.binary_search_by_key(&index, |entry| usize::from(entry.index)) {
+                    Ok(pos) => {
+                        entries[pos] = SparseSlot::new(index, slot);
+                        false
+                    }
+                    Err(pos) => {
+                        if entries.len() >= DENSE_THRESHOLD {
+                            self.make_dense();
+                            return self.set(index, slot);
+                        }
+                        // Grow one entry at a time; sparse chunks stay small
+                        entries.reserve_exact(1);
+                        entries.insert(pos, SparseSlot::new(index, slot));
+                        true
+                    }
+                }
+            }
+            Chunk::Dense(dense) => {
+                let was_empty = dense.tags[index] == 0;
+                dense.amounts[index] = slot.amount;
+                dense.clients[index] = slot.client;
+                dense.tags[index] = slot.tag;
+                was_empty
+            }
+        }
+    }
+
+    fn make_dense(&mut self) {
+        let mut dense = Box::new(DenseChunk {
+            amounts: [0; CHUNK_SLOTS],
+            clients: [0; CHUNK_SLOTS],
+            tags: [0; CHUNK_SLOTS],
+        });
+        if let Chunk::Sparse(entries) = self {
+            for entry in entries.iter() {
+                let index = usize::from(entry.index);
+                dense.amounts[index] = entry.amount;
+                dense.clients[index] = entry.client;
+                dense.tags[index] = entry.tag;
+            }
+        }
+        *self = Chunk::Dense(dense);
+    }
+}
+
+impl CompactTransactionStore {
+    /// Creates an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn slab(&self) -> MutexGuard<'_, Slab> {
+        // Every update of a slot is a plain store, so a poisoned lock holds consistent data
+        self.slab
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl TransactionStore for CompactTransactionStore {
+    fn record(&self, tx: u32) -> io::Result<Option<TransactionRecord>> {
+        let slab = self.slab();
+        let (chunk_id, index) = split(tx);
+        Ok(slab
+            .chunks
+            .get(&chunk_id)
+            .and_then(|chunk| chunk.get(index))
+            .map(|slot| unpack(tx, slot, &slab)))
+    }
+
+    fn insert_record(&self, tx: u32, record: TransactionRecord) -> io::Result<()> {
+        let mut slab = self.slab();
+        let (chunk_id, index) = split(tx);
+
+        let (amount, scale) = match inline_amount(record.amount) {
+            Some((mantissa, scale)) => {
+                slab.overflow.remove(&tx);
+                (mantissa, scale)
+            }
+            None => {
+                slab.overflow.insert(tx, record.amount);
+                (OVERFLOW_AMOUNT, 0)
+            }
+        };
+        match record.destination {
+            Some(destination) => slab.destinations.insert(tx, destination),
+            None => slab.destinations.remove(&tx),
+        };
+        match record.currency {
+            Currency::Eur => slab.currencies.remove(&tx),
+            currency => slab.currencies.insert(tx, currency),
+        };
+        let tag = pack_kind(&mut slab.kinds, tx, record.tx_type, record.state);
+        let slot = Slot {
+            amount,
+            client: record.client_id,
+            tag: tag | ((scale as u8) << SCALE_SHIFT),
+        };
+
+        let inserted = slab
+            .chunks
+            .entry(chunk_id)
+            .or_insert_with(|| Chunk::Sparse(Vec::new()))
+            .set(index, slot);
+        if inserted {
+            slab.len += 1;
+        }
+        Ok(())
+    }
+
+    fn set_state(&self, tx: u32, state: TransactionState) -> io::Result<()> {
+        let mut slab = self.slab();
+        let Slab { chunks, kinds, .. } = &mut *slab;
+        let (chunk_id, index) = split(tx);
+        if let Some(chunk) = chunks.get_mut(&chunk_id)
+            && let Some(mut slot) = chunk.get(index)
+        {
+            let (tx_type, _) = unpack_kind(tx, slot.tag, kinds);
+            slot.tag = (slot.tag & SCALE_MASK) | pack_kind(kinds, tx, tx_type, state);
+            chunk.set(index, slot);
+        }
+        Ok(())
+    }
+
+    fn record_count(&self) -> io::Result<usize> {
+        Ok(self.slab().len)
+    }
+
+    fn for_each_record(
+        &self,
+        f: &mut dyn FnMut(u32, TransactionRecord) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let slab = self.slab();
+        let mut chunk_ids: Vec<u32> = slab.chunks.keys().copied().collect();
+        chunk_ids.sort_unstable();
+
+        for chunk_id in chunk_ids {
+            let chunk = &slab.chunks[&chunk_id];
+            for index in 0..CHUNK_SLOTS {
+                let tx = (chunk_id << CHUNK_BITS) | index as u32;
+                if let Some(slot) = chunk.get(index) {
+                    f(tx, unpack(tx, slot, &slab))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Splits a tx ID into its chunk ID and slot index.
+fn split(tx: u32) -> (u32, usize) {
+    (tx >> CHUNK_BITS, (tx as usize) & (CHUNK_SLOTS - 1))
+}
+
+/// Returns the `(mantissa, scale)` of `amount` if it can be stored inline.
+fn inline_amount(amount: Decimal) -> Option<(i64, u32)> {
+    let mantissa = i64::try_from(amount.mantissa()).ok()?;
+    (amount.scale() <= MAX_INLINE_SCALE && mantissa != OVERFLOW_AMOUNT)
+        .then_some((mantissa, amount.scale()))
+}
+
+// Tag layout: bits 0-1 state, bits 2-4 transaction type code, bits 5-7 amount scale. The
+// state bits depend on the type: authorizations end captured, voided or expired, other
+// records go through the dispute states.
+const STATE_MASK: u8 = 0b0000_0011;
+const TYPE_SHIFT: u8 = 2;
+const TYPE_MASK: u8 = 0b0001_1100;
+const SCALE_SHIFT: u8 = 5;
+const SCALE_MASK: u8 = 0b1110_0000;
+
+/// Type code of a record whose type and state are kept in `Slab::kinds`.
+const OTHER_TYPE: u8 = 7;
+
+/// Returns the state and type bits of a tag for `tx_type` in `state`, moving the record
+/// to or from `kinds` depending on whether the tag can encode them.
+fn pack_kind(
+    kinds: &mut HashMap<u32, (TransactionType, TransactionState)>,
+    tx: u32,
+    tx_type: TransactionType,
+    state: TransactionState,
+) -> u8 {
+    let type_bits = match tx_type {
+        TransactionType::Deposit => Some(1),
+        TransactionType::Withdrawal => Some(2),
+        TransactionType::Transfer => Some(3),
+        TransactionType::Authorize => Some(4),
+        TransactionType::Adjustment => Some(5),
+        _ => None,
+    };
+    let state_bits = match (&tx_type, state) {
+        (_, TransactionState::Processed) => Some(0),
+        (TransactionType::Authorize, TransactionState::Captured) => Some(1),
+        (TransactionType::Authorize, TransactionState::Voided) => Some(2),
+        (TransactionType::Authorize, TransactionState::Expired) => Some(3),
+        (TransactionType::Authorize, _) => None,
+        (_, TransactionState::Disputed) => Some(1),
+        (_, TransactionState::Resolved) => Some(2),
+        (_, TransactionState::ChargedBack) => Some(3),
+        _ => None,
+    };
+
+    match (type_bits, state_bits) {
+        (Some(type_bits), Some(state_bits)) => {
+            kinds.remove(&tx);
+            state_bits | (type_bits << TYPE_SHIFT)
+        }
+        _ => {
+            kinds.insert(tx, (tx_type, state));
+            OTHER_TYPE << TYPE_SHIFT
+        }
+    }
+}
+
+/// Decodes the type and state of transaction `tx` from its tag.
+fn unpack_kind(
+    tx: u32,
+    tag: u8,
+    kinds: &HashMap<u32, (TransactionType, TransactionState)>,
+) -> (TransactionType, TransactionState) {
+    let tx_type = match (tag & TYPE_MASK) >> TYPE_SHIFT {
+        1 => TransactionType::Deposit,
+        2 => TransactionType::Withdrawal,
+        3 => TransactionType::Transfer,
+        4 => TransactionType::Authorize,
+        5 => TransactionType::Adjustment,
+        _ => return kinds[&tx].clone(),
+    };
+    let state = match (&tx_type, tag & STATE_MASK) {
+        (_, 0) => TransactionState::Processed,
+        (TransactionType::Authorize, 1) => TransactionState::Captured,
+        (TransactionType::Authorize, 2) => TransactionState::Voided,
+        (TransactionType::Authorize, _) => TransactionState::Expired,
+        (_, 1) => TransactionState::Disputed,
+        (_, 2) => TransactionState::Resolved,
+        _ => TransactionState::ChargedBack,
+    };
+    (tx_type, state)
+}
+
+/// Decodes a non-empty slot of transaction `tx`.
+fn unpack(tx: u32, slot: Slot, slab: &Slab) -> TransactionRecord {
+    let (tx_type, state) = unpack_kind(tx, slot.tag, &slab.kinds);
+    let amount = match slot.amount {
+        OVERFLOW_AMOUNT => slab.overflow[&tx],
+        mantissa => Decimal::new(mantissa, u32::from(slot.tag >> SCALE_SHIFT)),
+    };
+
+    TransactionRecord {
+        client_id: slot.client,
+        amount,
+        state,
+        tx_type,
+        destination: slab.destinations.get(&tx).copied(),
+        currency: slab.currencies.get(&tx).copied().unwrap_or_default(),
+    }
+}
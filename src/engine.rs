@@ -1,194 +1,1200 @@
-use tokio::sync::mpsc;
-use tracing::{info, warn};
+use std::io;
 
+use itertools::Itertools;
+use rust_decimal::Decimal;
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::task::{JoinError, JoinHandle};
+use tracing::{error, info, warn};
+
+use crate::authorizations::AuthorizationTracker;
+use crate::invariants::{InvariantChecker, InvariantViolation};
+use crate::storage::{AccountStore, TransactionStore};
 use crate::structures::{
-    ClientsMap, TransactionMessage, TransactionRecord, TransactionType, TransactionsMap,
+    AccountSummary, ClientAccount, ClientsMap, Currency, EngineConfig, Outcome,
+    ProcessedTransaction, RedisputePolicy, TransactionError, TransactionMessage, TransactionRecord,
+    TransactionState, TransactionType, TransactionsMap, WithdrawalDisputePolicy,
 };
+use crate::validation::validate;
+use crate::wal::WalWriter;
 
-/// Processes incoming transaction messages asynchronously.
+/// Synchronous transaction engine holding client accounts and transaction records.
 ///
-/// This function listens on a channel for incoming `TransactionMessage`s and applies
-/// the appropriate logic to update client accounts and track transaction records.
-/// It supports the following transaction types:
-/// - Deposit: Adds funds to the client's account.
-/// - Withdrawal: Removes funds from the client's available balance.
-/// - Dispute: Moves a deposit amount from available to held funds.
-/// - Resolve: Moves a held amount back to available funds.
-/// - Chargeback: Removes held funds and locks the client's account.
-/// - Terminate: Terminates the processing loop.
+/// `Engine` applies `TransactionMessage`s one at a time and can be embedded directly
+/// in library code without a tokio runtime or channel. The asynchronous
+/// `process_transaction` loop is a thin wrapper around it.
 ///
-/// # Arguments
-/// * `receiver` - An `mpsc::Receiver` for receiving `TransactionMessage`s.
-/// * `clients` - A thread-safe map (`ClientsMap`) of client accounts.
-/// * `transactions` - A thread-safe map (`TransactionsMap`) storing transaction records.
-///
-/// # Notes
-/// - Accounts that are locked will not process any new transactions.
-/// - Transactions are logged for auditing and error diagnosis.
-///
-/// # Panics
-/// This function does not panic but logs errors for invalid transactions.
+/// Accounts and transaction records live in pluggable `AccountStore` and
+/// `TransactionStore` backends. By default these are the in-memory `ClientsMap` and
+/// `TransactionsMap`; an on-disk `storage::SqliteStore` can be used via `with_stores`.
 ///
 /// # Example
-/// ```no_run
-/// let (tx, rx) = mpsc::channel(100);
-/// let clients = Arc::new(DashMap::new());
-/// let transactions = Arc::new(DashMap::new());
-/// tokio::spawn(async move {
-///     process_transaction(rx, clients, transactions).await;
-/// });
 /// ```
-pub async fn process_transaction(
-    mut receiver: mpsc::Receiver<TransactionMessage>,
-    clients: ClientsMap,
-    transactions: TransactionsMap,
-) {
-    while let Some(msg) = receiver.recv().await {
-        info!("msg received: {:?}", msg);
-        if msg.tx_type == TransactionType::Terminate {
-            warn!("Terminate message received, stopping processor.");
-            break;
+/// # use payments_engine::engine::Engine;
+/// # use payments_engine::structures::{Outcome, TransactionMessage, TransactionType};
+/// # use rust_decimal::Decimal;
+/// let mut engine = Engine::new();
+/// let deposit = TransactionMessage::new(TransactionType::Deposit, 1, 1, Some(Decimal::new(10, 0)));
+/// assert_eq!(engine.apply(deposit), Ok(Outcome::Applied));
+/// assert_eq!(engine.account(1).unwrap().available, Decimal::new(10, 0));
+/// ```
+#[derive(Debug, Default)]
+pub struct Engine<A: AccountStore = ClientsMap, T: TransactionStore = TransactionsMap> {
+    clients: A,
+    transactions: T,
+    config: EngineConfig,
+    wal: Option<WalWriter>,
+    wal_failure: Option<io::Error>,
+    invariants: InvariantChecker,
+    authorizations: AuthorizationTracker,
+    expired: Vec<ProcessedTransaction>,
+}
+
+impl Engine {
+    /// Creates an engine with empty account and transaction maps.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an engine operating on existing, possibly shared, maps.
+    pub fn with_maps(clients: ClientsMap, transactions: TransactionsMap) -> Self {
+        Self::with_stores(clients, transactions)
+    }
+}
+
+impl<A: AccountStore, T: TransactionStore> Engine<A, T> {
+    /// Creates an engine operating on the given storage backends.
+    pub fn with_stores(clients: A, transactions: T) -> Self {
+        Self {
+            clients,
+            transactions,
+            config: EngineConfig::default(),
+            wal: None,
+            wal_failure: None,
+            invariants: InvariantChecker::new(),
+            authorizations: AuthorizationTracker::new(),
+            expired: Vec::new(),
         }
+    }
 
-        let mut client_entry = clients.entry(msg.client).or_default();
-        let account = client_entry.clone();
+    /// Sets the business-rule configuration used by `apply`.
+    pub fn with_config(mut self, config: EngineConfig) -> Self {
+        self.config = config;
+        self
+    }
 
-        if account.locked {
-            warn!(
-                "Account {} is locked. Ignoring transaction: {:?}",
-                msg.client, msg
-            );
-            continue;
+    /// Logs every message processed by the asynchronous loop to a write-ahead log.
+    ///
+    /// `apply` itself never writes to the log, so recovery can replay entries through it.
+    pub fn with_wal(mut self, wal: WalWriter) -> Self {
+        self.wal = Some(wal);
+        self
+    }
+
+    /// Returns the error that stopped the asynchronous loop from writing the write-ahead
+    /// log, if any.
+    ///
+    /// The loop stops at the first failed append, so the log may miss the last message
+    /// applied and must not be truncated.
+    pub fn wal_failure(&self) -> Option<&io::Error> {
+        self.wal_failure.as_ref()
+    }
+
+    /// Returns the sequence number of the last entry written to the write-ahead log, if
+    /// the engine keeps one.
+    pub fn wal_seq(&self) -> Option<u64> {
+        self.wal.as_ref().map(WalWriter::last_seq)
+    }
+
+    /// Makes the invariant checks of this engine track held funds together with `other`'s,
+    /// which is required when both engines share their stores, see
+    /// `InvariantChecker::share_index`.
+    pub fn share_invariants_with(&mut self, other: &Self) {
+        self.invariants.share_index(&other.invariants);
+    }
+
+    /// Returns the first invariant violation observed while
+    /// `EngineConfig::check_invariants` was enabled, if any.
+    pub fn invariant_violation(&self) -> Option<&InvariantViolation> {
+        self.invariants.first_violation()
+    }
+
+    /// Returns the authorizations that expired while the last message was applied.
+    ///
+    /// Each is reported as a `void` of the authorization with `Outcome::Expired`, the
+    /// input line of the message that triggered the expiry and the account right after the
+    /// held funds were released.
+    pub fn expired(&self) -> &[ProcessedTransaction] {
+        &self.expired
+    }
+
+    /// Returns a copy of the account state for `client` in
+    /// `EngineConfig::default_currency`, if the account exists.
+    ///
+    /// A storage error is logged and reported as a missing account.
+    pub fn account(&self, client: u16) -> Option<ClientAccount> {
+        self.account_in(client, self.config.default_currency)
+    }
+
+    /// Returns a copy of the account state for `client` in `currency`, if the account
+    /// exists.
+    ///
+    /// A storage error is logged and reported as a missing account.
+    pub fn account_in(&self, client: u16, currency: Currency) -> Option<ClientAccount> {
+        self.clients.account(client, currency).unwrap_or_else(|e| {
+            error!("Failed to read account {} ({}): {}", client, currency, e);
+            None
+        })
+    }
+
+    /// Returns a summary of every account, sorted by client ID and currency.
+    ///
+    /// A storage error is logged and reported as no accounts.
+    pub fn accounts(&self) -> Vec<AccountSummary> {
+        self.clients
+            .all_accounts()
+            .unwrap_or_else(|e| {
+                error!("Failed to read accounts: {}", e);
+                Vec::new()
+            })
+            .iter()
+            .map(|(client, currency, account)| AccountSummary::new(*client, *currency, account))
+            .sorted_by_key(|summary| (summary.client, summary.currency))
+            .collect()
+    }
+
+    /// Applies a single transaction message to the engine state.
+    ///
+    /// The supported transaction types behave as follows:
+    /// - Deposit: Adds funds to the client's account.
+    /// - Withdrawal: Removes funds from the client's available balance.
+    /// - Transfer: Moves funds from the client's available balance to the destination
+    ///   client's; rejected if either account is locked.
+    /// - Dispute: Moves a deposit amount from available to held funds. Depending on
+    ///   `EngineConfig::withdrawal_disputes`, a disputed withdrawal is either rejected or
+    ///   provisionally credited back as held funds. A disputed transfer, raised by its
+    ///   sender, holds the amount on the destination account.
+    /// - Resolve: Moves a held deposit or transfer amount back to available funds, or
+    ///   reverses the provisional credit of a disputed withdrawal.
+    /// - Chargeback: Removes held deposit funds (or makes a withdrawal's provisional credit
+    ///   available) and locks the client's account. A charged back transfer returns the
+    ///   held amount to the sender and locks the destination account instead.
+    /// - Authorize: Moves an amount from available to held funds until it is captured or
+    ///   voided.
+    /// - Capture: Settles a pending authorization, removing the captured amount (the whole
+    ///   authorized amount unless the message names a smaller one) from held funds and
+    ///   releasing the rest.
+    /// - Void: Releases the held amount of a pending authorization.
+    /// - Lock, Unlock: Locks or unlocks the client's account, e.g. to reopen an account
+    ///   frozen by a chargeback.
+    /// - Adjustment: Adds a signed amount to the client's available funds; rejected if a
+    ///   debit exceeds them.
+    ///
+    /// Lock, unlock and adjustment are administrative: they must name an operator and are
+    /// accepted even on a locked account. They are reported like any other message, so
+    /// their outcome can be kept in an audit trail (see `audit`).
+    ///
+    /// Authorizations expire as described in `authorizations::AuthorizationTracker` when
+    /// `EngineConfig::authorization_ttl` or `EngineConfig::authorization_window` is set.
+    /// Expired authorizations are released before the message triggering the expiry is
+    /// applied, even if that message is then rejected, and are listed by `expired`.
+    ///
+    /// Every client has one account per currency. Deposits, withdrawals and transfers
+    /// apply in the message's currency, or `EngineConfig::default_currency` if it names
+    /// none; a transfer credits the destination in the same currency. Dispute, resolve,
+    /// chargeback, capture and void apply in the currency of the referenced transaction
+    /// and are rejected if they name a different one. Locking affects a single currency
+    /// account.
+    ///
+    /// Dispute, resolve and chargeback follow the per-transaction lifecycle
+    /// `Processed -> Disputed -> Resolved | ChargedBack`; a resolved transaction may be
+    /// disputed again only if `EngineConfig::redisputes` allows it.
+    ///
+    /// Messages are first checked by `validation::validate` according to
+    /// `EngineConfig::validation`; invalid messages are rejected without touching any
+    /// state. Otherwise an account is created on first reference, even if the message
+    /// is then rejected. Messages involving two accounts update both or neither.
+    ///
+    /// A deposit, withdrawal, transfer, authorization or adjustment reusing a recorded
+    /// transaction ID is rejected as a duplicate, unless `EngineConfig::idempotent_replays`
+    /// is set and the message exactly matches the recorded type, client, amount,
+    /// destination and currency, in which case it is acknowledged as a no-op.
+    ///
+    /// # Returns
+    /// - `Ok(Outcome::Applied)` if the message changed account state.
+    /// - `Ok(Outcome::Replayed)` if the message was an acknowledged idempotent replay.
+    /// - `Err(TransactionError)` explaining why the message was rejected. A rejected
+    ///   message never modifies any account, except by expiring authorizations. A failing
+    ///   storage backend is reported as `TransactionError::Storage`; the message's writes
+    ///   are then undone as one unit (see `AccountStore::begin`).
+    ///
+    /// # Panics
+    /// With `EngineConfig::check_invariants` enabled, the accounts changed by every applied
+    /// message are validated (see `invariants::InvariantChecker`). A violation is
+    /// logged and recorded; with `EngineConfig::panic_on_violation` it also panics.
+    pub fn apply(&mut self, msg: TransactionMessage) -> Result<Outcome, TransactionError> {
+        self.expired.clear();
+        let storage = storage_error(msg.tx);
+        self.clients.begin().map_err(storage)?;
+        if let Err(e) = self.transactions.begin() {
+            let _ = self.clients.rollback();
+            return Err(storage(e));
+        }
+
+        let checked = self.config.check_invariants.then(|| msg.clone());
+        let result = self.apply_message(msg);
+
+        // A message failing on a storage error is undone as a whole, a rejected one keeps
+        // the accounts it opened and the authorizations it expired
+        let ended = if matches!(result, Err(TransactionError::Storage { .. })) {
+            self.expired.clear();
+            // Reloaded from the store on next use, as expiries may have been undone
+            self.authorizations = AuthorizationTracker::new();
+            self.transactions.rollback().and(self.clients.rollback())
+        } else {
+            self.transactions.commit().and(self.clients.commit())
+        };
+        ended.map_err(storage)?;
+
+        if let Some(checked) = checked {
+            let expired = std::mem::take(&mut self.expired);
+            for processed in &expired {
+                self.check_invariants(&processed.message, processed.currency);
+            }
+            self.expired = expired;
+            if result == Ok(Outcome::Applied) {
+                self.check_invariants(&checked, self.currency_of(&checked));
+            }
+        }
+        result
+    }
+
+    /// Checks the accounts changed by `msg`, logging a violation and panicking on it if
+    /// `EngineConfig::panic_on_violation` is set.
+    fn check_invariants(&mut self, msg: &TransactionMessage, currency: Currency) {
+        if let Err(violation) =
+            self.invariants
+                .check(&self.clients, &self.transactions, msg, currency)
+        {
+            error!("Invariant violated: {}", violation);
+            if self.config.panic_on_violation {
+                panic!("invariant violated: {violation}");
+            }
+        }
+    }
+
+    fn apply_message(&mut self, msg: TransactionMessage) -> Result<Outcome, TransactionError> {
+        let msg = validate(msg, self.config.validation)?;
+        let storage = storage_error(msg.tx);
+
+        self.expire_authorizations(&msg)?;
+
+        let currency = self.resolve_currency(&msg)?;
+
+        // Accounts are read, modified and written back once the message is applied
+        let mut account = self.open_account(msg.client, currency, msg.tx)?;
+
+        if account.locked && !msg.tx_type.is_admin() {
+            return Err(TransactionError::AccountLocked { client: msg.client });
         }
 
+        if let Some(outcome) = check_duplicate(&self.transactions, &self.config, &msg, currency)? {
+            return Ok(outcome);
+        }
+
+        // Account of the other client of a transfer, written back along with `account`
+        let mut counterparty = None;
+
         match msg.tx_type {
             TransactionType::Deposit => {
-                if let Some(amount) = msg.amount {
-                    client_entry.available += amount;
-                    client_entry.total += amount;
+                let amount = msg
+                    .amount
+                    .ok_or(TransactionError::MissingAmount { tx: msg.tx })?;
 
-                    // Store transaction for future dispute reference
-                    transactions.insert(
+                account.available = add(account.available, amount, msg.client, msg.tx)?;
+                account.total = add(account.total, amount, msg.client, msg.tx)?;
+
+                // Store transaction for future dispute reference
+                self.transactions
+                    .insert_record(
                         msg.tx,
                         TransactionRecord {
                             client_id: msg.client,
                             amount,
-                            disputed: false,
+                            state: TransactionState::Processed,
                             tx_type: TransactionType::Deposit,
+                            destination: None,
+                            currency,
                         },
-                    );
-                }
+                    )
+                    .map_err(storage)?;
             }
             TransactionType::Withdrawal => {
-                if client_entry.locked {
-                    warn!("Withdrawal ignored: account {} is locked", msg.client);
-                    return;
+                let amount = msg
+                    .amount
+                    .ok_or(TransactionError::MissingAmount { tx: msg.tx })?;
+
+                if account.available < amount {
+                    return Err(TransactionError::InsufficientFunds {
+                        client: msg.client,
+                        available: account.available,
+                        requested: amount,
+                    });
                 }
-                if let Some(amount) = msg.amount {
-                    if client_entry.available >= amount {
-                        client_entry.available -= amount;
-                        client_entry.total -= amount;
-
-                        // Store withdrawal transaction as well (optional depending on specs)
-                        transactions.insert(
-                            msg.tx,
-                            TransactionRecord {
-                                client_id: msg.client,
-                                amount,
-                                disputed: false,
-                                tx_type: TransactionType::Withdrawal,
-                            },
-                        );
-                    } else {
-                        warn!(
-                            "Withdrawal failed due to insufficient funds for client {}",
-                            msg.client
-                        );
-                    }
+
+                account.available -= amount;
+                account.total -= amount;
+
+                // Store withdrawal transaction as well (optional depending on specs)
+                self.transactions
+                    .insert_record(
+                        msg.tx,
+                        TransactionRecord {
+                            client_id: msg.client,
+                            amount,
+                            state: TransactionState::Processed,
+                            tx_type: TransactionType::Withdrawal,
+                            destination: None,
+                            currency,
+                        },
+                    )
+                    .map_err(storage)?;
+            }
+            TransactionType::Transfer => {
+                let amount = msg
+                    .amount
+                    .ok_or(TransactionError::MissingAmount { tx: msg.tx })?;
+                let destination = msg
+                    .destination
+                    .ok_or(TransactionError::MissingDestination { tx: msg.tx })?;
+
+                let mut credited = self.open_account(destination, currency, msg.tx)?;
+                if credited.locked {
+                    return Err(TransactionError::AccountLocked {
+                        client: destination,
+                    });
+                }
+                if account.available < amount {
+                    return Err(TransactionError::InsufficientFunds {
+                        client: msg.client,
+                        available: account.available,
+                        requested: amount,
+                    });
                 }
+
+                account.available -= amount;
+                account.total -= amount;
+                credited.available = add(credited.available, amount, destination, msg.tx)?;
+                credited.total = add(credited.total, amount, destination, msg.tx)?;
+                counterparty = Some((destination, credited));
+
+                // Both legs are reversed through this record if the transfer is disputed
+                self.transactions
+                    .insert_record(
+                        msg.tx,
+                        TransactionRecord {
+                            client_id: msg.client,
+                            amount,
+                            state: TransactionState::Processed,
+                            tx_type: TransactionType::Transfer,
+                            destination: Some(destination),
+                            currency,
+                        },
+                    )
+                    .map_err(storage)?;
             }
             TransactionType::Dispute => {
-                if let Some(tx_rec) = transactions.get(&msg.tx) {
-                    let tx_client = tx_rec.client_id;
-                    let amount = tx_rec.amount;
-                    let tx_type = tx_rec.tx_type.clone();
-                    let was_disputed = tx_rec.disputed;
-                    drop(tx_rec);
-
-                    // Only allow dispute on client's own deposit transactions not already disputed
-                    if tx_client == msg.client && !was_disputed {
-                        if tx_type != TransactionType::Deposit {
-                            warn!(
-                                "Dispute failed: transaction {} is not a deposit (type: {:?})",
-                                msg.tx, tx_type
-                            );
-                            return;
-                        }
+                let record = lookup_record(&self.transactions, &msg)?;
+                let next = next_state(&msg, record.state, &self.config)?;
+                let amount = record.amount;
 
-                        if client_entry.available >= amount {
-                            client_entry.available -= amount;
-                            client_entry.held += amount;
+                match (&record.tx_type, self.config.withdrawal_disputes) {
+                    (TransactionType::Deposit, _) => {
+                        if account.available < amount {
+                            return Err(TransactionError::InsufficientFunds {
+                                client: msg.client,
+                                available: account.available,
+                                requested: amount,
+                            });
+                        }
 
-                            // Mark transaction as disputed
-                            transactions.entry(msg.tx).and_modify(|rec| {
-                                rec.disputed = true;
+                        account.available -= amount;
+                        account.held = add(account.held, amount, msg.client, msg.tx)?;
+                    }
+                    (TransactionType::Withdrawal, WithdrawalDisputePolicy::ProvisionalCredit) => {
+                        // Provisionally credit the withdrawn amount, held until the dispute settles
+                        account.held = add(account.held, amount, msg.client, msg.tx)?;
+                        account.total = add(account.total, amount, msg.client, msg.tx)?;
+                    }
+                    (TransactionType::Transfer, _) => {
+                        // The transferred amount is held on the credited account
+                        let (destination, mut credited) = self.counterparty(&record, msg.tx)?;
+                        if credited.available < amount {
+                            return Err(TransactionError::InsufficientFunds {
+                                client: destination,
+                                available: credited.available,
+                                requested: amount,
                             });
-                        } else {
-                            warn!(
-                                "Dispute failed: client {} does not have enough available funds to hold",
-                                msg.client
-                            );
                         }
-                    } else if tx_client != msg.client {
-                        warn!(
-                            "Dispute failed: client {} attempted to dispute transaction {} owned by client {}",
-                            msg.client, msg.tx, tx_client
-                        );
+
+                        credited.available -= amount;
+                        credited.held = add(credited.held, amount, destination, msg.tx)?;
+                        counterparty = Some((destination, credited));
+                    }
+                    (tx_type, _) => {
+                        return Err(TransactionError::NotDisputable {
+                            tx: msg.tx,
+                            tx_type: tx_type.clone(),
+                        });
                     }
-                } else {
-                    warn!("Dispute failed: transaction {} not found", msg.tx);
                 }
+
+                self.transactions.set_state(msg.tx, next).map_err(storage)?;
             }
             TransactionType::Resolve => {
-                if let Some(tx_rec) = transactions.get(&msg.tx) {
-                    let tx_client = tx_rec.client_id;
-                    let amount = tx_rec.amount;
-                    let was_disputed = tx_rec.disputed;
-                    drop(tx_rec);
-
-                    if tx_client == msg.client && was_disputed {
-                        client_entry.held -= amount;
-                        client_entry.available += amount;
-
-                        // Mark transaction as no longer disputed
-                        transactions
-                            .entry(msg.tx)
-                            .and_modify(|rec| rec.disputed = false);
+                let record = lookup_record(&self.transactions, &msg)?;
+                let next = next_state(&msg, record.state, &self.config)?;
+                let amount = record.amount;
+
+                match record.tx_type {
+                    TransactionType::Withdrawal => {
+                        // Dispute rejected: reverse the provisional credit
+                        account.held -= amount;
+                        account.total -= amount;
+                    }
+                    TransactionType::Transfer => {
+                        let (destination, mut credited) = self.counterparty(&record, msg.tx)?;
+                        credited.held -= amount;
+                        credited.available = add(credited.available, amount, destination, msg.tx)?;
+                        counterparty = Some((destination, credited));
+                    }
+                    _ => {
+                        account.held -= amount;
+                        account.available = add(account.available, amount, msg.client, msg.tx)?;
                     }
                 }
+
+                self.transactions.set_state(msg.tx, next).map_err(storage)?;
             }
             TransactionType::Chargeback => {
-                if let Some(tx_rec) = transactions.get(&msg.tx) {
-                    let tx_client = tx_rec.client_id;
-                    let amount = tx_rec.amount;
-                    let was_disputed = tx_rec.disputed;
-                    drop(tx_rec);
-
-                    if tx_client == msg.client && was_disputed {
-                        client_entry.held -= amount;
-                        client_entry.total -= amount;
-
-                        client_entry.locked = true; // freeze account on chargeback
-
-                        // Mark transaction as no longer disputed
-                        transactions
-                            .entry(msg.tx)
-                            .and_modify(|rec| rec.disputed = false);
+                let record = lookup_record(&self.transactions, &msg)?;
+                let next = next_state(&msg, record.state, &self.config)?;
+                let amount = record.amount;
+
+                match record.tx_type {
+                    TransactionType::Withdrawal => {
+                        // Withdrawal reversed: the provisional credit becomes permanent
+                        account.held -= amount;
+                        account.available = add(account.available, amount, msg.client, msg.tx)?;
+                        account.locked = true; // freeze account on chargeback
+                    }
+                    TransactionType::Transfer => {
+                        // Transfer reversed: the held amount returns to the sender and the
+                        // credited account is frozen, as for a charged back deposit
+                        let (destination, mut credited) = self.counterparty(&record, msg.tx)?;
+                        credited.held -= amount;
+                        credited.total -= amount;
+                        credited.locked = true;
+                        account.available = add(account.available, amount, msg.client, msg.tx)?;
+                        account.total = add(account.total, amount, msg.client, msg.tx)?;
+                        counterparty = Some((destination, credited));
+                    }
+                    _ => {
+                        account.held -= amount;
+                        account.total -= amount;
+                        account.locked = true; // freeze account on chargeback
+                    }
+                }
+
+                self.transactions.set_state(msg.tx, next).map_err(storage)?;
+            }
+            TransactionType::Authorize => {
+                let amount = msg
+                    .amount
+                    .ok_or(TransactionError::MissingAmount { tx: msg.tx })?;
+
+                if account.available < amount {
+                    return Err(TransactionError::InsufficientFunds {
+                        client: msg.client,
+                        available: account.available,
+                        requested: amount,
+                    });
+                }
+
+                account.available -= amount;
+                account.held = add(account.held, amount, msg.client, msg.tx)?;
+
+                // Captured, voided or expired through this record
+                self.transactions
+                    .insert_record(
+                        msg.tx,
+                        TransactionRecord {
+                            client_id: msg.client,
+                            amount,
+                            state: TransactionState::Processed,
+                            tx_type: TransactionType::Authorize,
+                            destination: None,
+                            currency,
+                        },
+                    )
+                    .map_err(storage)?;
+            }
+            TransactionType::Capture | TransactionType::Void => {
+                let record = lookup_record(&self.transactions, &msg)?;
+                if record.tx_type != TransactionType::Authorize {
+                    return Err(TransactionError::NotAnAuthorization {
+                        tx: msg.tx,
+                        tx_type: record.tx_type,
+                    });
+                }
+                match record.state {
+                    TransactionState::Processed => {}
+                    TransactionState::Expired => {
+                        return Err(TransactionError::AuthorizationExpired { tx: msg.tx });
+                    }
+                    state => {
+                        return Err(TransactionError::AuthorizationClosed { tx: msg.tx, state });
                     }
                 }
+
+                let authorized = record.amount;
+                let (captured, next) = match msg.tx_type {
+                    TransactionType::Capture => {
+                        (msg.amount.unwrap_or(authorized), TransactionState::Captured)
+                    }
+                    _ => (Decimal::ZERO, TransactionState::Voided),
+                };
+                if captured > authorized {
+                    return Err(TransactionError::CaptureExceedsAuthorization {
+                        tx: msg.tx,
+                        authorized,
+                        requested: captured,
+                    });
+                }
+
+                // The authorization is closed even by a partial capture; the rest is released
+                account.held -= authorized;
+                account.total -= captured;
+                account.available =
+                    add(account.available, authorized - captured, msg.client, msg.tx)?;
+
+                self.transactions.set_state(msg.tx, next).map_err(storage)?;
+            }
+            TransactionType::Lock => account.locked = true,
+            TransactionType::Unlock => account.locked = false,
+            TransactionType::Adjustment => {
+                let amount = msg
+                    .amount
+                    .ok_or(TransactionError::MissingAmount { tx: msg.tx })?;
+
+                if amount.is_sign_negative() && account.available < -amount {
+                    return Err(TransactionError::InsufficientFunds {
+                        client: msg.client,
+                        available: account.available,
+                        requested: -amount,
+                    });
+                }
+
+                account.available = add(account.available, amount, msg.client, msg.tx)?;
+                account.total = add(account.total, amount, msg.client, msg.tx)?;
+
+                // Recorded for duplicate detection only; adjustments cannot be disputed
+                self.transactions
+                    .insert_record(
+                        msg.tx,
+                        TransactionRecord {
+                            client_id: msg.client,
+                            amount,
+                            state: TransactionState::Processed,
+                            tx_type: TransactionType::Adjustment,
+                            destination: None,
+                            currency,
+                        },
+                    )
+                    .map_err(storage)?;
+            }
+        }
+
+        if let Some((client, credited)) = counterparty {
+            self.clients
+                .put_account(client, currency, credited)
+                .map_err(storage)?;
+        }
+        self.clients
+            .put_account(msg.client, currency, account)
+            .map_err(storage)?;
+
+        match msg.tx_type {
+            TransactionType::Authorize => {
+                self.authorizations
+                    .insert(msg.client, msg.tx, msg.timestamp)
+            }
+            TransactionType::Capture | TransactionType::Void => {
+                self.authorizations.remove(msg.client, msg.tx)
             }
             _ => {}
         }
+        Ok(Outcome::Applied)
+    }
+
+    /// Releases the held funds of the authorizations of `msg.client` that expire before
+    /// `msg` is applied, and records them in `expired`.
+    fn expire_authorizations(&mut self, msg: &TransactionMessage) -> Result<(), TransactionError> {
+        let storage = storage_error(msg.tx);
+        let due = self
+            .authorizations
+            .expire_due(&self.transactions, &self.config, msg.client, msg.timestamp)
+            .map_err(storage)?;
+
+        for tx in due {
+            let Some(record) = self.transactions.record(tx).map_err(storage)? else {
+                continue;
+            };
+            if record.tx_type != TransactionType::Authorize
+                || record.state != TransactionState::Processed
+            {
+                continue;
+            }
+
+            // Released even on a locked account, as a void would be if it were allowed
+            let mut account = self.open_account(record.client_id, record.currency, msg.tx)?;
+            account.held -= record.amount;
+            account.available = add(account.available, record.amount, record.client_id, msg.tx)?;
+            self.transactions
+                .set_state(tx, TransactionState::Expired)
+                .map_err(storage)?;
+            self.clients
+                .put_account(record.client_id, record.currency, account.clone())
+                .map_err(storage)?;
+
+            info!(
+                "Authorization {} of client {} expired",
+                tx, record.client_id
+            );
+            self.expired.push(ProcessedTransaction {
+                message: TransactionMessage {
+                    currency: Some(record.currency),
+                    timestamp: msg.timestamp,
+                    line: msg.line,
+                    source: msg.source,
+                    ..TransactionMessage::new(TransactionType::Void, record.client_id, tx, None)
+                },
+                result: Ok(Outcome::Expired),
+                currency: record.currency,
+                account: Some(account),
+                counterparty: None,
+            });
+        }
+        Ok(())
+    }
+
+    /// Returns the currency `msg` applies in: the currency of the referenced transaction
+    /// for a dispute, resolve, chargeback, capture or void of one of the client's
+    /// transactions, else the message's own currency or `EngineConfig::default_currency`.
+    ///
+    /// A storage error is logged and the message's own currency is reported.
+    pub fn currency_of(&self, msg: &TransactionMessage) -> Currency {
+        self.resolve_currency(msg)
+            .unwrap_or(msg.currency.unwrap_or(self.config.default_currency))
+    }
+
+    /// Resolves the currency of `msg` as described in `currency_of`, rejecting a message
+    /// referencing a transaction that names a currency other than the transaction's.
+    fn resolve_currency(&self, msg: &TransactionMessage) -> Result<Currency, TransactionError> {
+        let own = msg.currency.unwrap_or(self.config.default_currency);
+        if !matches!(
+            msg.tx_type,
+            TransactionType::Dispute
+                | TransactionType::Resolve
+                | TransactionType::Chargeback
+                | TransactionType::Capture
+                | TransactionType::Void
+        ) {
+            return Ok(own);
+        }
+
+        let record = self
+            .transactions
+            .record(msg.tx)
+            .map_err(storage_error(msg.tx))?;
+        match record {
+            // Records of other clients are rejected by `lookup_record`
+            Some(record) if record.client_id == msg.client => match msg.currency {
+                Some(currency) if currency != record.currency => {
+                    Err(TransactionError::CurrencyMismatch {
+                        tx: msg.tx,
+                        expected: record.currency,
+                        actual: currency,
+                    })
+                }
+                _ => Ok(record.currency),
+            },
+            _ => Ok(own),
+        }
+    }
+
+    /// Returns the client whose account is changed by `msg` besides the message's own
+    /// client: the destination of a transfer, or of the transfer referenced by a dispute,
+    /// resolve or chargeback.
+    ///
+    /// A storage error is logged and reported as no counterparty.
+    pub fn counterparty_of(&self, msg: &TransactionMessage) -> Option<u16> {
+        match msg.tx_type {
+            TransactionType::Transfer => msg.destination,
+            TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback => {
+                let record = self.transactions.record(msg.tx).unwrap_or_else(|e| {
+                    error!("Failed to read transaction {}: {}", msg.tx, e);
+                    None
+                });
+                record.and_then(|record| record.destination)
+            }
+            _ => None,
+        }
+    }
+
+    /// Describes `msg`, just applied with `result`, for the outcome reports: the currency
+    /// it applied in and the accounts it changed, as they are now.
+    pub(crate) fn processed(
+        &self,
+        msg: TransactionMessage,
+        result: Result<Outcome, TransactionError>,
+    ) -> ProcessedTransaction {
+        let currency = self.currency_of(&msg);
+        let counterparty = (result == Ok(Outcome::Applied))
+            .then(|| self.counterparty_of(&msg))
+            .flatten()
+            .and_then(|client| Some((client, self.account_in(client, currency)?)));
+        ProcessedTransaction {
+            currency,
+            account: self.account_in(msg.client, currency),
+            counterparty,
+            message: msg,
+            result,
+        }
+    }
+
+    /// Returns the transaction store, e.g. to inspect records shared between engines.
+    pub(crate) fn transactions(&self) -> &T {
+        &self.transactions
+    }
+
+    /// Reads the account of `client` in `currency`, creating an empty one on first
+    /// reference.
+    fn open_account(
+        &self,
+        client: u16,
+        currency: Currency,
+        tx: u32,
+    ) -> Result<ClientAccount, TransactionError> {
+        let storage = storage_error(tx);
+        match self.clients.account(client, currency).map_err(storage)? {
+            Some(account) => Ok(account),
+            None => {
+                self.clients
+                    .put_account(client, currency, ClientAccount::default())
+                    .map_err(storage)?;
+                Ok(ClientAccount::default())
+            }
+        }
+    }
+
+    /// Reads the credited account of a disputed transfer, rejecting the message if it is
+    /// locked.
+    fn counterparty(
+        &self,
+        record: &TransactionRecord,
+        tx: u32,
+    ) -> Result<(u16, ClientAccount), TransactionError> {
+        let destination = record
+            .destination
+            .ok_or(TransactionError::MissingDestination { tx })?;
+        let account = self.open_account(destination, record.currency, tx)?;
+        if account.locked {
+            return Err(TransactionError::AccountLocked {
+                client: destination,
+            });
+        }
+        Ok((destination, account))
+    }
+}
+
+/// Handle to an `Engine` running in a background task and fed through a channel.
+///
+/// Any number of producers can obtain a sender with `EngineHandle::sender` and feed
+/// messages concurrently; per-producer ordering is preserved by the channel. The engine
+/// stops once every sender has been dropped, or earlier when `EngineHandle::shutdown`
+/// is called.
+///
+/// # Example
+/// ```no_run
+/// # use payments_engine::engine::{Engine, EngineHandle};
+/// # use payments_engine::structures::{TransactionMessage, TransactionType};
+/// # use rust_decimal::Decimal;
+/// # async fn run() {
+/// let handle = EngineHandle::spawn(Engine::new(), 100, None);
+/// let sender = handle.sender();
+/// tokio::spawn(async move {
+///     let msg = TransactionMessage::new(TransactionType::Deposit, 1, 1, Some(Decimal::ONE));
+///     sender.send(msg).await.unwrap();
+/// });
+/// let engine = handle.shutdown().await.unwrap();
+/// println!("{:?}", engine.accounts());
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct EngineHandle<A: AccountStore = ClientsMap, T: TransactionStore = TransactionsMap> {
+    sender: mpsc::Sender<TransactionMessage>,
+    shutdown: oneshot::Sender<()>,
+    progress: watch::Receiver<u64>,
+    task: JoinHandle<Engine<A, T>>,
+}
+
+impl<A: AccountStore + 'static, T: TransactionStore + 'static> EngineHandle<A, T> {
+    /// Spawns `engine` on a background task fed by a bounded channel of `capacity`.
+    ///
+    /// Every processed message is reported on `outcomes`, if given.
+    pub fn spawn(
+        engine: Engine<A, T>,
+        capacity: usize,
+        outcomes: Option<mpsc::Sender<ProcessedTransaction>>,
+    ) -> Self {
+        let (sender, receiver) = mpsc::channel(capacity);
+        let (shutdown, shutdown_receiver) = oneshot::channel();
+        let (progress_sender, progress) = watch::channel(0);
+        let task = tokio::spawn(run(
+            engine,
+            receiver,
+            outcomes,
+            Some(shutdown_receiver),
+            Some(progress_sender),
+        ));
+
+        Self {
+            sender,
+            shutdown,
+            progress,
+            task,
+        }
+    }
+
+    /// Returns a new sender feeding this engine, e.g. for an additional producer.
+    pub fn sender(&self) -> mpsc::Sender<TransactionMessage> {
+        self.sender.clone()
+    }
+
+    /// Returns a receiver of the number of messages processed (applied or rejected) so far.
+    ///
+    /// The count is updated after a message's effects are visible in the stores and its
+    /// outcome was reported, so it can be used to wait until a message sent on this
+    /// engine's channel has taken effect.
+    pub fn progress(&self) -> watch::Receiver<u64> {
+        self.progress.clone()
+    }
+
+    /// Waits until every producer has dropped its sender and all messages are applied.
+    ///
+    /// Returns the engine with its final state.
+    pub async fn join(self) -> Result<Engine<A, T>, JoinError> {
+        let EngineHandle {
+            sender,
+            shutdown,
+            task,
+            ..
+        } = self;
+        drop(sender);
+        let engine = task.await;
+        // Keep the shutdown signal alive until the task finished, dropping it closes the channel
+        drop(shutdown);
+        engine
+    }
+
+    /// Gracefully shuts the engine down.
+    ///
+    /// The channel is closed to new messages, so remaining producers observe a send
+    /// error, while messages already in flight are drained and applied. Returns the
+    /// engine with its final state.
+    pub async fn shutdown(self) -> Result<Engine<A, T>, JoinError> {
+        let EngineHandle {
+            sender,
+            shutdown,
+            task,
+            ..
+        } = self;
+        drop(sender);
+        let _ = shutdown.send(());
+        task.await
+    }
+}
+
+/// Processes incoming transaction messages asynchronously.
+///
+/// This function listens on a channel for incoming `TransactionMessage`s and applies each
+/// of them to an `Engine` built on top of the given maps. It is a thin asynchronous
+/// wrapper around `Engine::apply`; see there for the business rules.
+/// It supports the following transaction types:
+/// - Deposit: Adds funds to the client's account.
+/// - Withdrawal: Removes funds from the client's available balance.
+/// - Transfer: Moves available funds to another client's account.
+/// - Dispute: Moves a deposit amount from available to held funds.
+/// - Resolve: Moves a held amount back to available funds.
+/// - Chargeback: Removes held funds and locks the client's account.
+/// - Authorize, Capture, Void: Holds funds, then settles or releases them.
+/// - Lock, Unlock, Adjustment: Administrative changes, accepted on locked accounts.
+///
+/// Processing stops once all senders of the channel have been dropped and every
+/// buffered message has been applied.
+///
+/// # Arguments
+/// * `receiver` - An `mpsc::Receiver` for receiving `TransactionMessage`s.
+/// * `clients` - A thread-safe map (`ClientsMap`) of client accounts.
+/// * `transactions` - A thread-safe map (`TransactionsMap`) storing transaction records.
+/// * `outcomes` - Optional channel on which every processed message is reported together
+///   with its result, so callers can count and act on rejections. Authorizations expired
+///   by a message are reported just before it.
+///
+/// # Notes
+/// - Accounts that are locked will not process any new transactions, except
+///   administrative ones.
+/// - Rejected transactions leave account state untouched and are reported with
+///   a `TransactionError` describing the reason.
+///
+/// # Panics
+/// This function does not panic; invalid transactions are reported as rejections.
+///
+/// # Example
+/// ```no_run
+/// # use std::sync::Arc;
+/// # use dashmap::DashMap;
+/// # use tokio::sync::mpsc;
+/// # use payments_engine::engine::process_transaction;
+/// # use payments_engine::structures::TransactionMessage;
+/// # async fn run() {
+/// let (tx, rx) = mpsc::channel::<TransactionMessage>(100);
+/// let clients = Arc::new(DashMap::new());
+/// let transactions = Arc::new(DashMap::new());
+/// tokio::spawn(async move {
+///     process_transaction(rx, clients, transactions, None).await;
+/// });
+/// # }
+/// ```
+pub async fn process_transaction(
+    receiver: mpsc::Receiver<TransactionMessage>,
+    clients: ClientsMap,
+    transactions: TransactionsMap,
+    outcomes: Option<mpsc::Sender<ProcessedTransaction>>,
+) {
+    run(
+        Engine::with_maps(clients, transactions),
+        receiver,
+        outcomes,
+        None,
+        None,
+    )
+    .await;
+}
+
+/// Runs the processing loop until the channel is closed and drained.
+///
+/// When the optional `shutdown` signal fires (or its sender is dropped), the receiver
+/// is closed so no new messages are accepted, and the buffered ones are still applied.
+/// The number of processed messages is published on `progress`, if given.
+///
+/// A failure to write the engine's write-ahead log stops the loop without reporting the
+/// message or accepting any other, and is recorded for `Engine::wal_failure`. An
+/// administrative message is synced to the log before its outcome is reported, so the
+/// audit trail only holds messages recovery replays.
+async fn run<A: AccountStore, T: TransactionStore>(
+    mut engine: Engine<A, T>,
+    mut receiver: mpsc::Receiver<TransactionMessage>,
+    outcomes: Option<mpsc::Sender<ProcessedTransaction>>,
+    mut shutdown: Option<oneshot::Receiver<()>>,
+    progress: Option<watch::Sender<u64>>,
+) -> Engine<A, T> {
+    let mut processed = 0;
+    loop {
+        let msg = tokio::select! {
+            biased;
+            _ = async { shutdown.as_mut().unwrap().await }, if shutdown.is_some() => {
+                info!("Shutdown requested, draining in-flight messages.");
+                receiver.close();
+                shutdown = None;
+                continue;
+            }
+            msg = receiver.recv() => msg,
+        };
+        let Some(msg) = msg else {
+            break;
+        };
+        info!("msg received: {:?}", msg);
+
+        let result = engine.apply(msg.clone());
+        if let Err(e) = &result {
+            warn!("Transaction {} rejected: {}", msg.tx, e);
+        }
+        if let Some(wal) = engine.wal.as_mut()
+            && let Err(e) = wal.append(&msg).and_then(|()| {
+                // The audit trail must never record a message the log may still lose
+                if msg.tx_type.is_admin() && outcomes.is_some() {
+                    wal.sync()
+                } else {
+                    Ok(())
+                }
+            })
+        {
+            // A hole in the log would break recovery, so stop before processing anything else
+            error!(
+                "Failed to append transaction {} to write-ahead log, stopping: {}",
+                msg.tx, e
+            );
+            engine.wal = None;
+            engine.wal_failure = Some(e);
+            break;
+        }
+
+        if let Some(outcomes) = &outcomes {
+            for expired in std::mem::take(&mut engine.expired) {
+                if outcomes.send(expired).await.is_err() {
+                    warn!("Outcome receiver dropped, no further outcomes will be reported");
+                }
+            }
+            let processed = engine.processed(msg, result);
+            if outcomes.send(processed).await.is_err() {
+                warn!("Outcome receiver dropped, no further outcomes will be reported");
+            }
+        }
+
+        // Published last: once a message is reported as processed, the shard owning the
+        // other client of a transfer may change that account and report on it again
+        processed += 1;
+        if let Some(progress) = &progress {
+            progress.send_replace(processed);
+        }
+    }
+    if let Some(wal) = engine.wal.as_mut()
+        && let Err(e) = wal.sync()
+    {
+        error!("Failed to sync write-ahead log: {}", e);
+        engine.wal_failure = Some(e);
     }
     info!("Transaction processor stopped.");
+    engine
+}
+
+/// Checks whether a deposit, withdrawal, transfer, authorization or adjustment reuses an
+/// already recorded transaction ID.
+///
+/// Returns `Ok(None)` if the message should be applied, `Ok(Some(Outcome::Replayed))` if it
+/// is an exact replay of the recorded transaction and idempotent replays are enabled, or
+/// `Err(TransactionError::DuplicateTransaction)` otherwise.
+fn check_duplicate<T: TransactionStore>(
+    transactions: &T,
+    config: &EngineConfig,
+    msg: &TransactionMessage,
+    currency: Currency,
+) -> Result<Option<Outcome>, TransactionError> {
+    if !matches!(
+        msg.tx_type,
+        TransactionType::Deposit
+            | TransactionType::Withdrawal
+            | TransactionType::Transfer
+            | TransactionType::Authorize
+            | TransactionType::Adjustment
+    ) {
+        return Ok(None);
+    }
+
+    let existing = transactions.record(msg.tx).map_err(storage_error(msg.tx))?;
+    let Some(existing) = existing else {
+        return Ok(None);
+    };
+
+    let is_replay = existing.tx_type == msg.tx_type
+        && existing.client_id == msg.client
+        && Some(existing.amount) == msg.amount
+        && existing.destination == msg.destination
+        && existing.currency == currency;
+
+    if config.idempotent_replays && is_replay {
+        Ok(Some(Outcome::Replayed))
+    } else {
+        Err(TransactionError::DuplicateTransaction { tx: msg.tx })
+    }
+}
+
+/// Computes the dispute lifecycle state a transaction moves to when `msg` is applied.
+///
+/// The allowed transitions are:
+/// - Dispute: `Processed` -> `Disputed`, and `Resolved` -> `Disputed` if re-disputes are
+///   allowed by `EngineConfig::redisputes`.
+/// - Resolve: `Disputed` -> `Resolved`.
+/// - Chargeback: `Disputed` -> `ChargedBack` (final).
+///
+/// Any other combination is rejected with the matching `TransactionError`.
+fn next_state(
+    msg: &TransactionMessage,
+    state: TransactionState,
+    config: &EngineConfig,
+) -> Result<TransactionState, TransactionError> {
+    let tx = msg.tx;
+    match (&msg.tx_type, state) {
+        (_, TransactionState::ChargedBack) => Err(TransactionError::AlreadyChargedBack { tx }),
+        (TransactionType::Dispute, TransactionState::Processed) => Ok(TransactionState::Disputed),
+        (TransactionType::Dispute, TransactionState::Resolved) => match config.redisputes {
+            RedisputePolicy::Allow => Ok(TransactionState::Disputed),
+            RedisputePolicy::Forbid => Err(TransactionError::RedisputeNotAllowed { tx }),
+        },
+        (TransactionType::Dispute, TransactionState::Disputed) => {
+            Err(TransactionError::AlreadyDisputed { tx })
+        }
+        (TransactionType::Resolve, TransactionState::Disputed) => Ok(TransactionState::Resolved),
+        (TransactionType::Chargeback, TransactionState::Disputed) => {
+            Ok(TransactionState::ChargedBack)
+        }
+        _ => Err(TransactionError::NotDisputed { tx }),
+    }
+}
+
+/// Looks up the transaction referenced by a dispute, resolve, chargeback, capture or void
+/// message.
+///
+/// Returns the record, or an error if the transaction does not exist or belongs to another
+/// client.
+fn lookup_record<T: TransactionStore>(
+    transactions: &T,
+    msg: &TransactionMessage,
+) -> Result<TransactionRecord, TransactionError> {
+    let tx_rec = transactions
+        .record(msg.tx)
+        .map_err(storage_error(msg.tx))?
+        .ok_or(TransactionError::TransactionNotFound { tx: msg.tx })?;
+
+    if tx_rec.client_id != msg.client {
+        return Err(TransactionError::ClientMismatch {
+            tx: msg.tx,
+            owner: tx_rec.client_id,
+            client: msg.client,
+        });
+    }
+
+    Ok(tx_rec)
+}
+
+/// Adds `amount` to a balance of `client`, rejecting transaction `tx` instead of
+/// overflowing `Decimal`.
+fn add(
+    balance: Decimal,
+    amount: Decimal,
+    client: u16,
+    tx: u32,
+) -> Result<Decimal, TransactionError> {
+    balance
+        .checked_add(amount)
+        .ok_or(TransactionError::BalanceOverflow { client, tx })
+}
+
+/// Returns a mapper turning a storage backend failure into a rejection of transaction `tx`.
+fn storage_error(tx: u32) -> impl Fn(io::Error) -> TransactionError + Copy {
+    move |e| TransactionError::Storage {
+        tx,
+        message: e.to_string(),
+    }
 }
@@ -0,0 +1,235 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use rust_decimal::Decimal;
+
+use crate::storage::{AccountStore, TransactionStore};
+use crate::structures::{
+    ClientAccount, Currency, TransactionMessage, TransactionRecord, TransactionState,
+    TransactionType,
+};
+
+/// A broken account invariant, attributed to the first message after which it was observed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvariantViolation {
+    /// Client whose account is inconsistent.
+    pub client: u16,
+    /// Currency of the inconsistent account.
+    pub currency: Currency,
+    /// Transaction ID of the message that was just applied.
+    pub tx: u32,
+    /// Input line of that message, if known.
+    pub line: Option<u64>,
+    /// Description of the broken invariant.
+    pub detail: String,
+}
+
+impl fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "account {} ({}) violates an invariant after transaction {}",
+            self.client, self.currency, self.tx
+        )?;
+        if let Some(line) = self.line {
+            write!(f, " (line {line})")?;
+        }
+        write!(f, ": {}", self.detail)
+    }
+}
+
+impl std::error::Error for InvariantViolation {}
+
+/// Checks the balance invariants of a single account.
+///
+/// - `total` must equal `available + held`.
+/// - `held` must equal `disputed`, the sum of the amounts held for the client's
+///   transactions in the account's currency: those under dispute and authorizations not
+///   yet captured, voided or expired.
+///
+/// Returns a description of the first broken invariant.
+pub fn check_account(account: &ClientAccount, disputed: Decimal) -> Result<(), String> {
+    if account.available.checked_add(account.held) != Some(account.total) {
+        return Err(format!(
+            "total {} != available {} + held {}",
+            account.total, account.available, account.held
+        ));
+    }
+    if account.held != disputed {
+        return Err(format!("held {} != {} disputed", account.held, disputed));
+    }
+    Ok(())
+}
+
+/// Transactions holding funds, keyed by the client holding their amount and their currency.
+type HeldIndex = HashMap<(u16, Currency), HashSet<u32>>;
+
+/// Validates the accounts touched by every applied message.
+///
+/// The checker tracks which transactions hold funds (disputes and pending authorizations),
+/// per client holding their amount (the destination of a transfer, the owner otherwise)
+/// and currency, so the
+/// expected held amount is computed from the transaction store rather than from the
+/// engine's own arithmetic. The index is built from the store once, on the first check,
+/// which covers state restored from a snapshot, and then updated from every checked
+/// message. Engines sharing their stores may dispute transfers crediting each other's
+/// clients, so their checkers must share the index as well (see `share_index`).
+#[derive(Debug, Default)]
+pub struct InvariantChecker {
+    held: Arc<Mutex<Option<HeldIndex>>>,
+    first_violation: Option<InvariantViolation>,
+}
+
+impl InvariantChecker {
+    /// Creates a checker with no recorded violation.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes this checker track held funds in the same index as `other`, as the checkers
+    /// of engines sharing their stores must.
+    pub fn share_index(&mut self, other: &InvariantChecker) {
+        self.held = Arc::clone(&other.held);
+    }
+
+    /// Checks the account of `msg.client`, and of the other client of a transfer, in
+    /// `currency` after `msg` was applied in that currency.
+    ///
+    /// The first violation is recorded and every violation is returned; a storage error
+    /// while checking is reported as a violation as well.
+    pub fn check<A: AccountStore, T: TransactionStore>(
+        &mut self,
+        clients: &A,
+        transactions: &T,
+        msg: &TransactionMessage,
+        currency: Currency,
+    ) -> Result<(), InvariantViolation> {
+        let result = self
+            .check_message(clients, transactions, msg, currency)
+            .map_err(|(client, detail)| InvariantViolation {
+                client,
+                currency,
+                tx: msg.tx,
+                line: msg.line,
+                detail,
+            });
+        if let Err(violation) = &result
+            && self.first_violation.is_none()
+        {
+            self.first_violation = Some(violation.clone());
+        }
+        result
+    }
+
+    /// Returns the first violation observed, if any.
+    pub fn first_violation(&self) -> Option<&InvariantViolation> {
+        self.first_violation.as_ref()
+    }
+
+    fn check_message<A: AccountStore, T: TransactionStore>(
+        &self,
+        clients: &A,
+        transactions: &T,
+        msg: &TransactionMessage,
+        currency: Currency,
+    ) -> Result<(), (u16, String)> {
+        let storage = |e: std::io::Error| (msg.client, format!("storage error: {e}"));
+        let mut index = self.index();
+        if index.is_none() {
+            *index = Some(held_by_holder(transactions).map_err(storage)?);
+        }
+        let held = index.as_mut().expect("initialized above");
+
+        let mut touched = vec![msg.client];
+        match msg.tx_type {
+            TransactionType::Transfer => touched.extend(msg.destination),
+            TransactionType::Dispute
+            | TransactionType::Resolve
+            | TransactionType::Chargeback
+            | TransactionType::Authorize
+            | TransactionType::Capture
+            | TransactionType::Void => {
+                if let Some(record) = transactions.record(msg.tx).map_err(storage)? {
+                    let holder = record.destination.unwrap_or(record.client_id);
+                    let txs = held.entry((holder, record.currency)).or_default();
+                    if holds_funds(&record) {
+                        txs.insert(msg.tx);
+                    } else {
+                        txs.remove(&msg.tx);
+                    }
+                    touched.extend(record.destination);
+                }
+            }
+            _ => {}
+        }
+
+        for client in touched {
+            check_client(clients, transactions, held, client, currency)
+                .map_err(|detail| (client, detail))?;
+        }
+        Ok(())
+    }
+
+    fn index(&self) -> MutexGuard<'_, Option<HeldIndex>> {
+        // Updates only insert or remove IDs, so a panic while holding the lock leaves a
+        // usable index
+        self.held
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Checks the account of `client` in `currency` against the transactions it holds funds for.
+fn check_client<A: AccountStore, T: TransactionStore>(
+    clients: &A,
+    transactions: &T,
+    holding: &HeldIndex,
+    client: u16,
+    currency: Currency,
+) -> Result<(), String> {
+    let storage = |e: std::io::Error| format!("storage error: {e}");
+
+    let mut held = Decimal::ZERO;
+    for &tx in holding.get(&(client, currency)).into_iter().flatten() {
+        match transactions.record(tx).map_err(storage)? {
+            Some(record) => {
+                held = held
+                    .checked_add(record.amount)
+                    .ok_or_else(|| "held amounts overflow".to_owned())?;
+            }
+            None => return Err(format!("held transaction {tx} has no record")),
+        }
+    }
+
+    let account = clients
+        .account(client, currency)
+        .map_err(storage)?
+        .ok_or_else(|| "account does not exist".to_owned())?;
+    check_account(&account, held)
+}
+
+/// Collects the transactions in the store holding funds, keyed by the client holding
+/// their amount and their currency.
+fn held_by_holder<T: TransactionStore>(transactions: &T) -> std::io::Result<HeldIndex> {
+    let mut held = HeldIndex::new();
+    transactions.for_each_record(&mut |tx, record| {
+        if holds_funds(&record) {
+            let holder = record.destination.unwrap_or(record.client_id);
+            held.entry((holder, record.currency))
+                .or_default()
+                .insert(tx);
+        }
+        Ok(())
+    })?;
+    Ok(held)
+}
+
+/// Returns whether the amount of `record` is currently held: it is under dispute, or a
+/// pending authorization.
+fn holds_funds(record: &TransactionRecord) -> bool {
+    match record.tx_type {
+        TransactionType::Authorize => record.state == TransactionState::Processed,
+        _ => record.state == TransactionState::Disputed,
+    }
+}
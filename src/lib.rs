@@ -1,3 +1,14 @@
+pub mod audit;
+pub mod authorizations;
+pub mod compact_store;
 pub mod engine;
+pub mod invariants;
 pub mod producer;
+pub mod reports;
+pub mod sharded;
+pub mod snapshot;
+pub mod statements;
+pub mod storage;
 pub mod structures;
+pub mod validation;
+pub mod wal;
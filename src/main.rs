@@ -1,17 +1,27 @@
-use crate::engine::process_transaction;
-use crate::producer::process_file;
-use crate::reports::print_final_report;
-use crate::structures::{Args, ClientsMap, TransactionsMap};
 use clap::Parser;
 use dashmap::DashMap;
+use payments_engine::audit::{
+    append_audit_entries, audit_entry, open_audit_trail, unrecorded_audit_entries,
+    write_audit_report,
+};
+use payments_engine::compact_store::CompactTransactionStore;
+use payments_engine::engine::Engine;
+use payments_engine::producer::process_file_from;
+use payments_engine::reports::{write_final_report, write_rejected_report};
+use payments_engine::sharded::ShardedEngineHandle;
+use payments_engine::snapshot::{load_snapshot, save_snapshot};
+use payments_engine::statements::write_statement_report;
+use payments_engine::storage::{AccountStore, SqliteStore, TransactionStore};
+use payments_engine::structures::{
+    Args, ClientsMap, EngineConfig, ProcessedTransaction, ResumePoints, TransactionsMap, TxIndex,
+};
+use payments_engine::wal::{WalWriter, recover_with, truncate_wal};
+use std::path::Path;
 use std::sync::Arc;
+use tokio::fs::File;
+use tokio::io::BufWriter;
 use tokio::{io, main, sync::mpsc};
-use tracing::{error, info};
-
-mod engine;
-mod producer;
-mod reports;
-mod structures;
+use tracing::{error, info, warn};
 
 /// @brief Asynchronous entry point of the application.
 ///
@@ -20,12 +30,28 @@ mod structures;
 ///
 /// Tasks:
 /// - Parses command-line arguments.
-/// - Creates a bounded channel for sending transaction messages.
-/// - Initializes shared concurrent maps for clients and transactions.
-/// - Spawns a consumer task that processes transactions received from the channel.
-/// - Spawns a producer task that reads input data and sends transaction messages.
-/// - Waits for both tasks to complete.
-/// - After completion, prints the final report of client states.
+/// - Initializes the account and transaction stores (a shared concurrent map and the
+///   transaction index selected by `--tx-index`, or an on-disk SQLite database with
+///   `--store-db`), optionally restoring them from a snapshot.
+/// - Optionally replays a write-ahead log left by an interrupted run and keeps logging
+///   processed transactions to it; a run replaying entries refuses to write a rejected
+///   report or a statement.
+/// - Optionally spawns report tasks writing rejected transactions and unparsable rows
+///   to a CSV file, a statement with running balances per client and an audit trail of
+///   administrative transactions, appended to and completed with those replayed from
+///   the log that it misses; the report files are opened up front.
+/// - Spawns the engine on a consumer task fed by a bounded channel, or with `--shards`
+///   one engine per shard behind a task routing messages by client.
+/// - Spawns a producer task that reads input data and sends transaction messages,
+///   skipping rows already recovered from the write-ahead log.
+/// - Waits for the producer to finish (or for Ctrl-C), then gracefully shuts the engine
+///   down, draining messages still in flight.
+/// - Fails without a snapshot or final report if an engine task failed, observed a
+///   violated balance invariant (`--check-invariants`, always on in debug builds) or
+///   could not write the write-ahead log, or if the producer failed to read the input.
+/// - Optionally writes a snapshot of the final state, unless the run was interrupted.
+/// - After completion, prints the final report of client states and empties the
+///   write-ahead log, unless the run was interrupted.
 ///
 /// @return `io::Result<()>` Result indicating the success or failure of the runtime.
 #[main]
@@ -37,35 +63,253 @@ async fn main() -> io::Result<()> {
         .init();
 
     let args = Args::parse();
+    if args.wal.is_some() && args.shards > 1 {
+        // The log resumes after the last logged line, which requires a single processing order
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--wal cannot be combined with more than one shard",
+        ));
+    }
 
-    let (sender, receiver) = mpsc::channel(100);
+    match args.store_db.clone() {
+        Some(path) => {
+            let store = SqliteStore::create(Path::new(&path))?;
+            run(args, store.clone(), store).await
+        }
+        None => {
+            let clients: ClientsMap = Arc::new(DashMap::new());
+            match args.tx_index {
+                TxIndex::Compact => run(args, clients, CompactTransactionStore::new()).await,
+                TxIndex::Map => {
+                    let transactions: TransactionsMap = Arc::new(DashMap::new());
+                    run(args, clients, transactions).await
+                }
+            }
+        }
+    }
+}
+
+/// @brief Runs the engine and its producer and report tasks on the given stores.
+///
+/// @param args          Parsed command-line arguments.
+/// @param clients       Account store shared between the engine and the final report.
+/// @param transactions  Transaction store shared between the engine and snapshots.
+/// @return `io::Result<()>` Result indicating the success or failure of the run.
+async fn run<A, T>(args: Args, clients: A, transactions: T) -> io::Result<()>
+where
+    A: AccountStore + Clone + 'static,
+    T: TransactionStore + Clone + 'static,
+{
     let args_clone = args.clone();
-    let clients: ClientsMap = Arc::new(DashMap::new());
-    let transactions: TransactionsMap = Arc::new(DashMap::new());
 
-    let consumer_clients = Arc::clone(&clients);
-    let consumer_transactions = Arc::clone(&transactions);
+    let mut snapshot_seq = None;
+    if let Some(path) = &args.snapshot_in {
+        snapshot_seq = load_snapshot(Path::new(path), &clients, &transactions)?.wal_seq;
+    }
 
-    let consumer_handle = tokio::spawn(async move {
-        info!("Consumer task started");
-        process_transaction(receiver, consumer_clients, consumer_transactions).await;
-        info!("Consumer task completed");
-    });
+    let config = EngineConfig::from(&args);
+    let new_engine =
+        || Engine::with_stores(clients.clone(), transactions.clone()).with_config(config.clone());
+    let mut engine = new_engine();
+    let mut resume = ResumePoints::new();
+    // Administrative messages replayed from the log, the last of which the interrupted
+    // run may not have written to its audit trail yet
+    let mut recovered_audit = Vec::new();
+    if let Some(path) = &args.wal {
+        // Only accepted with a single shard, see `main`
+        let path = Path::new(path);
+        // Entries the snapshot already includes, if it was written just before a crash
+        // prevented emptying the log, are skipped rather than applied twice
+        let stats = recover_with(path, &mut engine, snapshot_seq, |processed| {
+            recovered_audit.extend(audit_entry(&processed));
+        })?;
+        if stats.replayed + stats.skipped > 0 {
+            // These reports would be recreated without the rows of the interrupted run
+            let report = if args.rejected_output.is_some() {
+                Some("--rejected-output")
+            } else if args.statement.is_some() {
+                Some("--statement")
+            } else {
+                None
+            };
+            if let Some(report) = report {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{report} cannot be combined with recovery from a write-ahead log"),
+                ));
+            }
+        }
+        resume = stats.resume;
+        let wal = WalWriter::open(path, args.wal_sync_every)?.continue_after(stats.last_seq);
+        engine = engine.with_wal(wal);
+    }
+
+    let mut outcome_senders = Vec::new();
+    let mut report_handles = Vec::new();
+    let mut parse_error_sender = None;
+    if let Some(path) = args.rejected_output.clone() {
+        // Created before any transaction is processed, so an unwritable path fails the run
+        let file = File::create(&path).await?;
+        let (outcome_sender, outcome_receiver) = mpsc::channel(100);
+        let (parse_errors, parse_error_receiver) = mpsc::channel(100);
+        outcome_senders.push(outcome_sender);
+        parse_error_sender = Some(parse_errors);
+        report_handles.push(tokio::spawn(async move {
+            if let Err(e) =
+                write_rejected_report(outcome_receiver, parse_error_receiver, file).await
+            {
+                error!("Failed to write rejected report to {}: {:?}", path, e);
+            }
+        }));
+    }
+    if let Some(path) = args.audit_output.clone() {
+        let mut file = open_audit_trail(&path).await?;
+        let recovered_audit = unrecorded_audit_entries(&path, recovered_audit).await?;
+        append_audit_entries(recovered_audit, &mut file).await?;
+        let (outcome_sender, outcome_receiver) = mpsc::channel(100);
+        outcome_senders.push(outcome_sender);
+        report_handles.push(tokio::spawn(async move {
+            if let Err(e) = write_audit_report(outcome_receiver, file).await {
+                error!("Failed to write audit trail to {}: {:?}", path, e);
+            }
+        }));
+    }
+    if let Some(path) = args.statement.clone() {
+        let out = BufWriter::new(File::create(&path).await?);
+        let (outcome_sender, outcome_receiver) = mpsc::channel(100);
+        let (client, format) = (args.statement_client, args.statement_format);
+        outcome_senders.push(outcome_sender);
+        report_handles.push(tokio::spawn(async move {
+            if let Err(e) = write_statement_report(outcome_receiver, client, format, out).await {
+                error!("Failed to write statement to {}: {:?}", path, e);
+            }
+        }));
+    }
+    let outcome_sender = fan_out(outcome_senders);
 
-    let producer_handle = tokio::spawn(async move {
+    info!("Consumer task started");
+    let engines = std::iter::once(engine)
+        .chain((1..args.shards).map(|_| new_engine()))
+        .collect();
+    let engine_handle = ShardedEngineHandle::spawn(engines, 100, outcome_sender);
+
+    let sender = engine_handle.sender();
+    let mut producer_handle = tokio::spawn(async move {
         info!("Producer task started");
-        if let Err(e) = process_file(args_clone, sender).await {
-            error!("Producer task encountered error: {:?}", e);
-        } else {
-            info!("Producer task completed");
+        let result = process_file_from(args_clone, sender, resume, parse_error_sender).await;
+        match &result {
+            Ok(()) => info!("Producer task completed"),
+            Err(e) => error!("Producer task encountered error: {:?}", e),
         }
+        result
     });
 
-    let _ = producer_handle.await;
-    let _ = consumer_handle.await;
+    let (interrupted, produced) = tokio::select! {
+        result = &mut producer_handle => (false, Some(result)),
+        _ = tokio::signal::ctrl_c() => {
+            warn!("Interrupted, shutting down gracefully");
+            (true, None)
+        }
+    };
+    let producer_error = match produced {
+        Some(Ok(result)) => result.err(),
+        Some(Err(e)) => Some(io::Error::other(format!("producer task failed: {e}"))),
+        None => None,
+    };
+    let completed = !interrupted && producer_error.is_none();
+
+    // Stop accepting new messages and drain the ones already in flight
+    let engines = engine_handle.shutdown().await;
+    if interrupted {
+        let _ = producer_handle.await;
+    }
+    for report_handle in report_handles {
+        let _ = report_handle.await;
+    }
+    // A failed engine task leaves state of unknown consistency, which must not be persisted
+    let engines = engines.map_err(|e| io::Error::other(format!("consumer task failed: {e}")))?;
+    info!("Consumer task completed");
+
+    let violation = engines
+        .iter()
+        .filter_map(|engine| engine.invariant_violation().cloned())
+        .min_by_key(|violation| violation.line);
+    if let Some(violation) = violation {
+        // Leave the snapshot and write-ahead log untouched rather than persist corrupt state
+        return Err(io::Error::other(format!("invariant violated: {violation}")));
+    }
+    if let Some(e) = engines.iter().find_map(|engine| engine.wal_failure()) {
+        // The log misses processed messages, keep it and fail rather than lose them
+        return Err(io::Error::new(
+            e.kind(),
+            format!("failed to write the write-ahead log: {e}"),
+        ));
+    }
+
+    if let Some(e) = producer_error {
+        // Only part of the input was processed, the state must not pass for a complete run
+        return Err(io::Error::new(
+            e.kind(),
+            format!("failed to read the input: {e}"),
+        ));
+    }
+
+    if let Some(path) = &args.snapshot_out {
+        if completed {
+            // Records how much of the log the state includes, see `recover_with`
+            let wal_seq = engines
+                .iter()
+                .find_map(|engine| engine.wal_seq())
+                .or(snapshot_seq);
+            save_snapshot(Path::new(path), &clients, &transactions, wal_seq)?;
+        } else {
+            // Only part of the input was processed, a next run must not start from it
+            warn!("Run did not complete, not writing snapshot {}", path);
+        }
+    }
 
     info!("All tasks completed, printing final report");
-    print_final_report(clients);
+    write_final_report(
+        clients,
+        args.default_currency,
+        args.output_format,
+        args.output.as_deref(),
+    )?;
+
+    if let Some(path) = &args.wal {
+        if completed {
+            truncate_wal(Path::new(path))?;
+        } else {
+            warn!(
+                "Run did not complete, keeping write-ahead log {} for recovery",
+                path
+            );
+        }
+    }
 
     Ok(())
 }
+
+/// @brief Returns a sender forwarding every processed transaction to all `senders`.
+///
+/// A single sender is returned as is; with several, a task clones each outcome to every
+/// receiver until the returned sender is dropped.
+///
+/// @param senders  Senders of the report tasks consuming outcomes.
+/// @return `None` if no report consumes outcomes.
+fn fan_out(
+    mut senders: Vec<mpsc::Sender<ProcessedTransaction>>,
+) -> Option<mpsc::Sender<ProcessedTransaction>> {
+    if senders.len() <= 1 {
+        return senders.pop();
+    }
+    let (sender, mut receiver) = mpsc::channel::<ProcessedTransaction>(100);
+    tokio::spawn(async move {
+        while let Some(processed) = receiver.recv().await {
+            for sender in &senders {
+                let _ = sender.send(processed.clone()).await;
+            }
+        }
+    });
+    Some(sender)
+}
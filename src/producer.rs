@@ -1,13 +1,21 @@
-use csv_async::AsyncReaderBuilder;
+use async_compression::tokio::bufread::{GzipDecoder, ZstdDecoder};
+use csv_async::{AsyncReaderBuilder, StringRecord, StringRecordsIntoStream};
 use futures_util::stream::StreamExt;
+use rust_decimal::Decimal;
+use std::cmp::Ordering;
 use std::str::FromStr;
 use tokio::fs::File;
-use tokio::io;
+use tokio::io::{self, AsyncBufReadExt, AsyncRead, BufReader, Split};
 use tokio::sync::mpsc;
-use tokio_util::compat::TokioAsyncReadCompatExt;
+use tokio_util::compat::{Compat, TokioAsyncReadCompatExt};
 use tracing::{error, warn};
 
 use crate::structures::Args;
+use crate::structures::Currency;
+use crate::structures::InputFormat;
+use crate::structures::PARSE_ERROR;
+use crate::structures::RejectedRecord;
+use crate::structures::ResumePoints;
 use crate::structures::TransactionMessage;
 use crate::structures::TransactionType;
 use serde::{self, Deserialize, Deserializer};
@@ -33,7 +41,7 @@ use serde::{self, Deserialize, Deserializer};
 /// attribute on struct fields to sanitize input from CSV, JSON, or other text-based sources.
 ///
 /// # Example
-/// ```rust
+/// ```ignore
 /// #[derive(Deserialize)]
 /// struct Record {
 ///     #[serde(deserialize_with = "trimmed_string")]
@@ -48,9 +56,16 @@ where
     Ok(s.trim().to_owned())
 }
 
-/// Represents a single transaction record parsed from a CSV file.
+/// Trims an optional text column, treating an empty value as missing.
+fn non_empty(value: Option<String>) -> Option<String> {
+    value
+        .map(|value| value.trim().to_owned())
+        .filter(|value| !value.is_empty())
+}
+
+/// Represents a single transaction record parsed from a CSV row or NDJSON object.
 ///
-/// This struct is used for deserializing CSV input using Serde. Each record
+/// This struct is used for deserializing CSV and NDJSON input using Serde. Each record
 /// corresponds to one transaction instruction with optional amount information.
 ///
 /// Fields:
@@ -61,8 +76,17 @@ where
 /// - `tx`: Unique transaction identifier.
 /// - `amount`: Optional monetary amount involved in the transaction (if applicable),
 ///   represented as a `Decimal` with expected precision up to 4 decimal places.
+/// - `destination`: Client credited by a transfer; the column is optional.
+/// - `currency`: Currency code such as `EUR`, `USD` or `PLN`, case-insensitive; the column
+///   is optional and an empty value means the engine's default currency.
+/// - `timestamp`: Integer time of the transaction, e.g. in Unix seconds, used to expire
+///   authorizations; the column is optional.
+/// - `operator`: ID of the operator issuing a `lock`, `unlock` or `adjustment`, and
+///   `reason`: reason code of an adjustment; both columns are optional and empty values
+///   mean none.
 ///
-/// The CSV must include a header row with columns: `type`, `client`, `tx`, `amount`.
+/// The CSV must include a header row with columns: `type`, `client`, `tx`, `amount`;
+/// NDJSON objects carry the same fields, with `amount` as a number or string.
 #[derive(Debug, Deserialize)]
 struct CsvRecord {
     #[serde(rename = "type", deserialize_with = "trimmed_string")]
@@ -70,78 +94,571 @@ struct CsvRecord {
     client: u16,
     tx: u32,
     amount: Option<rust_decimal::Decimal>,
+    destination: Option<u16>,
+    currency: Option<String>,
+    timestamp: Option<u64>,
+    operator: Option<String>,
+    reason: Option<String>,
+}
+
+/// Input path standing for standard input.
+pub const STDIN: &str = "-";
+
+/// Leading bytes of a gzip stream.
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+/// Leading bytes of a zstd frame.
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// Compression of an input stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Detects the compression of an input from its path's extension (`.gz`, `.zst`) or,
+    /// failing that, from the magic bytes at the start of its contents.
+    pub fn detect(path: &str, head: &[u8]) -> Self {
+        if path.ends_with(".gz") {
+            Compression::Gzip
+        } else if path.ends_with(".zst") {
+            Compression::Zstd
+        } else if head.starts_with(GZIP_MAGIC) {
+            Compression::Gzip
+        } else if head.starts_with(ZSTD_MAGIC) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// Opens `path` (or standard input for `-`) for reading, transparently decompressing
+/// gzip and zstd inputs in a streaming fashion.
+///
+/// Concatenated gzip members and zstd frames are decoded as one stream, so partitions
+/// joined with `cat` read as a single input.
+pub async fn open_input(path: &str) -> io::Result<Box<dyn AsyncRead + Unpin + Send>> {
+    let input: Box<dyn AsyncRead + Unpin + Send> = if path == STDIN {
+        Box::new(tokio::io::stdin())
+    } else {
+        Box::new(File::open(path).await?)
+    };
+    let mut input = BufReader::new(input);
+    let head = input.fill_buf().await?;
+
+    Ok(match Compression::detect(path, head) {
+        Compression::None => Box::new(input),
+        Compression::Gzip => {
+            let mut decoder = GzipDecoder::new(input);
+            decoder.multiple_members(true);
+            Box::new(decoder)
+        }
+        Compression::Zstd => {
+            let mut decoder = ZstdDecoder::new(input);
+            decoder.multiple_members(true);
+            Box::new(decoder)
+        }
+    })
+}
+
+/// A row of one input, with its line number and merge key.
+///
+/// A row that failed to deserialize holds the columns that could still be read.
+struct SourceRecord {
+    line: u64,
+    record: Result<CsvRecord, InvalidRow>,
+    timestamp: Option<String>,
+}
+
+/// The columns of a row that cannot be converted into a transaction, as far as they could
+/// be read, for its row in the rejected report.
+#[derive(Debug, Default)]
+struct InvalidRow {
+    tx_type: String,
+    client: Option<u16>,
+    tx: Option<u32>,
+    amount: Option<Decimal>,
+}
+
+impl InvalidRow {
+    /// Reads the columns of a row that failed to deserialize, leaving out the ones that
+    /// cannot be parsed; `field` returns the text of a column by name.
+    fn read(field: impl Fn(&str) -> Option<String>) -> Self {
+        let field = |name| field(name).map(|value| value.trim().to_owned());
+        Self {
+            tx_type: field("type").unwrap_or_default(),
+            client: field("client").and_then(|value| value.parse().ok()),
+            tx: field("tx").and_then(|value| value.parse().ok()),
+            amount: field("amount").and_then(|value| Decimal::from_str(&value).ok()),
+        }
+    }
+
+    /// Returns the rejected-report row of this row, read from line `line` of input
+    /// `source`.
+    fn rejected(self, source: usize, line: u64) -> RejectedRecord {
+        RejectedRecord {
+            tx_type: self.tx_type,
+            client: self.client,
+            tx: self.tx,
+            amount: self.amount,
+            input: source,
+            line: Some(line),
+            reason: PARSE_ERROR,
+        }
+    }
+}
+
+impl From<&CsvRecord> for InvalidRow {
+    fn from(record: &CsvRecord) -> Self {
+        Self {
+            tx_type: record.tx_type.clone(),
+            client: Some(record.client),
+            tx: Some(record.tx),
+            amount: record.amount,
+        }
+    }
+}
+
+/// A decompressed input stream.
+type Input = BufReader<Box<dyn AsyncRead + Unpin + Send>>;
+
+/// Row reader of one input, by format.
+enum Reader {
+    Csv {
+        headers: StringRecord,
+        timestamp_column: Option<usize>,
+        records: StringRecordsIntoStream<'static, Compat<Input>>,
+    },
+    Ndjson {
+        lines: Split<Input>,
+        line: u64,
+        merge_by: Option<String>,
+    },
+}
+
+/// One opened input.
+struct Source {
+    index: usize,
+    name: String,
+    reader: Reader,
+}
+
+impl Source {
+    /// Opens `path` (or standard input for `-`), detects its format if `format` is `Auto`
+    /// and, for CSV, reads its header row.
+    ///
+    /// If `merge_by` is given, the CSV header must contain that column.
+    async fn open(
+        index: usize,
+        path: &str,
+        format: InputFormat,
+        merge_by: Option<&str>,
+    ) -> io::Result<Self> {
+        let input = BufReader::new(open_input(path).await?);
+        Self::from_input(index, path, input, format, merge_by).await
+    }
+
+    /// Same as `open`, but reads the already opened and decompressed `input` named `path`.
+    async fn from_input(
+        index: usize,
+        path: &str,
+        mut input: Input,
+        format: InputFormat,
+        merge_by: Option<&str>,
+    ) -> io::Result<Self> {
+        let format = match format {
+            InputFormat::Auto => detect_format(path, input.fill_buf().await?),
+            format => format,
+        };
+
+        let reader = match format {
+            InputFormat::Ndjson => Reader::Ndjson {
+                lines: input.split(b'\n'),
+                line: 0,
+                merge_by: merge_by.map(str::to_owned),
+            },
+            _ => {
+                // convert the tokio reader to a compatibility layer so csv_async can use it.
+                // Records end at `\n` only: with CRLF terminators csv_async reports each
+                // record on the previous line, and a lone `\r` would not start a new line.
+                // The `\r` of a CRLF ending is removed by trimming.
+                let mut csv_reader = AsyncReaderBuilder::new()
+                    .has_headers(true)
+                    .trim(csv_async::Trim::All)
+                    .terminator(csv_async::Terminator::Any(b'\n'))
+                    .create_reader(input.compat());
+
+                let headers = csv_reader.headers().await?.clone();
+                let timestamp_column = match merge_by {
+                    Some(column) => {
+                        Some(headers.iter().position(|h| h == column).ok_or_else(|| {
+                            io::Error::new(
+                                io::ErrorKind::InvalidInput,
+                                format!("input {path} has no column {column}"),
+                            )
+                        })?)
+                    }
+                    None => None,
+                };
+                Reader::Csv {
+                    headers,
+                    timestamp_column,
+                    records: csv_reader.into_records(),
+                }
+            }
+        };
+
+        Ok(Self {
+            index,
+            name: path.to_owned(),
+            reader,
+        })
+    }
+
+    /// Reads and parses the next row, or returns `None` at the end of the input.
+    ///
+    /// A row that cannot be read or deserialized, e.g. with a wrong number of columns,
+    /// invalid UTF-8 or JSON, or a malformed id or amount, is logged and returned as an
+    /// `InvalidRow`; only I/O errors end the input.
+    async fn next(&mut self) -> io::Result<Option<SourceRecord>> {
+        let (line, record, timestamp) = match &mut self.reader {
+            Reader::Csv {
+                headers,
+                timestamp_column,
+                records,
+            } => {
+                let Some(row) = records.next().await else {
+                    return Ok(None);
+                };
+                let row = match row {
+                    Ok(row) => row,
+                    Err(e) if e.is_io_error() => return Err(e.into()),
+                    Err(e) => {
+                        let line = e.position().map_or(0, |position| position.line());
+                        error!("Invalid row on line {} of {}: {}", line, self.name, e);
+                        return Ok(Some(SourceRecord {
+                            line,
+                            record: Err(InvalidRow::default()),
+                            timestamp: None,
+                        }));
+                    }
+                };
+                let line = row.position().map_or(0, |position| position.line());
+                let record = row.deserialize::<CsvRecord>(Some(headers)).map_err(|e| {
+                    error!("Invalid row on line {} of {}: {}", line, self.name, e);
+                    InvalidRow::read(|name| {
+                        let column = headers.iter().position(|h| h == name)?;
+                        row.get(column).map(str::to_owned)
+                    })
+                });
+                let timestamp = timestamp_column
+                    .and_then(|column| row.get(column))
+                    .map(str::to_owned);
+                (line, record, timestamp)
+            }
+            Reader::Ndjson {
+                lines,
+                line,
+                merge_by,
+            } => {
+                // Blank lines are skipped but still counted
+                let text = loop {
+                    let Some(text) = lines.next_segment().await? else {
+                        return Ok(None);
+                    };
+                    *line += 1;
+                    if !text.trim_ascii().is_empty() {
+                        break text;
+                    }
+                };
+                let invalid = |e: serde_json::Error| {
+                    error!("Invalid JSON on line {} of {}: {}", line, self.name, e);
+                };
+                let object: serde_json::Map<String, serde_json::Value> =
+                    match serde_json::from_slice(&text) {
+                        Ok(object) => object,
+                        Err(e) => {
+                            invalid(e);
+                            return Ok(Some(SourceRecord {
+                                line: *line,
+                                record: Err(InvalidRow::default()),
+                                timestamp: None,
+                            }));
+                        }
+                    };
+                let timestamp = merge_by
+                    .as_deref()
+                    .and_then(|field| match object.get(field) {
+                        Some(serde_json::Value::String(value)) => Some(value.clone()),
+                        Some(serde_json::Value::Number(value)) => Some(value.to_string()),
+                        _ => None,
+                    });
+                let object = serde_json::Value::Object(object);
+                let record = CsvRecord::deserialize(&object).map_err(|e| {
+                    invalid(e);
+                    InvalidRow::read(|name| match object.get(name)? {
+                        serde_json::Value::String(value) => Some(value.clone()),
+                        value => Some(value.to_string()),
+                    })
+                });
+                (*line, record, timestamp)
+            }
+        };
+
+        if record.is_ok() && self.has_merge_key() && timestamp.as_deref().is_none_or(str::is_empty)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("missing timestamp on line {} of {}", line, self.name),
+            ));
+        }
+
+        Ok(Some(SourceRecord {
+            line,
+            record,
+            timestamp,
+        }))
+    }
+
+    fn has_merge_key(&self) -> bool {
+        match &self.reader {
+            Reader::Csv {
+                timestamp_column, ..
+            } => timestamp_column.is_some(),
+            Reader::Ndjson { merge_by, .. } => merge_by.is_some(),
+        }
+    }
+}
+
+/// Detects the format of an input from its path's extension (ignoring a compression
+/// suffix) or, failing that, from its first non-blank byte: `{` starts an NDJSON input.
+fn detect_format(path: &str, head: &[u8]) -> InputFormat {
+    let path = path
+        .strip_suffix(".gz")
+        .or_else(|| path.strip_suffix(".zst"))
+        .unwrap_or(path);
+    let json_extension = [".ndjson", ".jsonl", ".json"]
+        .iter()
+        .any(|extension| path.ends_with(extension));
+    let first = head.iter().find(|b| !b.is_ascii_whitespace());
+    if json_extension || first == Some(&b'{') {
+        InputFormat::Ndjson
+    } else {
+        InputFormat::Csv
+    }
 }
 
-/// Asynchronously processes a CSV input file and sends parsed transactions over a channel.
+/// Asynchronously processes the inputs and sends parsed transactions over a channel.
 ///
-/// This function opens the provided CSV file, deserializes each record into a `CsvRecord`,
-/// converts each record into a `TransactionMessage`, and sends it through the provided
-/// asynchronous channel. It also sends a final `Terminate` message to signal the end
-/// of input.
+/// Each input in `args.input_files` is a file path, or `-` for standard input, and may be
+/// gzip or zstd compressed (see `open_input`). Each input is read as CSV or NDJSON according
+/// to `args.input_format`, detected per input by default. Inputs are
+/// processed one after another in the given order, or, with `args.merge_by`, merged
+/// row by row by the value of that column. Each record is deserialized into a `CsvRecord`,
+/// converted into a `TransactionMessage` tagged with its input index and line number,
+/// and sent through the provided asynchronous channel. The end of input is signalled
+/// by dropping `tx`; the engine stops once every sender feeding it has been dropped.
 ///
-/// The CSV file must contain headers and should follow the expected transaction format:
+/// CSV inputs must contain headers, and both CSV rows and NDJSON objects should follow the
+/// expected transaction format:
 /// - `type`: String representation of the transaction type (e.g., deposit, withdrawal, etc.)
 /// - `client`: Client ID (u16)
 /// - `tx`: Transaction ID (u32)
-/// - `amount`: Optional amount (decimal, rounded to 4 places)
+/// - `amount`: Optional amount (decimal); amounts are forwarded as read and checked by
+///   the engine's validation layer (see `validation::validate`)
 ///
-/// If the transaction type cannot be parsed, the record is skipped. If the receiver is dropped,
-/// the loop terminates early.
+/// A row that cannot be parsed, e.g. with a malformed amount or an unknown transaction
+/// type or currency, is skipped. If the receiver is dropped, the loop terminates early.
 ///
-/// @param args        Command-line arguments containing the input file path.
+/// @param args        Command-line arguments containing the input paths.
 /// @param tx          Asynchronous channel sender used to forward transaction messages.
 /// @return            `Ok(())` if processing completes successfully, or an I/O error otherwise.
 pub async fn process_file(args: Args, tx: mpsc::Sender<TransactionMessage>) -> io::Result<()> {
-    let file = File::open(&args.input_file).await?;
-    // convert tokio::fs::File to a compatibility layer so csv_async can use it
-    let reader = file.compat();
-
-    let mut csv_reader = AsyncReaderBuilder::new()
-        .has_headers(true)
-        .trim(csv_async::Trim::All)
-        .create_deserializer(reader);
-
-    let mut records = csv_reader.deserialize::<CsvRecord>();
-
-    while let Some(record) = records.next().await {
-        let record = record?;
-
-        // Parse transaction type from string to enum
-        let tx_type = match TransactionType::from_str(&record.tx_type) {
-            Ok(t) => t,
-            Err(e) => {
-                error!("Failed to parse transaction type: {}", e);
-                continue;
+    process_file_from(args, tx, ResumePoints::new(), None).await
+}
+
+/// Same as `process_file`, but skips every record of an input up to and including the
+/// line recorded for that input in `resume`, and reports skipped rows that failed to
+/// parse on `parse_errors`, if given, with reason `PARSE_ERROR`.
+///
+/// Used when recovering from a write-ahead log: rows whose line number is already in the
+/// log were processed by the interrupted run and must not be applied twice.
+///
+/// @param args          Command-line arguments containing the input paths.
+/// @param tx            Asynchronous channel sender used to forward transaction messages.
+/// @param resume        Last line already processed, per input index.
+/// @param parse_errors  Channel receiving a rejected-report row per unparsable record.
+/// @return              `Ok(())` if processing completes successfully, or an I/O error
+///                      otherwise.
+pub async fn process_file_from(
+    args: Args,
+    tx: mpsc::Sender<TransactionMessage>,
+    resume: ResumePoints,
+    parse_errors: Option<mpsc::Sender<RejectedRecord>>,
+) -> io::Result<()> {
+    let parse_errors = parse_errors.as_ref();
+    match args.merge_by.as_deref() {
+        None => {
+            for (index, path) in args.input_files.iter().enumerate() {
+                let mut source = Source::open(index, path, args.input_format, None).await?;
+                while let Some(record) = source.next().await? {
+                    if !forward(source.index, record, &resume, &tx, parse_errors).await {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+        Some(column) => {
+            let mut sources = Vec::with_capacity(args.input_files.len());
+            let mut heads = Vec::with_capacity(args.input_files.len());
+            for (index, path) in args.input_files.iter().enumerate() {
+                let mut source = Source::open(index, path, args.input_format, Some(column)).await?;
+                heads.push(source.next().await?);
+                sources.push(source);
             }
-        };
 
-        let amount_rounded = record.amount.map(|a| a.round_dp(4));
+            // Always take the row with the smallest timestamp, preferring earlier inputs on ties
+            while let Some(next) = (0..sources.len())
+                .filter(|&i| heads[i].is_some())
+                .min_by(|&a, &b| compare_timestamps(&heads[a], &heads[b]))
+            {
+                let record = heads[next].take().expect("selected input has a row");
+                if !forward(sources[next].index, record, &resume, &tx, parse_errors).await {
+                    return Ok(());
+                }
+                heads[next] = sources[next].next().await?;
+            }
+        }
+    }
 
-        let message = TransactionMessage {
-            tx_type,
-            client: record.client,
-            tx: record.tx,
-            amount: amount_rounded,
-        };
+    Ok(())
+}
 
-        // Send the transaction message through the channel
-        if tx.send(message).await.is_err() {
-            warn!("Receiver dropped, stopping processing");
+/// Parses a single uncompressed input from `reader` and sends its transactions over `tx`.
+///
+/// Behaves like `process_file` with one input, without decompression; with
+/// `InputFormat::Auto` the format is detected from the contents. Useful for inputs that
+/// do not come from a file, and for fuzzing the parsers.
+///
+/// @param reader      Source of the CSV or NDJSON data.
+/// @param format      Format of the data.
+/// @param tx          Asynchronous channel sender used to forward transaction messages.
+/// @return            `Ok(())` if processing completes successfully, or an I/O error otherwise.
+pub async fn process_reader<R: AsyncRead + Unpin + Send + 'static>(
+    reader: R,
+    format: InputFormat,
+    tx: mpsc::Sender<TransactionMessage>,
+) -> io::Result<()> {
+    let input: Input = BufReader::new(Box::new(reader));
+    let mut source = Source::from_input(0, "input", input, format, None).await?;
+    let resume = ResumePoints::new();
+    while let Some(record) = source.next().await? {
+        if !forward(source.index, record, &resume, &tx, None).await {
             break;
         }
     }
+    Ok(())
+}
+
+/// Converts a record into a `TransactionMessage` and sends it, unless it is skipped.
+///
+/// A record that fails to parse is skipped and reported on `parse_errors`, if given.
+/// Returns `false` if the receiver was dropped and processing should stop.
+async fn forward(
+    source: usize,
+    record: SourceRecord,
+    resume: &ResumePoints,
+    tx: &mpsc::Sender<TransactionMessage>,
+    parse_errors: Option<&mpsc::Sender<RejectedRecord>>,
+) -> bool {
+    if resume.get(&source).is_some_and(|&last| record.line <= last) {
+        return true;
+    }
+    let line = record.line;
+    let record = match record.record {
+        Ok(record) => record,
+        Err(invalid) => {
+            report_parse_error(invalid.rejected(source, line), parse_errors).await;
+            return true;
+        }
+    };
+
+    // Parse transaction type from string to enum
+    let tx_type = match TransactionType::from_str(&record.tx_type) {
+        Ok(t) => t,
+        Err(e) => {
+            error!("Failed to parse transaction type: {}", e);
+            let rejected = InvalidRow::from(&record).rejected(source, line);
+            report_parse_error(rejected, parse_errors).await;
+            return true;
+        }
+    };
+
+    let currency = match record
+        .currency
+        .as_deref()
+        .map(str::trim)
+        .filter(|currency| !currency.is_empty())
+        .map(Currency::from_str)
+        .transpose()
+    {
+        Ok(currency) => currency,
+        Err(e) => {
+            error!("Failed to parse currency: {}", e);
+            let rejected = InvalidRow::from(&record).rejected(source, line);
+            report_parse_error(rejected, parse_errors).await;
+            return true;
+        }
+    };
 
     let message = TransactionMessage {
-        tx_type: TransactionType::Terminate,
-        client: 0,
-        tx: 0,
-        amount: None,
+        destination: record.destination,
+        currency,
+        timestamp: record.timestamp,
+        operator: non_empty(record.operator),
+        reason: non_empty(record.reason),
+        line: Some(line),
+        source,
+        ..TransactionMessage::new(tx_type, record.client, record.tx, record.amount)
     };
 
     // Send the transaction message through the channel
     if tx.send(message).await.is_err() {
-        error!("Receiver dropped, stopping processing");
+        warn!("Receiver dropped, stopping processing");
+        return false;
+    }
+    true
+}
+
+/// Sends the rejected-report row of a record that failed to parse to `parse_errors`.
+async fn report_parse_error(
+    rejected: RejectedRecord,
+    parse_errors: Option<&mpsc::Sender<RejectedRecord>>,
+) {
+    let Some(parse_errors) = parse_errors else {
+        return;
+    };
+    if parse_errors.send(rejected).await.is_err() {
+        warn!("Parse error receiver dropped, no further parse errors will be reported");
     }
+}
 
-    Ok(())
+/// Orders two pending rows by timestamp; integers compare numerically, anything else as text.
+fn compare_timestamps(a: &Option<SourceRecord>, b: &Option<SourceRecord>) -> Ordering {
+    let a = a
+        .as_ref()
+        .and_then(|r| r.timestamp.as_deref())
+        .unwrap_or("");
+    let b = b
+        .as_ref()
+        .and_then(|r| r.timestamp.as_deref())
+        .unwrap_or("");
+    match (a.parse::<i64>(), b.parse::<i64>()) {
+        (Ok(a), Ok(b)) => a.cmp(&b),
+        _ => a.cmp(b),
+    }
 }
@@ -1,46 +1,336 @@
-use itertools::Itertools;
-
-use crate::structures::ClientsMap;
-
-/// Prints the final report of all client accounts in CSV format.
-///
-/// This function takes a map of client accounts and prints a summary line for each client,
-/// including their available, held, total funds, and whether the account is locked. The output
-/// is sorted by client ID for consistency.
-///
-/// The output format is:
-/// ```text
-/// client,available,held,total,locked
-/// 1,100.0000,0.0000,100.0000,false
-/// 2,50.0000,10.0000,60.0000,true
-/// ...
-/// ```
-///
-/// # Parameters
-/// - `clients`: A `ClientsMap`, which is typically a `DashMap<u16, Account>` or similar concurrent map,
-///   containing client account states keyed by client ID.
-///
-/// # Requirements
-/// This function depends on the [`itertools`](https://docs.rs/itertools/latest/itertools/) crate
-/// for the `.sorted_by_key()` method.
-///
-/// # Example
-/// ```
-/// let clients: ClientsMap = DashMap::new();
-/// clients.insert(1, Account { available: dec!(100), held: dec!(0), total: dec!(100), locked: false });
-/// print_final_report(clients);
-/// ```
-pub fn print_final_report(clients: ClientsMap) {
-    println!("client,available,held,total,locked");
-
-    clients
-        .iter()
-        .map(|entry| (*entry.key(), entry.value().clone()))
-        .sorted_by_key(|(client_id, _)| *client_id) // wymaga itertools crate
-        .for_each(|(client_id, account)| {
-            println!(
-                "{},{:.4},{:.4},{:.4},{}",
-                client_id, account.available, account.held, account.total, account.locked
-            );
-        });
-}
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use csv_async::AsyncWriterBuilder;
+use itertools::Itertools;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use tokio::io::{self, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tokio_util::compat::TokioAsyncWriteCompatExt;
+use tracing::info;
+
+use crate::storage::AccountStore;
+use crate::structures::{
+    AccountSummary, Currency, OutputFormat, ProcessedTransaction, RejectedRecord,
+};
+
+/// Number of decimal places of amounts in the final report.
+const REPORT_SCALE: u32 = 4;
+
+/// Prints the final report of all client accounts in CSV format.
+///
+/// This function takes a map of client accounts and prints a summary line for each client
+/// and currency, including their available, held, total funds, and whether the account is
+/// locked. The output is sorted by client ID and currency for consistency.
+///
+/// The output format is:
+/// ```text
+/// client,available,held,total,locked
+/// 1,100.0000,0.0000,100.0000,false
+/// 2,50.0000,10.0000,60.0000,true
+/// ...
+/// ```
+///
+/// If any account is in a currency other than the default `EUR`, a `currency` column
+/// follows `client`, see [`write_report`].
+///
+/// # Parameters
+/// - `clients`: Any `AccountStore`, typically a `ClientsMap`, containing client account
+///   states keyed by client ID and currency.
+///
+/// # Errors
+/// Returns an error if the accounts cannot be read from the store.
+///
+/// # Example
+/// ```
+/// # use std::sync::Arc;
+/// # use dashmap::DashMap;
+/// # use rust_decimal::Decimal;
+/// # use payments_engine::reports::print_final_report;
+/// # use payments_engine::structures::{ClientAccount, ClientsMap, Currency};
+/// let clients: ClientsMap = Arc::new(DashMap::new());
+/// clients.insert((1, Currency::Eur), ClientAccount { available: Decimal::new(100, 0), held: Decimal::ZERO, total: Decimal::new(100, 0), locked: false });
+/// print_final_report(clients).unwrap();
+/// ```
+pub fn print_final_report<A: AccountStore>(clients: A) -> io::Result<()> {
+    write_final_report(clients, Currency::default(), OutputFormat::Csv, None)
+}
+
+/// Writes the final report of all client accounts in `format` to the file at `output`, or
+/// to standard output if no path is given. `default_currency` is the currency of input rows
+/// without one; the report only has a currency column if some account is in another one.
+///
+/// # Errors
+/// Returns an error if the accounts cannot be read from the store or the report cannot be
+/// written.
+pub fn write_final_report<A: AccountStore>(
+    clients: A,
+    default_currency: Currency,
+    format: OutputFormat,
+    output: Option<&str>,
+) -> io::Result<()> {
+    let accounts = account_summaries(&clients)?;
+    match output {
+        Some(path) => {
+            let mut writer = BufWriter::new(File::create(path)?);
+            write_report(&accounts, default_currency, format, &mut writer)?;
+            writer.flush()?;
+            info!("Final report written to {}", path);
+            Ok(())
+        }
+        None => write_report(
+            &accounts,
+            default_currency,
+            format,
+            std::io::stdout().lock(),
+        ),
+    }
+}
+
+/// Returns a summary of every account in `clients`, sorted by client ID and currency,
+/// with amounts at the report's fixed precision of four decimal places.
+pub fn account_summaries<A: AccountStore>(clients: &A) -> io::Result<Vec<AccountSummary>> {
+    Ok(clients
+        .all_accounts()?
+        .into_iter()
+        .sorted_by_key(|(client_id, currency, _)| (*client_id, *currency)) // wymaga itertools crate
+        .map(|(client_id, currency, account)| AccountSummary {
+            available: report_amount(account.available),
+            held: report_amount(account.held),
+            total: report_amount(account.total),
+            ..AccountSummary::new(client_id, currency, &account)
+        })
+        .collect())
+}
+
+/// Writes `accounts` to `out` in the given format.
+///
+/// Every format has the fields `client`, `available`, `held`, `total` and `locked`, in this
+/// order. If any account is in a currency other than `default_currency`, which is the case
+/// only for input with a `currency` column, a `currency` field follows `client` in every
+/// row; single-currency reports keep the original five columns. Amounts are written as
+/// given; in JSON they are strings, so no precision is lost.
+///
+/// The output formats are:
+/// ```text
+/// csv:    client,available,held,total,locked
+///         1,1.5000,0.0000,1.5000,false
+/// json:   [{"client":1,"available":"1.5000","held":"0.0000","total":"1.5000","locked":false}]
+/// ndjson: {"client":1,"available":"1.5000","held":"0.0000","total":"1.5000","locked":false}
+/// table:  client  available    held   total  locked
+///              1     1.5000  0.0000  1.5000  false
+/// ```
+pub fn write_report<W: Write>(
+    accounts: &[AccountSummary],
+    default_currency: Currency,
+    format: OutputFormat,
+    mut out: W,
+) -> io::Result<()> {
+    let with_currency = accounts
+        .iter()
+        .any(|account| account.currency != default_currency);
+    let rows = accounts.iter().map(|account| ReportRow {
+        client: account.client,
+        currency: with_currency.then_some(account.currency),
+        available: account.available,
+        held: account.held,
+        total: account.total,
+        locked: account.locked,
+    });
+    match format {
+        OutputFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(&mut out);
+            if accounts.is_empty() {
+                // `serialize` only emits the header along with the first row
+                writer.write_record(report_columns(false))?;
+            }
+            for row in rows {
+                writer.serialize(row)?;
+            }
+            writer.flush()?;
+        }
+        OutputFormat::Json => {
+            serde_json::to_writer(&mut out, &rows.collect_vec())?;
+            writeln!(out)?;
+        }
+        OutputFormat::Ndjson => {
+            for row in rows {
+                serde_json::to_writer(&mut out, &row)?;
+                writeln!(out)?;
+            }
+        }
+        OutputFormat::Table => write_table(rows, with_currency, &mut out)?,
+    }
+    out.flush()
+}
+
+/// One row of the account report; `currency` is left out of single-currency reports.
+#[derive(Serialize)]
+struct ReportRow {
+    client: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    currency: Option<Currency>,
+    available: Decimal,
+    held: Decimal,
+    total: Decimal,
+    locked: bool,
+}
+
+/// Column names of the account report, with or without the currency column.
+fn report_columns(with_currency: bool) -> Vec<&'static str> {
+    ["client", "currency", "available", "held", "total", "locked"]
+        .into_iter()
+        .filter(|&column| with_currency || column != "currency")
+        .collect()
+}
+
+/// Writes `rows` as a table with right-aligned numeric and left-aligned text columns.
+fn write_table<W: Write>(
+    rows: impl Iterator<Item = ReportRow>,
+    with_currency: bool,
+    out: &mut W,
+) -> io::Result<()> {
+    let columns = report_columns(with_currency);
+    let rows: Vec<Vec<String>> = rows
+        .map(|row| {
+            [
+                Some(row.client.to_string()),
+                row.currency.map(|currency| currency.to_string()),
+                Some(row.available.to_string()),
+                Some(row.held.to_string()),
+                Some(row.total.to_string()),
+                Some(row.locked.to_string()),
+            ]
+            .into_iter()
+            .flatten()
+            .collect()
+        })
+        .collect();
+
+    let mut widths = columns.iter().map(|column| column.len()).collect_vec();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let header = columns
+        .iter()
+        .map(|column| column.to_string())
+        .collect_vec();
+    for row in std::iter::once(&header).chain(&rows) {
+        let line = row
+            .iter()
+            .zip(&widths)
+            .zip(&columns)
+            .map(|((cell, &width), &column)| match column {
+                "currency" | "locked" => format!("{cell:<width$}"),
+                _ => format!("{cell:>width$}"),
+            })
+            .join("  ");
+        writeln!(out, "{}", line.trim_end())?;
+    }
+    Ok(())
+}
+
+/// Rounds `amount` to the report precision and pads it to exactly that many decimal places.
+pub(crate) fn report_amount(amount: Decimal) -> Decimal {
+    let mut amount = amount.round_dp(REPORT_SCALE);
+    amount.rescale(REPORT_SCALE);
+    amount
+}
+
+/// Encodes `row` as one CSV record, without a header.
+pub(crate) fn csv_row<S: Serialize>(row: &S) -> io::Result<Vec<u8>> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(Vec::new());
+    writer.serialize(row)?;
+    writer.into_inner().map_err(|e| e.into_error())
+}
+
+/// Column names of the rejected-transactions report.
+const REJECTED_HEADER: &str = "type,client,tx,amount,input,line,reason\n";
+
+/// Writes every rejected transaction received on the outcomes channel, and every input
+/// row that failed to parse, as CSV to `out`.
+///
+/// This function consumes `ProcessedTransaction`s reported by the engine and
+/// `RejectedRecord`s reported by the producer until both channels are closed, and writes
+/// one row for each message whose result is an error and for each unparsable row, with
+/// reason `parse_error`. Applied messages are skipped. Rows are written in the order they
+/// are received, engine outcomes first when both are pending, through an asynchronous
+/// writer so the runtime is never blocked.
+///
+/// The output format is:
+/// ```text
+/// type,client,tx,amount,input,line,reason
+/// withdrawal,1,4,1.5,0,5,insufficient_funds
+/// dispute,2,9,,0,7,transaction_not_found
+/// refund,3,10,2.0,1,3,parse_error
+/// ...
+/// ```
+///
+/// # Parameters
+/// - `outcomes`: Receiving end of the channel passed to `process_transaction`.
+/// - `parse_errors`: Receiving end of the channel passed to
+///   `producer::process_file_from`.
+/// - `out`: Destination of the report, typically a file created before the engine starts
+///   so that a report which cannot be written fails the run up front.
+///
+/// # Returns
+/// The number of rejected rows written, or an I/O error if the report cannot be written.
+pub async fn write_rejected_report<W: AsyncWrite + Unpin>(
+    mut outcomes: mpsc::Receiver<ProcessedTransaction>,
+    mut parse_errors: mpsc::Receiver<RejectedRecord>,
+    mut out: W,
+) -> io::Result<usize> {
+    // Header is written up front so an empty report is still a valid CSV file
+    out.write_all(REJECTED_HEADER.as_bytes()).await?;
+    let mut writer = AsyncWriterBuilder::new()
+        .has_headers(false)
+        .create_serializer(out.compat_write());
+    let mut rejected = 0;
+
+    let (mut outcomes_open, mut parse_errors_open) = (true, true);
+    while outcomes_open || parse_errors_open {
+        let record = tokio::select! {
+            biased;
+            processed = outcomes.recv(), if outcomes_open => match processed {
+                Some(processed) => rejected_record(processed),
+                None => {
+                    outcomes_open = false;
+                    None
+                }
+            },
+            record = parse_errors.recv(), if parse_errors_open => {
+                parse_errors_open = record.is_some();
+                record
+            }
+        };
+        if let Some(record) = record {
+            writer.serialize(record).await?;
+            rejected += 1;
+        }
+    }
+
+    writer.flush().await?;
+    info!("Rejected report written: {} rows", rejected);
+    Ok(rejected)
+}
+
+/// Returns the rejected-report row of `processed`, or `None` if it was not rejected.
+fn rejected_record(processed: ProcessedTransaction) -> Option<RejectedRecord> {
+    let reason = processed.result.err()?;
+    let message = processed.message;
+    Some(RejectedRecord {
+        tx_type: message.tx_type.to_string(),
+        client: Some(message.client),
+        tx: Some(message.tx),
+        amount: message.amount,
+        input: message.source,
+        line: message.line,
+        reason: reason.code(),
+    })
+}
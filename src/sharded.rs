@@ -0,0 +1,314 @@
+use std::collections::{HashMap, VecDeque};
+
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::task::{JoinError, JoinHandle};
+use tracing::{error, info, warn};
+
+use crate::engine::{Engine, EngineHandle};
+use crate::storage::{AccountStore, TransactionStore};
+use crate::structures::{
+    ClientsMap, ProcessedTransaction, TransactionMessage, TransactionType, TransactionsMap,
+};
+
+/// Handle to a set of `Engine`s processing disjoint sets of clients in parallel.
+///
+/// Messages sent to the handle are routed by `client % shards` to one engine per shard,
+/// each running on its own task. All messages of a client therefore go through the same
+/// channel and engine, so per-client ordering is preserved while different clients are
+/// processed concurrently.
+///
+/// The engines are expected to share the same account and transaction stores, so the
+/// final state can be read from any of them (or from the stores directly). Transaction
+/// IDs are global: when a message references a tx ID still being processed by another
+/// shard, routing waits for it, so the result is identical to a single engine processing
+/// the input in order.
+///
+/// A transfer between clients of different shards, and a dispute, resolve or chargeback
+/// of such a transfer, changes an account owned by another shard. Routing handles it as a
+/// barrier: it waits until the other shard is idle, sends the message to the sender's
+/// shard and waits until it is processed before routing anything else.
+///
+/// With a single engine no routing task is spawned and the handle behaves exactly like
+/// `EngineHandle`.
+///
+/// # Example
+/// ```no_run
+/// # use std::sync::Arc;
+/// # use dashmap::DashMap;
+/// # use payments_engine::engine::Engine;
+/// # use payments_engine::sharded::ShardedEngineHandle;
+/// # async fn run() {
+/// let clients = Arc::new(DashMap::new());
+/// let transactions = Arc::new(DashMap::new());
+/// let engines = (0..4)
+///     .map(|_| Engine::with_maps(clients.clone(), transactions.clone()))
+///     .collect();
+/// let handle = ShardedEngineHandle::spawn(engines, 100, None);
+/// let sender = handle.sender();
+/// // ... feed `sender` from a producer, then:
+/// drop(sender);
+/// let engines = handle.join().await.unwrap();
+/// println!("{:?}", engines[0].accounts());
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct ShardedEngineHandle<A: AccountStore = ClientsMap, T: TransactionStore = TransactionsMap>
+{
+    sender: mpsc::Sender<TransactionMessage>,
+    router: Option<Router>,
+    shards: Vec<EngineHandle<A, T>>,
+}
+
+#[derive(Debug)]
+struct Router {
+    shutdown: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl<A: AccountStore + 'static, T: TransactionStore + 'static> ShardedEngineHandle<A, T> {
+    /// Spawns one task per engine plus a routing task, all fed by bounded channels of
+    /// `capacity`.
+    ///
+    /// Every processed message is reported on `outcomes`, if given.
+    ///
+    /// # Panics
+    /// Panics if `engines` is empty.
+    pub fn spawn(
+        mut engines: Vec<Engine<A, T>>,
+        capacity: usize,
+        outcomes: Option<mpsc::Sender<ProcessedTransaction>>,
+    ) -> Self {
+        assert!(!engines.is_empty(), "at least one engine is required");
+        let (first, rest) = engines.split_at_mut(1);
+        for engine in rest {
+            engine.share_invariants_with(&first[0]);
+        }
+
+        // Destinations of transfers already recorded, e.g. restored from a snapshot
+        let mut transfers = HashMap::new();
+        if engines.len() > 1
+            && let Err(e) = engines[0]
+                .transactions()
+                .for_each_record(&mut |tx, record| {
+                    if let Some(destination) = record.destination {
+                        transfers.insert(tx, destination);
+                    }
+                    Ok(())
+                })
+        {
+            error!("Failed to read recorded transfers: {}", e);
+        }
+
+        let shards: Vec<EngineHandle<A, T>> = engines
+            .into_iter()
+            .map(|engine| EngineHandle::spawn(engine, capacity, outcomes.clone()))
+            .collect();
+
+        if shards.len() == 1 {
+            return Self {
+                sender: shards[0].sender(),
+                router: None,
+                shards,
+            };
+        }
+
+        let (sender, receiver) = mpsc::channel(capacity);
+        let (shutdown, shutdown_receiver) = oneshot::channel();
+        let routes = shards
+            .iter()
+            .map(|shard| ShardRoute {
+                sender: shard.sender(),
+                progress: shard.progress(),
+                sent: 0,
+                in_flight: VecDeque::new(),
+            })
+            .collect();
+        let task = tokio::spawn(route(receiver, routes, transfers, shutdown_receiver));
+        info!("Routing transactions to {} shards", shards.len());
+
+        Self {
+            sender,
+            router: Some(Router { shutdown, task }),
+            shards,
+        }
+    }
+
+    /// Returns a new sender feeding the engines, e.g. for an additional producer.
+    pub fn sender(&self) -> mpsc::Sender<TransactionMessage> {
+        self.sender.clone()
+    }
+
+    /// Waits until every producer has dropped its sender and all messages are applied.
+    ///
+    /// Returns the engines with their final state, in shard order.
+    pub async fn join(self) -> Result<Vec<Engine<A, T>>, JoinError> {
+        let ShardedEngineHandle {
+            sender,
+            router,
+            shards,
+        } = self;
+        drop(sender);
+        if let Some(router) = router {
+            router.task.await?;
+            // Keep the shutdown signal alive until routing finished, dropping it closes the channel
+            drop(router.shutdown);
+        }
+        join_shards(shards).await
+    }
+
+    /// Gracefully shuts all engines down.
+    ///
+    /// The input channel is closed to new messages, so remaining producers observe a send
+    /// error, while messages already in flight are routed and applied. Returns the engines
+    /// with their final state, in shard order.
+    pub async fn shutdown(self) -> Result<Vec<Engine<A, T>>, JoinError> {
+        let ShardedEngineHandle {
+            sender,
+            router,
+            shards,
+        } = self;
+        drop(sender);
+        match router {
+            Some(router) => {
+                let _ = router.shutdown.send(());
+                router.task.await?;
+                // The router dropped its senders, so the shards stop after draining
+                join_shards(shards).await
+            }
+            None => {
+                let mut engines = Vec::with_capacity(shards.len());
+                for shard in shards {
+                    engines.push(shard.shutdown().await?);
+                }
+                Ok(engines)
+            }
+        }
+    }
+}
+
+/// Waits for every shard to drain its channel, in shard order.
+async fn join_shards<A: AccountStore + 'static, T: TransactionStore + 'static>(
+    shards: Vec<EngineHandle<A, T>>,
+) -> Result<Vec<Engine<A, T>>, JoinError> {
+    let mut engines = Vec::with_capacity(shards.len());
+    for shard in shards {
+        engines.push(shard.join().await?);
+    }
+    Ok(engines)
+}
+
+/// Routing state of one shard.
+struct ShardRoute {
+    sender: mpsc::Sender<TransactionMessage>,
+    progress: watch::Receiver<u64>,
+    /// Number of messages sent to the shard; the n-th message has sequence number n.
+    sent: u64,
+    /// Sequence numbers and tx IDs of recorded transactions (deposits, withdrawals,
+    /// transfers, authorizations and adjustments) not yet processed.
+    in_flight: VecDeque<(u64, u32)>,
+}
+
+/// Forwards each message to the shard owning its client until the input is closed and drained.
+///
+/// Clients interact through transaction IDs and transfers. When a message references a
+/// tx ID whose recorded transaction is still in flight in another shard, routing waits
+/// until that shard has processed it, so duplicate IDs and cross-client
+/// references are resolved in input order exactly as by a single engine. A message
+/// changing an account of another shard is routed as a barrier (see `ShardedEngineHandle`);
+/// `transfers` maps the tx ID of every known transfer to its destination client.
+async fn route(
+    mut receiver: mpsc::Receiver<TransactionMessage>,
+    mut shards: Vec<ShardRoute>,
+    mut transfers: HashMap<u32, u16>,
+    mut shutdown: oneshot::Receiver<()>,
+) {
+    // Owner shard and sequence number of every recorded transaction in flight
+    let mut in_flight: HashMap<u32, (usize, u64)> = HashMap::new();
+    let mut shutting_down = false;
+    loop {
+        let msg = tokio::select! {
+            biased;
+            _ = &mut shutdown, if !shutting_down => {
+                info!("Shutdown requested, routing in-flight messages.");
+                receiver.close();
+                shutting_down = true;
+                continue;
+            }
+            msg = receiver.recv() => msg,
+        };
+        let Some(msg) = msg else {
+            break;
+        };
+
+        let shard = usize::from(msg.client) % shards.len();
+        forget_processed(&mut shards, &mut in_flight);
+        if let Some(&(owner, seq)) = in_flight.get(&msg.tx)
+            && owner != shard
+        {
+            let _ = shards[owner].progress.wait_for(|&done| done >= seq).await;
+            forget_processed(&mut shards, &mut in_flight);
+        }
+
+        // The transfer map may name transfers that end up rejected, which only costs an
+        // unnecessary barrier
+        let counterparty = match msg.tx_type {
+            TransactionType::Transfer => msg.destination.inspect(|&destination| {
+                transfers.insert(msg.tx, destination);
+            }),
+            TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback => {
+                transfers.get(&msg.tx).copied()
+            }
+            _ => None,
+        };
+        let barrier = counterparty
+            .map(|client| usize::from(client) % shards.len())
+            .filter(|&other| other != shard);
+        if let Some(other) = barrier {
+            let sent = shards[other].sent;
+            let _ = shards[other].progress.wait_for(|&done| done >= sent).await;
+        }
+
+        let route = &mut shards[shard];
+        let (tx, records) = (
+            msg.tx,
+            matches!(
+                msg.tx_type,
+                TransactionType::Deposit
+                    | TransactionType::Withdrawal
+                    | TransactionType::Transfer
+                    | TransactionType::Authorize
+                    | TransactionType::Adjustment
+            ),
+        );
+        if route.sender.send(msg).await.is_err() {
+            warn!("Shard {} stopped, dropping its remaining messages", shard);
+            continue;
+        }
+        route.sent += 1;
+        if records {
+            route.in_flight.push_back((route.sent, tx));
+            in_flight.insert(tx, (shard, route.sent));
+        }
+        if barrier.is_some() {
+            let sent = route.sent;
+            let _ = route.progress.wait_for(|&done| done >= sent).await;
+        }
+    }
+    info!("Transaction router stopped.");
+}
+
+/// Drops in-flight entries of messages their shard has already processed.
+fn forget_processed(shards: &mut [ShardRoute], in_flight: &mut HashMap<u32, (usize, u64)>) {
+    for (index, route) in shards.iter_mut().enumerate() {
+        let done = *route.progress.borrow();
+        while let Some(&(seq, tx)) = route.in_flight.front() {
+            if seq > done {
+                break;
+            }
+            route.in_flight.pop_front();
+            if in_flight.get(&tx) == Some(&(index, seq)) {
+                in_flight.remove(&tx);
+            }
+        }
+    }
+}
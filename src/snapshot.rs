@@ -0,0 +1,250 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use tempfile::NamedTempFile;
+use tracing::info;
+
+use crate::storage::{AccountStore, TransactionStore};
+use crate::structures::{
+    ClientAccount, Currency, TransactionRecord, TransactionState, TransactionType,
+};
+
+/// Version of the on-disk snapshot format written by `save_snapshot`.
+///
+/// Bump this whenever the layout of `SnapshotEntry` changes incompatibly;
+/// `load_snapshot` refuses files with a different version.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// A single line of a snapshot file.
+///
+/// Snapshots are newline-delimited JSON: the first line is a `Header` carrying the
+/// format version and the sequence number of the last write-ahead log entry the state
+/// includes, followed by one line per account and one line per transaction record. Amounts are stored as decimal strings, so no precision is lost. Entries
+/// without a currency, written before accounts had one, are in `Currency::Eur`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum SnapshotEntry {
+    Header {
+        version: u32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        wal_seq: Option<u64>,
+    },
+    Account {
+        client: u16,
+        #[serde(default)]
+        currency: Currency,
+        available: Decimal,
+        held: Decimal,
+        total: Decimal,
+        locked: bool,
+    },
+    Transaction {
+        tx: u32,
+        client: u16,
+        amount: Decimal,
+        tx_type: TransactionType,
+        state: TransactionState,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        destination: Option<u16>,
+        #[serde(default)]
+        currency: Currency,
+    },
+}
+
+/// Number of entries written to or read from a snapshot.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SnapshotStats {
+    pub accounts: usize,
+    pub transactions: usize,
+    /// Sequence number of the last write-ahead log entry included in the state, if any.
+    pub wal_seq: Option<u64>,
+}
+
+/// Writes all accounts and transaction records to a versioned snapshot file.
+///
+/// The snapshot is first written to a temporary file in the same directory and then
+/// atomically renamed over `path`, so an interrupted write never leaves a truncated
+/// snapshot behind.
+///
+/// # Parameters
+/// - `path`: Destination of the snapshot.
+/// - `clients`: Client accounts to persist.
+/// - `transactions`: Transaction records to persist.
+/// - `wal_seq`: Sequence number of the last write-ahead log entry included in the state,
+///   so recovery does not replay it again on top of the snapshot.
+///
+/// # Returns
+/// The number of accounts and transactions written, or an I/O error.
+pub fn save_snapshot<A: AccountStore, T: TransactionStore>(
+    path: &Path,
+    clients: &A,
+    transactions: &T,
+    wal_seq: Option<u64>,
+) -> io::Result<SnapshotStats> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let tmp = NamedTempFile::new_in(dir)?;
+    let mut writer = BufWriter::new(tmp);
+    let mut stats = SnapshotStats {
+        wal_seq,
+        ..SnapshotStats::default()
+    };
+
+    write_entry(
+        &mut writer,
+        &SnapshotEntry::Header {
+            version: SNAPSHOT_VERSION,
+            wal_seq,
+        },
+    )?;
+
+    for (client, currency, account) in clients.all_accounts()? {
+        write_entry(
+            &mut writer,
+            &SnapshotEntry::Account {
+                client,
+                currency,
+                available: account.available,
+                held: account.held,
+                total: account.total,
+                locked: account.locked,
+            },
+        )?;
+        stats.accounts += 1;
+    }
+
+    transactions.for_each_record(&mut |tx, record| {
+        write_entry(
+            &mut writer,
+            &SnapshotEntry::Transaction {
+                tx,
+                client: record.client_id,
+                amount: record.amount,
+                tx_type: record.tx_type,
+                state: record.state,
+                destination: record.destination,
+                currency: record.currency,
+            },
+        )?;
+        stats.transactions += 1;
+        Ok(())
+    })?;
+
+    let tmp = writer.into_inner().map_err(|e| e.into_error())?;
+    tmp.as_file().sync_all()?;
+    tmp.persist(path).map_err(|e| e.error)?;
+
+    info!(
+        "Snapshot written to {:?}: {} accounts, {} transactions",
+        path, stats.accounts, stats.transactions
+    );
+    Ok(stats)
+}
+
+/// Restores accounts and transaction records from a snapshot file.
+///
+/// Entries are inserted into the given maps, replacing existing entries with the same
+/// key. The file must start with a header of version `SNAPSHOT_VERSION`.
+///
+/// # Returns
+/// The number of accounts and transactions restored and the write-ahead log position of
+/// the snapshot, or an `InvalidData` error if the file is not a snapshot of a supported
+/// version or a line cannot be parsed.
+pub fn load_snapshot<A: AccountStore, T: TransactionStore>(
+    path: &Path,
+    clients: &A,
+    transactions: &T,
+) -> io::Result<SnapshotStats> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut lines = reader.lines();
+    let mut stats = SnapshotStats::default();
+
+    match lines.next().transpose()?.as_deref().map(parse_entry) {
+        Some(Ok(SnapshotEntry::Header { version, wal_seq })) if version == SNAPSHOT_VERSION => {
+            stats.wal_seq = wal_seq;
+        }
+        Some(Ok(SnapshotEntry::Header { version, .. })) => {
+            return Err(invalid_data(format!(
+                "unsupported snapshot version {version}, expected {SNAPSHOT_VERSION}"
+            )));
+        }
+        Some(Err(e)) => return Err(e),
+        _ => return Err(invalid_data("missing snapshot header".to_string())),
+    }
+
+    for line in lines {
+        match parse_entry(&line?)? {
+            SnapshotEntry::Header { .. } => {
+                return Err(invalid_data("unexpected snapshot header".to_string()));
+            }
+            SnapshotEntry::Account {
+                client,
+                currency,
+                available,
+                held,
+                total,
+                locked,
+            } => {
+                clients.put_account(
+                    client,
+                    currency,
+                    ClientAccount {
+                        available,
+                        held,
+                        total,
+                        locked,
+                    },
+                )?;
+                stats.accounts += 1;
+            }
+            SnapshotEntry::Transaction {
+                tx,
+                client,
+                amount,
+                tx_type,
+                state,
+                destination,
+                currency,
+            } => {
+                transactions.insert_record(
+                    tx,
+                    TransactionRecord {
+                        client_id: client,
+                        amount,
+                        state,
+                        tx_type,
+                        destination,
+                        currency,
+                    },
+                )?;
+                stats.transactions += 1;
+            }
+        }
+    }
+
+    info!(
+        "Snapshot restored from {:?}: {} accounts, {} transactions",
+        path, stats.accounts, stats.transactions
+    );
+    Ok(stats)
+}
+
+/// Serializes one entry as a JSON line.
+fn write_entry<W: Write>(writer: &mut W, entry: &SnapshotEntry) -> io::Result<()> {
+    serde_json::to_writer(&mut *writer, entry)?;
+    writer.write_all(b"\n")
+}
+
+/// Parses one JSON line into an entry.
+fn parse_entry(line: &str) -> io::Result<SnapshotEntry> {
+    serde_json::from_str(line).map_err(|e| invalid_data(format!("invalid snapshot entry: {e}")))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,189 @@
+use tokio::io::{self, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tracing::info;
+
+use crate::reports::{csv_row, report_amount};
+use crate::structures::{
+    Outcome, ProcessedTransaction, StatementEntry, StatementFormat, TransactionError,
+};
+
+/// Builds the statement entry of one processed message.
+///
+/// Balances are those of the client's account right after the message was processed, so
+/// a rejected message repeats the previous balances.
+pub fn statement_entry(processed: &ProcessedTransaction) -> StatementEntry {
+    let message = &processed.message;
+    let (status, reason) = status(&processed.result);
+    let account = processed.account.clone().unwrap_or_default();
+
+    StatementEntry {
+        client: message.client,
+        tx_type: message.tx_type.clone(),
+        tx: message.tx,
+        amount: message.amount,
+        currency: processed.currency,
+        input: message.source,
+        line: message.line,
+        status,
+        reason,
+        available: report_amount(account.available),
+        held: report_amount(account.held),
+        total: report_amount(account.total),
+        locked: account.locked,
+    }
+}
+
+/// Returns the status column of a processing result and the rejection reason code, if any.
+pub(crate) fn status(
+    result: &Result<Outcome, TransactionError>,
+) -> (&'static str, Option<&'static str>) {
+    match result {
+        Ok(Outcome::Applied) => ("applied", None),
+        Ok(Outcome::Replayed) => ("replayed", None),
+        Ok(Outcome::Expired) => ("expired", None),
+        Err(e) => ("rejected", Some(e.code())),
+    }
+}
+
+/// Builds the statement entry of the other client of an applied transfer (or of the
+/// dispute, resolve or chargeback of one), if any.
+///
+/// The entry repeats the message with the other client's ID and balances, so the
+/// credited client's statement shows where its balance changes come from.
+pub fn counterparty_entry(processed: &ProcessedTransaction) -> Option<StatementEntry> {
+    let (client, account) = processed.counterparty.as_ref()?;
+    Some(StatementEntry {
+        client: *client,
+        available: report_amount(account.available),
+        held: report_amount(account.held),
+        total: report_amount(account.total),
+        locked: account.locked,
+        ..statement_entry(processed)
+    })
+}
+
+/// Column names of a statement.
+const STATEMENT_HEADER: [&str; 13] = [
+    "client",
+    "type",
+    "tx",
+    "amount",
+    "currency",
+    "input",
+    "line",
+    "status",
+    "reason",
+    "available",
+    "held",
+    "total",
+    "locked",
+];
+
+/// Streams statement entries to an output in a given format.
+///
+/// Each entry is encoded in memory and written with a single asynchronous write, so the
+/// runtime is never blocked and nothing is buffered beyond the current entry.
+///
+/// The output formats are:
+/// ```text
+/// csv:  client,type,tx,amount,currency,input,line,status,reason,available,held,total,locked
+///       1,deposit,1,1.5,EUR,0,2,applied,,1.5000,0.0000,1.5000,false
+///       1,withdrawal,2,5.0,EUR,0,3,rejected,insufficient_funds,1.5000,0.0000,1.5000,false
+/// json: [{"client":1,"type":"deposit","tx":1,"amount":"1.5","currency":"EUR","input":0,
+///         "line":2,"status":"applied","reason":null,"available":"1.5000",
+///         "held":"0.0000","total":"1.5000","locked":false}, ...]
+/// ```
+#[derive(Debug)]
+pub struct StatementWriter<W: AsyncWrite + Unpin> {
+    out: W,
+    format: StatementFormat,
+    written: usize,
+}
+
+impl<W: AsyncWrite + Unpin> StatementWriter<W> {
+    /// Starts a statement on `out`, writing the CSV header or the opening bracket of the
+    /// JSON array.
+    pub async fn new(mut out: W, format: StatementFormat) -> io::Result<Self> {
+        match format {
+            StatementFormat::Csv => {
+                let mut header = csv::Writer::from_writer(Vec::new());
+                header.write_record(STATEMENT_HEADER)?;
+                out.write_all(&header.into_inner().map_err(|e| e.into_error())?)
+                    .await?;
+            }
+            StatementFormat::Json => out.write_all(b"[").await?,
+        }
+        Ok(Self {
+            out,
+            format,
+            written: 0,
+        })
+    }
+
+    /// Appends one entry to the statement.
+    pub async fn write(&mut self, entry: &StatementEntry) -> io::Result<()> {
+        let encoded = match self.format {
+            StatementFormat::Csv => csv_row(entry)?,
+            StatementFormat::Json => {
+                let mut object = if self.written > 0 {
+                    b",".to_vec()
+                } else {
+                    Vec::new()
+                };
+                serde_json::to_writer(&mut object, entry)?;
+                object
+            }
+        };
+        self.out.write_all(&encoded).await?;
+        self.written += 1;
+        Ok(())
+    }
+
+    /// Completes the statement and flushes the output.
+    ///
+    /// # Returns
+    /// The number of entries written.
+    pub async fn finish(mut self) -> io::Result<usize> {
+        if self.format == StatementFormat::Json {
+            self.out.write_all(b"]\n").await?;
+        }
+        self.out.flush().await?;
+        Ok(self.written)
+    }
+}
+
+/// Streams the statement entries of every processed message received on the outcomes
+/// channel to `out` in the given format, until the channel is closed.
+///
+/// Entries are written as they arrive, in processing order, which the engine preserves
+/// for each client even when sharded. Transfers appear in the statements of both
+/// clients. Only entries of `client` are written if given.
+///
+/// # Parameters
+/// - `outcomes`: Receiving end of the channel passed to `process_transaction`.
+/// - `out`: Destination of the statement, typically a file created before the engine
+///   starts so that a statement which cannot be written fails the run up front.
+///
+/// # Returns
+/// The number of statement entries written, or an I/O error if they cannot be written.
+pub async fn write_statement_report<W: AsyncWrite + Unpin>(
+    mut outcomes: mpsc::Receiver<ProcessedTransaction>,
+    client: Option<u16>,
+    format: StatementFormat,
+    out: W,
+) -> io::Result<usize> {
+    let mut writer = StatementWriter::new(out, format).await?;
+    while let Some(processed) = outcomes.recv().await {
+        if client.is_none_or(|client| client == processed.message.client) {
+            writer.write(&statement_entry(&processed)).await?;
+        }
+        if let Some(entry) =
+            counterparty_entry(&processed).filter(|entry| client.is_none_or(|c| c == entry.client))
+        {
+            writer.write(&entry).await?;
+        }
+    }
+    let written = writer.finish().await?;
+    info!("Statement written: {} entries", written);
+    Ok(written)
+}
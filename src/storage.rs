@@ -0,0 +1,502 @@
+use std::fmt::Debug;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use rusqlite::{Connection, OptionalExtension, params};
+use rust_decimal::Decimal;
+
+use crate::structures::{
+    ClientAccount, ClientsMap, Currency, TransactionRecord, TransactionState, TransactionType,
+    TransactionsMap,
+};
+
+/// Storage backend for client accounts, one per client and currency.
+///
+/// Methods take `&self` so stores can be shared between the engine and report or
+/// snapshot code; implementations provide their own interior mutability. Reads return
+/// owned copies and the engine writes an account back with `put_account` once a
+/// transaction has been applied.
+pub trait AccountStore: Debug + Send + Sync {
+    /// Returns the account of `client` in `currency`, if it exists.
+    fn account(&self, client: u16, currency: Currency) -> io::Result<Option<ClientAccount>>;
+
+    /// Inserts or replaces the account of `client` in `currency`.
+    fn put_account(
+        &self,
+        client: u16,
+        currency: Currency,
+        account: ClientAccount,
+    ) -> io::Result<()>;
+
+    /// Returns every account, in no particular order.
+    ///
+    /// Client IDs are `u16` and there are few currencies, so the result is bounded in size.
+    fn all_accounts(&self) -> io::Result<Vec<(u16, Currency, ClientAccount)>>;
+
+    /// Starts an atomic unit of writes, ended by `commit` or `rollback`.
+    ///
+    /// The engine applies every message in one unit of each store, so a storage error
+    /// partway through leaves no partial change behind. Units may nest, as they do when
+    /// one store implements both traits. Stores whose writes cannot fail, like the
+    /// in-memory maps, apply writes immediately and ignore units.
+    fn begin(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Ends the unit started by the last `begin`, keeping its writes.
+    fn commit(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Ends the unit started by the last `begin`, undoing its writes.
+    fn rollback(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Storage backend for the records of deposits and withdrawals.
+///
+/// Transaction IDs are `u32`, so a store may hold billions of records; iteration is
+/// therefore callback-based rather than materializing every record.
+pub trait TransactionStore: Debug + Send + Sync {
+    /// Returns the record of transaction `tx`, if it exists.
+    fn record(&self, tx: u32) -> io::Result<Option<TransactionRecord>>;
+
+    /// Inserts or replaces the record of transaction `tx`.
+    fn insert_record(&self, tx: u32, record: TransactionRecord) -> io::Result<()>;
+
+    /// Updates the dispute lifecycle state of an existing record.
+    fn set_state(&self, tx: u32, state: TransactionState) -> io::Result<()>;
+
+    /// Returns the number of stored records.
+    fn record_count(&self) -> io::Result<usize>;
+
+    /// Calls `f` for every stored record, in no particular order, stopping at the first error.
+    fn for_each_record(
+        &self,
+        f: &mut dyn FnMut(u32, TransactionRecord) -> io::Result<()>,
+    ) -> io::Result<()>;
+
+    /// Starts an atomic unit of writes, see `AccountStore::begin`.
+    fn begin(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Ends the unit started by the last `begin`, keeping its writes.
+    fn commit(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Ends the unit started by the last `begin`, undoing its writes.
+    fn rollback(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AccountStore for ClientsMap {
+    fn account(&self, client: u16, currency: Currency) -> io::Result<Option<ClientAccount>> {
+        Ok(self
+            .get(&(client, currency))
+            .map(|entry| entry.value().clone()))
+    }
+
+    fn put_account(
+        &self,
+        client: u16,
+        currency: Currency,
+        account: ClientAccount,
+    ) -> io::Result<()> {
+        self.insert((client, currency), account);
+        Ok(())
+    }
+
+    fn all_accounts(&self) -> io::Result<Vec<(u16, Currency, ClientAccount)>> {
+        Ok(self
+            .iter()
+            .map(|entry| {
+                let (client, currency) = *entry.key();
+                (client, currency, entry.value().clone())
+            })
+            .collect())
+    }
+}
+
+impl TransactionStore for TransactionsMap {
+    fn record(&self, tx: u32) -> io::Result<Option<TransactionRecord>> {
+        Ok(self.get(&tx).map(|entry| entry.value().clone()))
+    }
+
+    fn insert_record(&self, tx: u32, record: TransactionRecord) -> io::Result<()> {
+        self.insert(tx, record);
+        Ok(())
+    }
+
+    fn set_state(&self, tx: u32, state: TransactionState) -> io::Result<()> {
+        if let Some(mut record) = self.get_mut(&tx) {
+            record.state = state;
+        }
+        Ok(())
+    }
+
+    fn record_count(&self) -> io::Result<usize> {
+        Ok(self.len())
+    }
+
+    fn for_each_record(
+        &self,
+        f: &mut dyn FnMut(u32, TransactionRecord) -> io::Result<()>,
+    ) -> io::Result<()> {
+        self.iter()
+            .try_for_each(|entry| f(*entry.key(), entry.value().clone()))
+    }
+}
+
+/// On-disk store backed by an embedded SQLite database.
+///
+/// Implements both `AccountStore` and `TransactionStore`, so histories with far more
+/// transaction IDs than fit in memory can be processed. The database is a working store
+/// only: durability across runs is provided by snapshots and the write-ahead log, so it
+/// keeps its rollback journal in memory, never syncs and always starts empty. The units
+/// of `AccountStore::begin` are savepoints, so each message is committed at once.
+///
+/// Cloning is cheap and yields a handle to the same database.
+#[derive(Debug, Clone)]
+pub struct SqliteStore {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStore {
+    /// Creates a database at `path`.
+    ///
+    /// An existing empty file is reused; any other existing file is refused with
+    /// `io::ErrorKind::AlreadyExists` rather than overwritten.
+    pub fn create(path: &Path) -> io::Result<Self> {
+        match std::fs::metadata(path) {
+            Ok(metadata) if metadata.len() > 0 => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("refusing to overwrite existing file {}", path.display()),
+                ));
+            }
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        Self::init(Connection::open(path).map_err(sql_error)?)
+    }
+
+    /// Creates a store in a private in-memory database, mainly useful in tests.
+    pub fn in_memory() -> io::Result<Self> {
+        Self::init(Connection::open_in_memory().map_err(sql_error)?)
+    }
+
+    fn init(conn: Connection) -> io::Result<Self> {
+        conn.execute_batch(
+            "PRAGMA journal_mode = MEMORY;
+             PRAGMA synchronous = OFF;
+             CREATE TABLE accounts (
+                 client    INTEGER NOT NULL,
+                 currency  TEXT NOT NULL,
+                 available TEXT NOT NULL,
+                 held      TEXT NOT NULL,
+                 total     TEXT NOT NULL,
+                 locked    INTEGER NOT NULL,
+                 PRIMARY KEY (client, currency)
+             );
+             CREATE TABLE transactions (
+                 tx      INTEGER PRIMARY KEY,
+                 client  INTEGER NOT NULL,
+                 amount  TEXT NOT NULL,
+                 tx_type TEXT NOT NULL,
+                 state   TEXT NOT NULL,
+                 destination INTEGER,
+                 currency TEXT NOT NULL
+             );",
+        )
+        .map_err(sql_error)?;
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    fn execute(&self, sql: &str) -> io::Result<()> {
+        self.conn().execute_batch(sql).map_err(sql_error)
+    }
+
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        // A panic while holding the lock cannot leave the connection in a torn state
+        self.conn
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl AccountStore for SqliteStore {
+    fn account(&self, client: u16, currency: Currency) -> io::Result<Option<ClientAccount>> {
+        let conn = self.conn();
+        let mut stmt = conn
+            .prepare_cached(
+                "SELECT available, held, total, locked FROM accounts
+                 WHERE client = ?1 AND currency = ?2",
+            )
+            .map_err(sql_error)?;
+        let row = stmt
+            .query_row(params![client, currency.to_string()], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, bool>(3)?,
+                ))
+            })
+            .optional()
+            .map_err(sql_error)?;
+
+        row.map(|(available, held, total, locked)| {
+            Ok(ClientAccount {
+                available: parse_decimal(&available)?,
+                held: parse_decimal(&held)?,
+                total: parse_decimal(&total)?,
+                locked,
+            })
+        })
+        .transpose()
+    }
+
+    fn put_account(
+        &self,
+        client: u16,
+        currency: Currency,
+        account: ClientAccount,
+    ) -> io::Result<()> {
+        let conn = self.conn();
+        let mut stmt = conn
+            .prepare_cached(
+                "INSERT OR REPLACE INTO accounts (client, currency, available, held, total, locked)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )
+            .map_err(sql_error)?;
+        stmt.execute(params![
+            client,
+            currency.to_string(),
+            account.available.to_string(),
+            account.held.to_string(),
+            account.total.to_string(),
+            account.locked,
+        ])
+        .map_err(sql_error)?;
+        Ok(())
+    }
+
+    fn all_accounts(&self) -> io::Result<Vec<(u16, Currency, ClientAccount)>> {
+        let conn = self.conn();
+        let mut stmt = conn
+            .prepare_cached("SELECT client, currency, available, held, total, locked FROM accounts")
+            .map_err(sql_error)?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, u16>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, String>(4)?,
+                    row.get::<_, bool>(5)?,
+                ))
+            })
+            .map_err(sql_error)?;
+
+        rows.map(|row| {
+            let (client, currency, available, held, total, locked) = row.map_err(sql_error)?;
+            Ok((
+                client,
+                Currency::from_str(&currency).map_err(invalid_data)?,
+                ClientAccount {
+                    available: parse_decimal(&available)?,
+                    held: parse_decimal(&held)?,
+                    total: parse_decimal(&total)?,
+                    locked,
+                },
+            ))
+        })
+        .collect()
+    }
+
+    fn begin(&self) -> io::Result<()> {
+        self.execute("SAVEPOINT unit")
+    }
+
+    fn commit(&self) -> io::Result<()> {
+        self.execute("RELEASE unit")
+    }
+
+    fn rollback(&self) -> io::Result<()> {
+        self.execute("ROLLBACK TO unit; RELEASE unit")
+    }
+}
+
+impl TransactionStore for SqliteStore {
+    fn record(&self, tx: u32) -> io::Result<Option<TransactionRecord>> {
+        let conn = self.conn();
+        let mut stmt = conn
+            .prepare_cached(
+                "SELECT client, amount, tx_type, state, destination, currency FROM transactions
+                 WHERE tx = ?1",
+            )
+            .map_err(sql_error)?;
+        let row = stmt
+            .query_row(params![tx], |row| {
+                Ok((
+                    row.get::<_, u16>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, Option<u16>>(4)?,
+                    row.get::<_, String>(5)?,
+                ))
+            })
+            .optional()
+            .map_err(sql_error)?;
+
+        row.map(|(client, amount, tx_type, state, destination, currency)| {
+            to_record(client, &amount, &tx_type, &state, destination, &currency)
+        })
+        .transpose()
+    }
+
+    fn insert_record(&self, tx: u32, record: TransactionRecord) -> io::Result<()> {
+        let conn = self.conn();
+        let mut stmt = conn
+            .prepare_cached(
+                "INSERT OR REPLACE INTO transactions
+                     (tx, client, amount, tx_type, state, destination, currency)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            )
+            .map_err(sql_error)?;
+        stmt.execute(params![
+            tx,
+            record.client_id,
+            record.amount.to_string(),
+            record.tx_type.to_string(),
+            state_name(record.state),
+            record.destination,
+            record.currency.to_string(),
+        ])
+        .map_err(sql_error)?;
+        Ok(())
+    }
+
+    fn set_state(&self, tx: u32, state: TransactionState) -> io::Result<()> {
+        let conn = self.conn();
+        let mut stmt = conn
+            .prepare_cached("UPDATE transactions SET state = ?2 WHERE tx = ?1")
+            .map_err(sql_error)?;
+        stmt.execute(params![tx, state_name(state)])
+            .map_err(sql_error)?;
+        Ok(())
+    }
+
+    fn record_count(&self) -> io::Result<usize> {
+        self.conn()
+            .query_row("SELECT COUNT(*) FROM transactions", [], |row| row.get(0))
+            .map_err(sql_error)
+    }
+
+    fn for_each_record(
+        &self,
+        f: &mut dyn FnMut(u32, TransactionRecord) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let conn = self.conn();
+        let mut stmt = conn
+            .prepare_cached(
+                "SELECT tx, client, amount, tx_type, state, destination, currency FROM transactions",
+            )
+            .map_err(sql_error)?;
+        let mut rows = stmt.query([]).map_err(sql_error)?;
+
+        while let Some(row) = rows.next().map_err(sql_error)? {
+            let tx: u32 = row.get(0).map_err(sql_error)?;
+            let client: u16 = row.get(1).map_err(sql_error)?;
+            let amount: String = row.get(2).map_err(sql_error)?;
+            let tx_type: String = row.get(3).map_err(sql_error)?;
+            let state: String = row.get(4).map_err(sql_error)?;
+            let destination: Option<u16> = row.get(5).map_err(sql_error)?;
+            let currency: String = row.get(6).map_err(sql_error)?;
+            f(
+                tx,
+                to_record(client, &amount, &tx_type, &state, destination, &currency)?,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn begin(&self) -> io::Result<()> {
+        AccountStore::begin(self)
+    }
+
+    fn commit(&self) -> io::Result<()> {
+        AccountStore::commit(self)
+    }
+
+    fn rollback(&self) -> io::Result<()> {
+        AccountStore::rollback(self)
+    }
+}
+
+fn to_record(
+    client: u16,
+    amount: &str,
+    tx_type: &str,
+    state: &str,
+    destination: Option<u16>,
+    currency: &str,
+) -> io::Result<TransactionRecord> {
+    Ok(TransactionRecord {
+        client_id: client,
+        amount: parse_decimal(amount)?,
+        state: parse_state(state)?,
+        tx_type: TransactionType::from_str(tx_type).map_err(invalid_data)?,
+        destination,
+        currency: Currency::from_str(currency).map_err(invalid_data)?,
+    })
+}
+
+fn state_name(state: TransactionState) -> &'static str {
+    match state {
+        TransactionState::Processed => "processed",
+        TransactionState::Disputed => "disputed",
+        TransactionState::Resolved => "resolved",
+        TransactionState::ChargedBack => "charged_back",
+        TransactionState::Captured => "captured",
+        TransactionState::Voided => "voided",
+        TransactionState::Expired => "expired",
+    }
+}
+
+fn parse_state(state: &str) -> io::Result<TransactionState> {
+    match state {
+        "processed" => Ok(TransactionState::Processed),
+        "disputed" => Ok(TransactionState::Disputed),
+        "resolved" => Ok(TransactionState::Resolved),
+        "charged_back" => Ok(TransactionState::ChargedBack),
+        "captured" => Ok(TransactionState::Captured),
+        "voided" => Ok(TransactionState::Voided),
+        "expired" => Ok(TransactionState::Expired),
+        _ => Err(invalid_data(format!("unknown transaction state: {state}"))),
+    }
+}
+
+fn parse_decimal(value: &str) -> io::Result<Decimal> {
+    Decimal::from_str(value).map_err(|e| invalid_data(format!("invalid amount {value}: {e}")))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn sql_error(e: rusqlite::Error) -> io::Error {
+    io::Error::other(e)
+}
@@ -1,6 +1,7 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use dashmap::DashMap;
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
@@ -9,12 +10,319 @@ use serde::{Deserialize, Serialize};
 #[derive(Parser, Debug, Clone)]
 #[command(author, version, about)]
 pub struct Args {
-    #[arg(value_name = "FILE")]
-    pub input_file: String,
+    /// Input files (CSV or NDJSON, optionally compressed), processed in the given order;
+    /// `-` reads standard input.
+    #[arg(value_name = "FILE", required = true, num_args = 1..)]
+    pub input_files: Vec<String>,
+
+    /// Merge the inputs by this column instead of processing them one after another.
+    /// Each input must be sorted by it; integer values are compared numerically, other
+    /// values as strings, and ties are taken from the earlier input.
+    #[arg(long, value_name = "COLUMN")]
+    pub merge_by: Option<String>,
+
+    /// Format of the inputs; `auto` detects it per input from the extension or contents.
+    #[arg(long, value_enum, default_value_t = InputFormat::Auto)]
+    pub input_format: InputFormat,
+
+    /// Currency of input rows without a currency.
+    #[arg(long, value_enum, default_value_t = Currency::Eur)]
+    pub default_currency: Currency,
+
+    /// Write the final account report to this file instead of standard output.
+    #[arg(long, value_name = "FILE")]
+    pub output: Option<String>,
+
+    /// Format of the final account report.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Csv)]
+    pub output_format: OutputFormat,
+
+    /// Write a statement of every processed transaction with running balances to this file.
+    #[arg(long, value_name = "FILE")]
+    pub statement: Option<String>,
+
+    /// Restrict the statement to this client; all clients are included by default.
+    #[arg(long, value_name = "CLIENT", requires = "statement")]
+    pub statement_client: Option<u16>,
+
+    /// Format of the statement.
+    #[arg(long, value_enum, default_value_t = StatementFormat::Csv)]
+    pub statement_format: StatementFormat,
+
+    /// Write every rejected transaction, with its input line and reason code, to this CSV file.
+    #[arg(long, value_name = "FILE")]
+    pub rejected_output: Option<String>,
+
+    /// Append an audit trail of every administrative transaction (lock, unlock,
+    /// adjustment), with its operator and outcome, to this CSV file.
+    #[arg(long, value_name = "FILE")]
+    pub audit_output: Option<String>,
+
+    /// How disputes referencing withdrawals are handled.
+    #[arg(long, value_enum, default_value_t = WithdrawalDisputePolicy::Reject)]
+    pub withdrawal_disputes: WithdrawalDisputePolicy,
+
+    /// Whether a transaction may be disputed again after its dispute was resolved.
+    #[arg(long, value_enum, default_value_t = RedisputePolicy::Allow)]
+    pub redisputes: RedisputePolicy,
+
+    /// Acknowledge exact replays of an already applied deposit, withdrawal, transfer,
+    /// authorization or adjustment as no-ops instead of rejecting them as duplicates.
+    #[arg(long)]
+    pub idempotent_replays: bool,
+
+    /// How invalid amounts are handled: rejected (strict) or normalized where possible (lenient).
+    #[arg(long, value_enum, default_value_t = ValidationMode::Lenient)]
+    pub validation: ValidationMode,
+
+    /// Expire an authorization not captured or voided within this many subsequent
+    /// transactions of its client.
+    #[arg(long, value_name = "N")]
+    pub authorization_ttl: Option<u64>,
+
+    /// Expire an authorization once a transaction of its client is timestamped more than
+    /// this many units (e.g. seconds) after it.
+    #[arg(long, value_name = "SECONDS")]
+    pub authorization_window: Option<u64>,
+
+    /// Restore accounts and transaction records from this snapshot before processing.
+    #[arg(long, value_name = "FILE")]
+    pub snapshot_in: Option<String>,
+
+    /// Write a snapshot of accounts and transaction records to this file after processing.
+    #[arg(long, value_name = "FILE")]
+    pub snapshot_out: Option<String>,
+
+    /// Log every processed transaction to this write-ahead log and, on startup, recover
+    /// an interrupted run from it.
+    #[arg(long, value_name = "FILE")]
+    pub wal: Option<String>,
+
+    /// Number of write-ahead log entries written between two fsyncs.
+    #[arg(long, value_name = "N", default_value_t = crate::wal::DEFAULT_SYNC_EVERY)]
+    pub wal_sync_every: usize,
+
+    /// Keep accounts and transaction records in an on-disk SQLite database at this path
+    /// instead of memory. The file must not exist or be empty.
+    #[arg(long, value_name = "FILE")]
+    pub store_db: Option<String>,
+
+    /// In-memory index of transaction records, used unless `--store-db` is given.
+    #[arg(long, value_enum, default_value_t = TxIndex::Compact)]
+    pub tx_index: TxIndex,
+
+    /// Check balance invariants of every account after each applied message and fail the
+    /// run on the first violation; always on in debug builds.
+    #[arg(long)]
+    pub check_invariants: bool,
+
+    /// Number of engine tasks processing clients in parallel; messages are routed by
+    /// client ID, preserving per-client order.
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    pub shards: u16,
+}
+
+/// Settlement currency of an account or transaction.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    ValueEnum,
+)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Currency {
+    /// Euro.
+    #[default]
+    Eur,
+    /// Polish złoty.
+    Pln,
+    /// US dollar.
+    Usd,
+}
+
+impl std::fmt::Display for Currency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Currency::Eur => "EUR",
+            Currency::Pln => "PLN",
+            Currency::Usd => "USD",
+        })
+    }
+}
+
+impl std::str::FromStr for Currency {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "EUR" => Ok(Currency::Eur),
+            "PLN" => Ok(Currency::Pln),
+            "USD" => Ok(Currency::Usd),
+            _ => Err(format!("Unknown currency: {s}")),
+        }
+    }
 }
 
-/// Represents the financial state of a client account.
-#[derive(Debug, Clone, Default)]
+/// Format of a transaction input.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum InputFormat {
+    /// `.ndjson`/`.jsonl`/`.json` inputs (optionally compressed) and inputs starting with
+    /// `{` are read as NDJSON, everything else as CSV.
+    #[default]
+    Auto,
+    /// CSV with a header row.
+    Csv,
+    /// Newline-delimited JSON, one object per transaction.
+    Ndjson,
+}
+
+/// Format of the final account report.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// CSV with a header row.
+    #[default]
+    Csv,
+    /// A single JSON array of account objects.
+    Json,
+    /// Newline-delimited JSON, one account object per line.
+    Ndjson,
+    /// Human-readable table with aligned columns.
+    Table,
+}
+
+/// Policy for disputing a transaction whose previous dispute was resolved.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum RedisputePolicy {
+    /// A resolved transaction may be disputed again.
+    #[default]
+    Allow,
+    /// A resolved transaction is final; further disputes are rejected.
+    Forbid,
+}
+
+/// Format of a client statement.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum StatementFormat {
+    /// CSV with a header row.
+    #[default]
+    Csv,
+    /// A single JSON array of statement entries.
+    Json,
+}
+
+/// In-memory data structure holding transaction records.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum TxIndex {
+    /// Chunked slab indexed by tx id (`compact_store::CompactTransactionStore`); about
+    /// 11 bytes per record for densely allocated tx ids.
+    #[default]
+    Compact,
+    /// Concurrent hash map (`TransactionsMap`); better suited to widely scattered tx ids.
+    Map,
+}
+
+/// Validation mode applied to incoming transaction messages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ValidationMode {
+    /// Every invalid amount is rejected.
+    Strict,
+    /// Over-precise amounts are rounded, zero amounts accepted and amounts on
+    /// dispute-related messages dropped; missing and negative amounts are still rejected.
+    #[default]
+    Lenient,
+}
+
+/// Policy for disputes that reference a withdrawal rather than a deposit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum WithdrawalDisputePolicy {
+    /// Disputes on withdrawals are rejected as not disputable.
+    #[default]
+    Reject,
+    /// Disputing a withdrawal holds a provisional credit of the withdrawn amount;
+    /// resolve reverses it and chargeback makes it permanent.
+    ProvisionalCredit,
+}
+
+/// Business-rule configuration of the transaction engine.
+#[derive(Debug, Clone)]
+pub struct EngineConfig {
+    /// How disputes referencing withdrawals are handled.
+    pub withdrawal_disputes: WithdrawalDisputePolicy,
+
+    /// Whether a resolved transaction may be disputed again.
+    pub redisputes: RedisputePolicy,
+
+    /// Whether an exact replay of a recorded deposit, withdrawal, transfer, authorization or
+    /// adjustment (same type, client, tx, amount, destination and currency) is acknowledged
+    /// as a no-op rather than rejected as a duplicate.
+    pub idempotent_replays: bool,
+
+    /// How invalid amounts are handled before a message is applied.
+    pub validation: ValidationMode,
+
+    /// Whether every account is checked for balance invariants after each applied
+    /// message. On by default in builds with debug assertions, e.g. in tests.
+    pub check_invariants: bool,
+
+    /// Whether a violated invariant panics rather than only being logged and recorded
+    /// for `Engine::invariant_violation`. On by default in builds with debug assertions;
+    /// off for the binary, which stops on the recorded violation instead.
+    pub panic_on_violation: bool,
+
+    /// Currency of messages that do not name one.
+    pub default_currency: Currency,
+
+    /// Number of subsequent transactions of its client after which a pending
+    /// authorization expires; never by default.
+    pub authorization_ttl: Option<u64>,
+
+    /// Timestamp difference after which a pending authorization expires when its client
+    /// has another transaction; never by default.
+    pub authorization_window: Option<u64>,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            withdrawal_disputes: WithdrawalDisputePolicy::default(),
+            redisputes: RedisputePolicy::default(),
+            idempotent_replays: false,
+            validation: ValidationMode::default(),
+            check_invariants: cfg!(debug_assertions),
+            panic_on_violation: cfg!(debug_assertions),
+            default_currency: Currency::default(),
+            authorization_ttl: None,
+            authorization_window: None,
+        }
+    }
+}
+
+impl From<&Args> for EngineConfig {
+    fn from(args: &Args) -> Self {
+        Self {
+            withdrawal_disputes: args.withdrawal_disputes,
+            redisputes: args.redisputes,
+            idempotent_replays: args.idempotent_replays,
+            validation: args.validation,
+            check_invariants: args.check_invariants || cfg!(debug_assertions),
+            panic_on_violation: false,
+            default_currency: args.default_currency,
+            authorization_ttl: args.authorization_ttl,
+            authorization_window: args.authorization_window,
+        }
+    }
+}
+
+/// Represents the financial state of a client account in one currency.
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct ClientAccount {
     pub available: Decimal,
     pub held: Decimal,
@@ -23,29 +331,49 @@ pub struct ClientAccount {
 }
 
 /// Supported types of transactions.
-#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum TransactionType {
-    Terminate,
     Deposit,
     Withdrawal,
     Dispute,
     Resolve,
     Chargeback,
+    Transfer,
+    Authorize,
+    Capture,
+    Void,
+    Lock,
+    Unlock,
+    Adjustment,
+}
+
+impl TransactionType {
+    /// Returns whether this is an administrative type, which requires an operator and is
+    /// accepted on locked accounts.
+    pub fn is_admin(&self) -> bool {
+        matches!(
+            self,
+            TransactionType::Lock | TransactionType::Unlock | TransactionType::Adjustment
+        )
+    }
 }
 
-/// A concurrent map of client IDs to their account state.
-pub type ClientsMap = Arc<DashMap<u16, ClientAccount>>;
+/// A concurrent map of client IDs and currencies to the client's account in that currency.
+pub type ClientsMap = Arc<DashMap<(u16, Currency), ClientAccount>>;
 
 /// A concurrent map of transaction IDs to transaction records.
 pub type TransactionsMap = Arc<DashMap<u32, TransactionRecord>>;
 
 /// Serializable summary of a client's account state.
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct AccountSummary {
     /// Client ID
     pub client: u16,
 
+    /// Currency of the balances
+    pub currency: Currency,
+
     /// Available funds (not held/disputed)
     pub available: Decimal,
 
@@ -59,6 +387,20 @@ pub struct AccountSummary {
     pub locked: bool,
 }
 
+impl AccountSummary {
+    /// Builds a summary for `client` from its current account state in `currency`.
+    pub fn new(client: u16, currency: Currency, account: &ClientAccount) -> Self {
+        Self {
+            client,
+            currency,
+            available: account.available,
+            held: account.held,
+            total: account.total,
+            locked: account.locked,
+        }
+    }
+}
+
 impl std::str::FromStr for TransactionType {
     type Err = String;
 
@@ -69,26 +411,501 @@ impl std::str::FromStr for TransactionType {
             "dispute" => Ok(TransactionType::Dispute),
             "resolve" => Ok(TransactionType::Resolve),
             "chargeback" => Ok(TransactionType::Chargeback),
+            "transfer" => Ok(TransactionType::Transfer),
+            "authorize" => Ok(TransactionType::Authorize),
+            "capture" => Ok(TransactionType::Capture),
+            "void" => Ok(TransactionType::Void),
+            "lock" => Ok(TransactionType::Lock),
+            "unlock" => Ok(TransactionType::Unlock),
+            "adjustment" => Ok(TransactionType::Adjustment),
             _ => Err(format!("Unknown transaction type: {s}")),
         }
     }
 }
 
+impl std::fmt::Display for TransactionType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            TransactionType::Deposit => "deposit",
+            TransactionType::Withdrawal => "withdrawal",
+            TransactionType::Dispute => "dispute",
+            TransactionType::Resolve => "resolve",
+            TransactionType::Chargeback => "chargeback",
+            TransactionType::Transfer => "transfer",
+            TransactionType::Authorize => "authorize",
+            TransactionType::Capture => "capture",
+            TransactionType::Void => "void",
+            TransactionType::Lock => "lock",
+            TransactionType::Unlock => "unlock",
+            TransactionType::Adjustment => "adjustment",
+        })
+    }
+}
+
 /// A message representing a transaction, parsed from CSV.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
 pub struct TransactionMessage {
     #[serde(rename = "type")]
     pub tx_type: TransactionType,
     pub client: u16,
     pub tx: u32,
     pub amount: Option<Decimal>,
+    /// Client credited by a transfer; ignored on other transaction types.
+    pub destination: Option<u16>,
+    /// Currency of the amount; `EngineConfig::default_currency` if not given. Disputes,
+    /// resolves, chargebacks, captures and voids apply in the currency of the referenced
+    /// transaction.
+    pub currency: Option<Currency>,
+    /// Time of the transaction as an integer, e.g. in Unix seconds; used to expire
+    /// authorizations.
+    pub timestamp: Option<u64>,
+    /// ID of the operator issuing an administrative message; required on those.
+    pub operator: Option<String>,
+    /// Reason code of an adjustment, e.g. `goodwill`; required on adjustments.
+    pub reason: Option<String>,
+    /// Line number in the input file this message was read from, if any.
+    #[serde(skip)]
+    pub line: Option<u64>,
+    /// Index of the input (in `Args::input_files`) this message was read from.
+    #[serde(skip)]
+    pub source: usize,
+}
+
+impl TransactionMessage {
+    /// Creates a message that did not originate from an input file.
+    pub fn new(tx_type: TransactionType, client: u16, tx: u32, amount: Option<Decimal>) -> Self {
+        Self {
+            tx_type,
+            client,
+            tx,
+            amount,
+            destination: None,
+            currency: None,
+            timestamp: None,
+            operator: None,
+            reason: None,
+            line: None,
+            source: 0,
+        }
+    }
+}
+
+/// Last input line already processed, per input index; used to resume after a crash.
+pub type ResumePoints = HashMap<usize, u64>;
+
+/// Reason why a transaction message was not applied by the engine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransactionError {
+    /// The client's account is locked and accepts no further transactions.
+    AccountLocked { client: u16 },
+    /// The client does not have enough available funds for the operation.
+    InsufficientFunds {
+        client: u16,
+        available: Decimal,
+        requested: Decimal,
+    },
+    /// A deposit, withdrawal, transfer, authorization or adjustment reused the ID of an
+    /// already recorded transaction.
+    DuplicateTransaction { tx: u32 },
+    /// A deposit, withdrawal, transfer, authorization or adjustment arrived without an
+    /// amount.
+    MissingAmount { tx: u32 },
+    /// A transfer arrived without a destination client.
+    MissingDestination { tx: u32 },
+    /// A transfer named its own client as the destination.
+    SelfTransfer { tx: u32 },
+    /// A lock, unlock or adjustment arrived without an operator ID.
+    MissingOperator { tx: u32 },
+    /// An adjustment arrived without a reason code.
+    MissingReason { tx: u32 },
+    /// A dispute, resolve, chargeback, capture or void named a currency other than the one
+    /// of the referenced transaction.
+    CurrencyMismatch {
+        tx: u32,
+        expected: Currency,
+        actual: Currency,
+    },
+    /// A deposit, withdrawal, transfer, authorization or capture carried a negative amount.
+    NegativeAmount { tx: u32, amount: Decimal },
+    /// A deposit, withdrawal, transfer, authorization, capture or adjustment carried a zero
+    /// amount (strict validation only).
+    ZeroAmount { tx: u32 },
+    /// An amount had more than four decimal places (strict validation only).
+    ExcessivePrecision { tx: u32, amount: Decimal },
+    /// A dispute, resolve or chargeback carried an amount (strict validation only).
+    UnexpectedAmount { tx: u32 },
+    /// The referenced transaction is not known to the engine.
+    TransactionNotFound { tx: u32 },
+    /// The referenced transaction belongs to a different client.
+    ClientMismatch { tx: u32, owner: u16, client: u16 },
+    /// The referenced transaction is already under dispute.
+    AlreadyDisputed { tx: u32 },
+    /// The referenced transaction is not under dispute.
+    NotDisputed { tx: u32 },
+    /// The referenced transaction was already charged back and cannot change any more.
+    AlreadyChargedBack { tx: u32 },
+    /// The referenced transaction was resolved and re-disputes are not permitted.
+    RedisputeNotAllowed { tx: u32 },
+    /// The referenced transaction cannot be disputed (e.g. a withdrawal under the
+    /// default `WithdrawalDisputePolicy`).
+    NotDisputable { tx: u32, tx_type: TransactionType },
+    /// A capture or void referenced a transaction that is not an authorization.
+    NotAnAuthorization { tx: u32, tx_type: TransactionType },
+    /// The referenced authorization was already captured or voided.
+    AuthorizationClosed { tx: u32, state: TransactionState },
+    /// The referenced authorization expired and its funds were released.
+    AuthorizationExpired { tx: u32 },
+    /// A capture requested more than the authorized amount.
+    CaptureExceedsAuthorization {
+        tx: u32,
+        authorized: Decimal,
+        requested: Decimal,
+    },
+    /// Applying the transaction would overflow a balance of the client's account.
+    BalanceOverflow { client: u16, tx: u32 },
+    /// The storage backend failed to read or write state; nothing was applied.
+    Storage { tx: u32, message: String },
+}
+
+impl std::fmt::Display for TransactionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransactionError::AccountLocked { client } => {
+                write!(f, "account {client} is locked")
+            }
+            TransactionError::InsufficientFunds {
+                client,
+                available,
+                requested,
+            } => write!(
+                f,
+                "client {client} has insufficient available funds ({available} < {requested})"
+            ),
+            TransactionError::DuplicateTransaction { tx } => {
+                write!(f, "transaction {tx} was already recorded")
+            }
+            TransactionError::MissingAmount { tx } => {
+                write!(f, "transaction {tx} has no amount")
+            }
+            TransactionError::MissingDestination { tx } => {
+                write!(f, "transfer {tx} has no destination client")
+            }
+            TransactionError::SelfTransfer { tx } => {
+                write!(
+                    f,
+                    "transfer {tx} has the same source and destination client"
+                )
+            }
+            TransactionError::MissingOperator { tx } => {
+                write!(f, "administrative transaction {tx} has no operator")
+            }
+            TransactionError::MissingReason { tx } => {
+                write!(f, "adjustment {tx} has no reason code")
+            }
+            TransactionError::CurrencyMismatch {
+                tx,
+                expected,
+                actual,
+            } => write!(f, "transaction {tx} is in {expected}, not {actual}"),
+            TransactionError::NegativeAmount { tx, amount } => {
+                write!(f, "transaction {tx} has negative amount {amount}")
+            }
+            TransactionError::ZeroAmount { tx } => {
+                write!(f, "transaction {tx} has zero amount")
+            }
+            TransactionError::ExcessivePrecision { tx, amount } => write!(
+                f,
+                "transaction {tx} amount {amount} has more than 4 decimal places"
+            ),
+            TransactionError::UnexpectedAmount { tx } => {
+                write!(f, "transaction {tx} must not carry an amount")
+            }
+            TransactionError::TransactionNotFound { tx } => {
+                write!(f, "transaction {tx} not found")
+            }
+            TransactionError::ClientMismatch { tx, owner, client } => write!(
+                f,
+                "client {client} referenced transaction {tx} owned by client {owner}"
+            ),
+            TransactionError::AlreadyDisputed { tx } => {
+                write!(f, "transaction {tx} is already disputed")
+            }
+            TransactionError::NotDisputed { tx } => {
+                write!(f, "transaction {tx} is not disputed")
+            }
+            TransactionError::AlreadyChargedBack { tx } => {
+                write!(f, "transaction {tx} was already charged back")
+            }
+            TransactionError::RedisputeNotAllowed { tx } => {
+                write!(
+                    f,
+                    "transaction {tx} was resolved and cannot be disputed again"
+                )
+            }
+            TransactionError::NotDisputable { tx, tx_type } => {
+                write!(f, "transaction {tx} of type {tx_type:?} cannot be disputed")
+            }
+            TransactionError::NotAnAuthorization { tx, tx_type } => {
+                write!(
+                    f,
+                    "transaction {tx} of type {tx_type:?} is not an authorization"
+                )
+            }
+            TransactionError::AuthorizationClosed { tx, state } => {
+                write!(f, "authorization {tx} is already {state:?}")
+            }
+            TransactionError::AuthorizationExpired { tx } => {
+                write!(f, "authorization {tx} has expired")
+            }
+            TransactionError::CaptureExceedsAuthorization {
+                tx,
+                authorized,
+                requested,
+            } => write!(
+                f,
+                "capture of {requested} exceeds the {authorized} authorized by transaction {tx}"
+            ),
+            TransactionError::BalanceOverflow { client, tx } => {
+                write!(
+                    f,
+                    "transaction {tx} would overflow the balance of client {client}"
+                )
+            }
+            TransactionError::Storage { tx, message } => {
+                write!(
+                    f,
+                    "storage error while processing transaction {tx}: {message}"
+                )
+            }
+        }
+    }
+}
+
+impl TransactionError {
+    /// Stable, machine-readable code identifying the rejection reason.
+    pub fn code(&self) -> &'static str {
+        match self {
+            TransactionError::AccountLocked { .. } => "account_locked",
+            TransactionError::InsufficientFunds { .. } => "insufficient_funds",
+            TransactionError::DuplicateTransaction { .. } => "duplicate_transaction",
+            TransactionError::MissingAmount { .. } => "missing_amount",
+            TransactionError::MissingDestination { .. } => "missing_destination",
+            TransactionError::SelfTransfer { .. } => "self_transfer",
+            TransactionError::MissingOperator { .. } => "missing_operator",
+            TransactionError::MissingReason { .. } => "missing_reason",
+            TransactionError::CurrencyMismatch { .. } => "currency_mismatch",
+            TransactionError::NegativeAmount { .. } => "negative_amount",
+            TransactionError::ZeroAmount { .. } => "zero_amount",
+            TransactionError::ExcessivePrecision { .. } => "excessive_precision",
+            TransactionError::UnexpectedAmount { .. } => "unexpected_amount",
+            TransactionError::TransactionNotFound { .. } => "transaction_not_found",
+            TransactionError::ClientMismatch { .. } => "client_mismatch",
+            TransactionError::AlreadyDisputed { .. } => "already_disputed",
+            TransactionError::NotDisputed { .. } => "not_disputed",
+            TransactionError::AlreadyChargedBack { .. } => "already_charged_back",
+            TransactionError::RedisputeNotAllowed { .. } => "redispute_not_allowed",
+            TransactionError::NotDisputable { .. } => "not_disputable",
+            TransactionError::NotAnAuthorization { .. } => "not_an_authorization",
+            TransactionError::AuthorizationClosed { .. } => "authorization_closed",
+            TransactionError::AuthorizationExpired { .. } => "authorization_expired",
+            TransactionError::CaptureExceedsAuthorization { .. } => "capture_exceeds_authorization",
+            TransactionError::BalanceOverflow { .. } => "balance_overflow",
+            TransactionError::Storage { .. } => "storage_error",
+        }
+    }
+}
+
+impl std::error::Error for TransactionError {}
+
+/// Successful result of applying a single transaction message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// The message was applied and account state was updated.
+    Applied,
+    /// The message exactly replayed an already applied transaction and was ignored.
+    Replayed,
+    /// The authorization referenced by a `void` message generated by the engine expired
+    /// and its funds were released.
+    Expired,
+}
+
+/// A processed message paired with its result, as observed by callers of the engine.
+///
+/// `Ok` carries the `Outcome` of an applied message; `Err` carries the reason the
+/// message was rejected, in which case account state is unchanged.
+#[derive(Debug, Clone)]
+pub struct ProcessedTransaction {
+    pub message: TransactionMessage,
+    pub result: Result<Outcome, TransactionError>,
+    /// Currency the message applied in, i.e. of `account` and `counterparty`.
+    pub currency: Currency,
+    /// State of the message's client account after processing, if the account exists.
+    pub account: Option<ClientAccount>,
+    /// Other client and its account state after an applied message that also changed the
+    /// account of another client, i.e. a transfer or the dispute of one.
+    pub counterparty: Option<(u16, ClientAccount)>,
+}
+
+/// Serializable row of the rejected-transactions report.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RejectedRecord {
+    /// Type of the rejected transaction, as given in the input for a row that failed to
+    /// parse
+    #[serde(rename = "type")]
+    pub tx_type: String,
+
+    /// Client ID, missing if a row that failed to parse has no valid one
+    pub client: Option<u16>,
+
+    /// Transaction ID, missing if a row that failed to parse has no valid one
+    pub tx: Option<u32>,
+
+    /// Amount as given in the input, if any and valid
+    pub amount: Option<Decimal>,
+
+    /// Index of the input file (in `Args::input_files`, from 0) the row was read from
+    pub input: usize,
+
+    /// Line number in the input file, if known
+    pub line: Option<u64>,
+
+    /// Machine-readable rejection reason (see `TransactionError::code`), or
+    /// `PARSE_ERROR` for a row that failed to parse
+    pub reason: &'static str,
+}
+
+/// Rejection reason of an input row that could not be parsed into a transaction, e.g.
+/// because of a malformed amount, a wrong number of columns or an unknown transaction
+/// type or currency.
+pub const PARSE_ERROR: &str = "parse_error";
+
+/// Serializable row of the audit trail of administrative transactions.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AuditEntry {
+    /// ID of the operator who issued the transaction, if given
+    pub operator: Option<String>,
+
+    /// Type of the transaction: `lock`, `unlock` or `adjustment`
+    #[serde(rename = "type")]
+    pub tx_type: TransactionType,
+
+    /// Client ID
+    pub client: u16,
+
+    /// Transaction ID
+    pub tx: u32,
+
+    /// Amount as given in the input, if any; signed for adjustments
+    pub amount: Option<Decimal>,
+
+    /// Currency of the account
+    pub currency: Currency,
+
+    /// Reason code of an adjustment
+    pub reason: Option<String>,
+
+    /// Index of the input file (in `Args::input_files`, from 0) the row was read from
+    pub input: usize,
+
+    /// Line number in the input file, if known
+    pub line: Option<u64>,
+
+    /// `applied`, `replayed` or `rejected`
+    pub status: &'static str,
+
+    /// Machine-readable rejection reason (see `TransactionError::code`), if rejected
+    pub rejection: Option<&'static str>,
+
+    /// Available funds after the transaction
+    pub available: Decimal,
+
+    /// Held funds after the transaction
+    pub held: Decimal,
+
+    /// Total funds after the transaction
+    pub total: Decimal,
+
+    /// Whether the account is locked after the transaction
+    pub locked: bool,
+}
+
+/// Serializable row of a client statement.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatementEntry {
+    /// Client ID
+    pub client: u16,
+
+    /// Type of the transaction
+    #[serde(rename = "type")]
+    pub tx_type: TransactionType,
+
+    /// Transaction ID
+    pub tx: u32,
+
+    /// Amount as given in the input, if any
+    pub amount: Option<Decimal>,
+
+    /// Currency of the amount and balances
+    pub currency: Currency,
+
+    /// Index of the input file (in `Args::input_files`, from 0) the row was read from
+    pub input: usize,
+
+    /// Line number in the input file, if known
+    pub line: Option<u64>,
+
+    /// `applied`, `replayed`, `rejected` or `expired` (an authorization expired by the engine)
+    pub status: &'static str,
+
+    /// Machine-readable rejection reason (see `TransactionError::code`), if rejected
+    pub reason: Option<&'static str>,
+
+    /// Available funds after the transaction
+    pub available: Decimal,
+
+    /// Held funds after the transaction
+    pub held: Decimal,
+
+    /// Total funds after the transaction
+    pub total: Decimal,
+
+    /// Whether the account is locked after the transaction
+    pub locked: bool,
+}
+
+/// Lifecycle state of a recorded transaction.
+///
+/// Deposits, withdrawals and transfers move through the dispute states; authorizations
+/// stay `Processed` while their funds are held and end `Captured`, `Voided` or `Expired`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionState {
+    /// Applied and never disputed.
+    #[default]
+    Processed,
+    /// Under dispute; the amount is held.
+    Disputed,
+    /// The dispute was resolved and the held amount released.
+    Resolved,
+    /// The dispute ended in a chargeback. This state is final.
+    ChargedBack,
+    /// The authorization was captured. This state is final.
+    Captured,
+    /// The authorization was voided. This state is final.
+    Voided,
+    /// The authorization expired before it was captured or voided. This state is final.
+    Expired,
 }
 
 /// A record representing the internal state of a transaction.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct TransactionRecord {
     pub client_id: u16,
     pub amount: Decimal,
-    pub disputed: bool,
+    pub state: TransactionState,
     pub tx_type: TransactionType,
+    /// Client credited by a transfer; `None` for deposits and withdrawals.
+    pub destination: Option<u16>,
+    /// Currency of the amount, in which disputes are settled.
+    pub currency: Currency,
 }
@@ -0,0 +1,135 @@
+use rust_decimal::Decimal;
+use tracing::warn;
+
+use crate::structures::{TransactionError, TransactionMessage, TransactionType, ValidationMode};
+
+/// Maximum number of decimal places accepted for transaction amounts.
+pub const MAX_AMOUNT_SCALE: u32 = 4;
+
+/// Validates a transaction message before it is applied by the engine.
+///
+/// Deposits, withdrawals, transfers and authorizations must carry a positive amount with
+/// at most four decimal places, as must captures that name one (a capture without an
+/// amount settles the whole authorization); disputes, resolves, chargebacks and voids must
+/// not carry an amount at all. Locks and unlocks are validated like disputes; adjustments
+/// carry a signed amount validated like a deposit's except for its sign, and a reason code.
+/// Every lock, unlock and adjustment must name its operator, regardless of `mode`.
+/// Transfers must also name a destination client other than their own, regardless of
+/// `mode`. How amount violations are handled depends on `mode`:
+///
+/// | Case                                  | Strict               | Lenient             |
+/// |---------------------------------------|----------------------|---------------------|
+/// | Missing amount (amount-carrying type) | `MissingAmount`      | `MissingAmount`     |
+/// | Negative amount                       | `NegativeAmount`     | `NegativeAmount`    |
+/// | Zero amount                           | `ZeroAmount`         | accepted            |
+/// | More than 4 decimal places            | `ExcessivePrecision` | rounded to 4 places |
+/// | Amount on dispute/resolve/chargeback  | `UnexpectedAmount`   | amount dropped      |
+/// | Amount on void, lock or unlock        | `UnexpectedAmount`   | amount dropped      |
+///
+/// Normalizations applied in lenient mode are logged together with the input line number.
+///
+/// # Returns
+/// - `Ok(TransactionMessage)`: The message, normalized if lenient mode required it.
+/// - `Err(TransactionError)`: The reason the message is invalid.
+pub fn validate(
+    mut msg: TransactionMessage,
+    mode: ValidationMode,
+) -> Result<TransactionMessage, TransactionError> {
+    if msg.tx_type.is_admin() && msg.operator.as_deref().is_none_or(str::is_empty) {
+        return Err(TransactionError::MissingOperator { tx: msg.tx });
+    }
+
+    match msg.tx_type {
+        TransactionType::Deposit | TransactionType::Withdrawal | TransactionType::Authorize => {
+            let amount = msg
+                .amount
+                .ok_or(TransactionError::MissingAmount { tx: msg.tx })?;
+            msg.amount = Some(validate_amount(&msg, amount, mode)?);
+        }
+        TransactionType::Transfer => {
+            match msg.destination {
+                None => return Err(TransactionError::MissingDestination { tx: msg.tx }),
+                Some(destination) if destination == msg.client => {
+                    return Err(TransactionError::SelfTransfer { tx: msg.tx });
+                }
+                Some(_) => {}
+            }
+            let amount = msg
+                .amount
+                .ok_or(TransactionError::MissingAmount { tx: msg.tx })?;
+            msg.amount = Some(validate_amount(&msg, amount, mode)?);
+        }
+        TransactionType::Adjustment => {
+            let amount = msg
+                .amount
+                .ok_or(TransactionError::MissingAmount { tx: msg.tx })?;
+            if msg.reason.as_deref().is_none_or(str::is_empty) {
+                return Err(TransactionError::MissingReason { tx: msg.tx });
+            }
+            // Adjustments may debit the account; the magnitude follows the deposit rules
+            let magnitude = validate_amount(&msg, amount.abs(), mode)?;
+            msg.amount = Some(if amount.is_sign_negative() {
+                -magnitude
+            } else {
+                magnitude
+            });
+        }
+        TransactionType::Capture => {
+            if let Some(amount) = msg.amount {
+                msg.amount = Some(validate_amount(&msg, amount, mode)?);
+            }
+        }
+        TransactionType::Dispute
+        | TransactionType::Resolve
+        | TransactionType::Chargeback
+        | TransactionType::Void
+        | TransactionType::Lock
+        | TransactionType::Unlock => {
+            if msg.amount.is_some() {
+                if mode == ValidationMode::Strict {
+                    return Err(TransactionError::UnexpectedAmount { tx: msg.tx });
+                }
+                warn!(
+                    "Line {:?}: ignoring amount on {:?} transaction {}",
+                    msg.line, msg.tx_type, msg.tx
+                );
+                msg.amount = None;
+            }
+        }
+    }
+
+    Ok(msg)
+}
+
+/// Checks the sign and precision of the amount of a deposit, withdrawal, transfer,
+/// authorization or capture, or of the magnitude of an adjustment.
+fn validate_amount(
+    msg: &TransactionMessage,
+    amount: Decimal,
+    mode: ValidationMode,
+) -> Result<Decimal, TransactionError> {
+    if amount.is_sign_negative() && !amount.is_zero() {
+        return Err(TransactionError::NegativeAmount { tx: msg.tx, amount });
+    }
+
+    // Trailing zeros (e.g. "1.000000") do not count as excess precision
+    let amount = if amount.normalize().scale() > MAX_AMOUNT_SCALE {
+        if mode == ValidationMode::Strict {
+            return Err(TransactionError::ExcessivePrecision { tx: msg.tx, amount });
+        }
+        let rounded = amount.round_dp(MAX_AMOUNT_SCALE);
+        warn!(
+            "Line {:?}: rounding amount {} of transaction {} to {}",
+            msg.line, amount, msg.tx, rounded
+        );
+        rounded
+    } else {
+        amount
+    };
+
+    if amount.is_zero() && mode == ValidationMode::Strict {
+        return Err(TransactionError::ZeroAmount { tx: msg.tx });
+    }
+
+    Ok(amount)
+}
@@ -0,0 +1,296 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::engine::Engine;
+use crate::storage::{AccountStore, TransactionStore};
+use crate::structures::{
+    Currency, ProcessedTransaction, ResumePoints, TransactionMessage, TransactionType,
+};
+
+/// Default number of appended entries after which the log is flushed and fsynced.
+pub const DEFAULT_SYNC_EVERY: usize = 64;
+
+/// A single line of the write-ahead log.
+///
+/// The log is newline-delimited JSON with one entry per message processed by the
+/// engine, in processing order. Rejected messages are logged as well: they may still
+/// open an empty account, so replaying them is required to reproduce the final report.
+///
+/// Entries are numbered by `seq`, which keeps increasing across runs and snapshots, so a
+/// snapshot records how much of the log it already includes.
+#[derive(Debug, Serialize, Deserialize)]
+struct WalEntry {
+    /// Sequence number of the entry, from 1; 0 in logs written before entries were
+    /// numbered.
+    #[serde(default)]
+    seq: u64,
+    #[serde(rename = "type")]
+    tx_type: TransactionType,
+    client: u16,
+    tx: u32,
+    amount: Option<Decimal>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    destination: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    currency: Option<Currency>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timestamp: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    operator: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+    line: Option<u64>,
+    #[serde(default)]
+    source: usize,
+}
+
+impl From<&TransactionMessage> for WalEntry {
+    fn from(msg: &TransactionMessage) -> Self {
+        Self {
+            seq: 0,
+            tx_type: msg.tx_type.clone(),
+            client: msg.client,
+            tx: msg.tx,
+            amount: msg.amount,
+            destination: msg.destination,
+            currency: msg.currency,
+            timestamp: msg.timestamp,
+            operator: msg.operator.clone(),
+            reason: msg.reason.clone(),
+            line: msg.line,
+            source: msg.source,
+        }
+    }
+}
+
+impl From<WalEntry> for TransactionMessage {
+    fn from(entry: WalEntry) -> Self {
+        TransactionMessage {
+            destination: entry.destination,
+            currency: entry.currency,
+            timestamp: entry.timestamp,
+            operator: entry.operator,
+            reason: entry.reason,
+            line: entry.line,
+            source: entry.source,
+            ..TransactionMessage::new(entry.tx_type, entry.client, entry.tx, entry.amount)
+        }
+    }
+}
+
+/// Append-only writer of the write-ahead log.
+///
+/// Entries are buffered and the file is fsynced once every `sync_every` appends and on
+/// `sync`. After a crash at most the last unsynced batch is lost; those rows are read
+/// again from the input on recovery.
+#[derive(Debug)]
+pub struct WalWriter {
+    writer: BufWriter<File>,
+    sync_every: usize,
+    pending: usize,
+    last_seq: u64,
+}
+
+impl WalWriter {
+    /// Opens the log at `path` for appending, creating it if it does not exist.
+    ///
+    /// A `sync_every` of 0 is treated as 1, i.e. every entry is synced. Entries are
+    /// numbered from 1, see `continue_after` to continue an existing sequence.
+    pub fn open(path: &Path, sync_every: usize) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            writer: BufWriter::new(file),
+            sync_every: sync_every.max(1),
+            pending: 0,
+            last_seq: 0,
+        })
+    }
+
+    /// Numbers the appended entries from `last_seq + 1`, continuing the sequence of a
+    /// recovered log or of the snapshot the engine started from (`RecoveryStats::last_seq`).
+    pub fn continue_after(mut self, last_seq: u64) -> Self {
+        self.last_seq = last_seq;
+        self
+    }
+
+    /// Returns the sequence number of the last appended entry, or the one the writer
+    /// continues after if nothing was appended yet.
+    pub fn last_seq(&self) -> u64 {
+        self.last_seq
+    }
+
+    /// Appends one processed message, syncing the log if the batch is full.
+    pub fn append(&mut self, msg: &TransactionMessage) -> io::Result<()> {
+        let entry = WalEntry {
+            seq: self.last_seq + 1,
+            ..WalEntry::from(msg)
+        };
+        serde_json::to_writer(&mut self.writer, &entry)?;
+        self.writer.write_all(b"\n")?;
+        self.last_seq = entry.seq;
+        self.pending += 1;
+        if self.pending >= self.sync_every {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    /// Flushes buffered entries and fsyncs the log file.
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_data()?;
+        self.pending = 0;
+        Ok(())
+    }
+}
+
+/// Result of replaying a write-ahead log.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecoveryStats {
+    /// Number of log entries replayed.
+    pub replayed: usize,
+    /// Number of log entries skipped because the snapshot already included them.
+    pub skipped: usize,
+    /// Highest line number found in the log per input index; each input up to its line
+    /// was processed.
+    pub resume: ResumePoints,
+    /// Sequence number of the last entry of the log or of the snapshot, whichever is
+    /// higher, for `WalWriter::continue_after`.
+    pub last_seq: u64,
+}
+
+/// Reads every complete entry of the log at `path`.
+///
+/// A missing file yields an empty log. A trailing line without a newline is a torn
+/// write from a crash and is discarded, while a malformed complete line is reported as
+/// an `InvalidData` error.
+pub fn read_wal(path: &Path) -> io::Result<Vec<TransactionMessage>> {
+    Ok(read_entries(path)?
+        .into_iter()
+        .map(TransactionMessage::from)
+        .collect())
+}
+
+/// Reads every complete entry of the log at `path`, see `read_wal`.
+fn read_entries(path: &Path) -> io::Result<Vec<WalEntry>> {
+    let mut contents = String::new();
+    match File::open(path) {
+        Ok(mut file) => file.read_to_string(&mut contents)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let complete = match contents.rfind('\n') {
+        Some(end) => &contents[..=end],
+        None => "",
+    };
+    if complete.len() < contents.len() {
+        warn!(
+            "Discarding torn write-ahead log entry: {:?}",
+            &contents[complete.len()..]
+        );
+    }
+
+    complete
+        .lines()
+        .map(|line| {
+            serde_json::from_str::<WalEntry>(line).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid write-ahead log entry: {e}"),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Replays the log at `path` into `engine` and truncates any torn trailing entry.
+///
+/// The engine should hold the state the log was written on top of, i.e. the same
+/// snapshot (or empty state) the interrupted run started from. Replayed messages are
+/// applied without being logged again.
+pub fn recover<A: AccountStore, T: TransactionStore>(
+    path: &Path,
+    engine: &mut Engine<A, T>,
+) -> io::Result<RecoveryStats> {
+    recover_with(path, engine, None, |_| {})
+}
+
+/// Same as `recover`, but skips the entries up to `snapshot_seq`, which the engine's
+/// snapshot already includes (`SnapshotStats::wal_seq`), and passes the outcome of every
+/// replayed message to `on_replay`, preceded by the authorizations it expired, as the
+/// engine's processing loop reports them.
+///
+/// Skipped entries still count towards the resume points, since their rows were processed.
+pub fn recover_with<A: AccountStore, T: TransactionStore>(
+    path: &Path,
+    engine: &mut Engine<A, T>,
+    snapshot_seq: Option<u64>,
+    mut on_replay: impl FnMut(ProcessedTransaction),
+) -> io::Result<RecoveryStats> {
+    let entries = read_entries(path)?;
+    let mut stats = RecoveryStats {
+        last_seq: snapshot_seq.unwrap_or(0),
+        ..RecoveryStats::default()
+    };
+
+    for entry in entries {
+        let seq = entry.seq;
+        let msg = TransactionMessage::from(entry);
+        if let Some(line) = msg.line {
+            let last = stats.resume.entry(msg.source).or_default();
+            *last = (*last).max(line);
+        }
+        stats.last_seq = stats.last_seq.max(seq);
+        if snapshot_seq.is_some_and(|included| seq <= included) {
+            stats.skipped += 1;
+            continue;
+        }
+        // The outcome is identical to the one observed when the entry was logged
+        let result = engine.apply(msg.clone());
+        engine.expired().iter().cloned().for_each(&mut on_replay);
+        on_replay(engine.processed(msg, result));
+        stats.replayed += 1;
+    }
+
+    if path.exists() {
+        // Drop a torn tail so new entries start on a fresh line
+        let valid_len = std::fs::read(path)?
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |end| end + 1);
+        OpenOptions::new()
+            .write(true)
+            .open(path)?
+            .set_len(valid_len as u64)?;
+    }
+
+    if stats.skipped > 0 {
+        info!(
+            "Skipped {} write-ahead log entries already included in the snapshot",
+            stats.skipped
+        );
+    }
+    if stats.replayed > 0 {
+        info!(
+            "Recovered {} entries from write-ahead log {:?}, resuming after lines {:?}",
+            stats.replayed, path, stats.resume
+        );
+    }
+    Ok(stats)
+}
+
+/// Empties the log at `path` once its entries are no longer needed for recovery.
+pub fn truncate_wal(path: &Path) -> io::Result<()> {
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(true)
+        .write(true)
+        .open(path)?;
+    file.sync_all()
+}
@@ -0,0 +1,171 @@
+use std::path::Path;
+
+use payments_engine::audit::{
+    append_audit_entries, audit_entry, open_audit_trail, unrecorded_audit_entries,
+    write_audit_report,
+};
+use payments_engine::engine::{Engine, EngineHandle};
+use payments_engine::structures::{TransactionMessage, TransactionType};
+use payments_engine::wal::{WalWriter, recover_with};
+use rust_decimal::Decimal;
+use tempfile::{NamedTempFile, TempDir};
+use tokio::sync::mpsc;
+
+/// Helper returning administrative messages for client 1, numbered from line 2.
+fn admin_messages() -> Vec<TransactionMessage> {
+    let admin = |tx_type, tx, amount: Option<i64>, reason: Option<&str>| TransactionMessage {
+        operator: Some("ops-7".to_owned()),
+        reason: reason.map(str::to_owned),
+        ..TransactionMessage::new(tx_type, 1, tx, amount.map(Decimal::from))
+    };
+    [
+        TransactionMessage::new(TransactionType::Deposit, 1, 1, Some(Decimal::TEN)),
+        admin(TransactionType::Lock, 2, None, None),
+        admin(TransactionType::Adjustment, 3, Some(-4), Some("fee_refund")),
+        admin(TransactionType::Adjustment, 4, Some(2), None),
+        TransactionMessage::new(TransactionType::Unlock, 1, 5, None),
+        admin(TransactionType::Unlock, 6, None, None),
+    ]
+    .into_iter()
+    .enumerate()
+    .map(|(line, msg)| TransactionMessage {
+        line: Some(line as u64 + 2),
+        ..msg
+    })
+    .collect()
+}
+
+/// Helper running the messages of `messages` the write-ahead log at `wal` does not cover
+/// yet, up to line `until`, as the binary does: the log is recovered first and the audit
+/// trail at `trail` completed with the replayed entries it misses.
+async fn run_until(
+    messages: &[TransactionMessage],
+    until: u64,
+    wal: &Path,
+    trail: &str,
+) -> std::io::Result<()> {
+    let mut engine = Engine::new();
+    let mut recovered = Vec::new();
+    let stats = recover_with(wal, &mut engine, None, |processed| {
+        recovered.extend(audit_entry(&processed));
+    })?;
+    let mut file = open_audit_trail(trail).await?;
+    append_audit_entries(unrecorded_audit_entries(trail, recovered).await?, &mut file).await?;
+
+    let (outcome_sender, outcome_receiver) = mpsc::channel(10);
+    let report = tokio::spawn(write_audit_report(outcome_receiver, file));
+    let engine = engine.with_wal(WalWriter::open(wal, 100)?.continue_after(stats.last_seq));
+    let handle = EngineHandle::spawn(engine, 10, Some(outcome_sender));
+    let resume = stats.resume.get(&0).copied().unwrap_or(0);
+    for msg in messages {
+        let line = msg.line.unwrap();
+        if line > resume && line <= until {
+            handle.sender().send(msg.clone()).await.unwrap();
+        }
+    }
+    handle.join().await.unwrap();
+    report.await.unwrap()?;
+    Ok(())
+}
+
+/// @brief The audit trail lists every administrative message, applied or rejected, with
+/// its operator, reason code and the account state after it, and nothing else.
+#[tokio::test]
+async fn test_write_audit_report() -> std::io::Result<()> {
+    let tmpfile = NamedTempFile::new()?;
+    let path = tmpfile.path().to_str().unwrap().to_string();
+
+    let (outcome_sender, outcome_receiver) = mpsc::channel(10);
+    let file = open_audit_trail(&path).await?;
+    let report = tokio::spawn(write_audit_report(outcome_receiver, file));
+
+    let handle = EngineHandle::spawn(Engine::new(), 10, Some(outcome_sender));
+    let sender = handle.sender();
+    for msg in admin_messages() {
+        sender.send(msg).await.unwrap();
+    }
+    drop(sender);
+    handle.join().await.unwrap();
+
+    assert_eq!(report.await.unwrap()?, 5);
+    let contents = std::fs::read_to_string(tmpfile.path())?;
+    assert_eq!(
+        contents,
+        "operator,type,client,tx,amount,currency,reason,input,line,status,rejection,available,held,total,locked\n\
+         ops-7,lock,1,2,,EUR,,0,3,applied,,10.0000,0.0000,10.0000,true\n\
+         ops-7,adjustment,1,3,-4,EUR,fee_refund,0,4,applied,,6.0000,0.0000,6.0000,true\n\
+         ops-7,adjustment,1,4,2,EUR,,0,5,rejected,missing_reason,6.0000,0.0000,6.0000,true\n\
+         ,unlock,1,5,,EUR,,0,6,rejected,missing_operator,6.0000,0.0000,6.0000,true\n\
+         ops-7,unlock,1,6,,EUR,,0,7,applied,,6.0000,0.0000,6.0000,false\n"
+    );
+    Ok(())
+}
+
+/// @brief An existing trail is appended to, without repeating its header.
+#[tokio::test]
+async fn test_audit_trail_appends() -> std::io::Result<()> {
+    let tmpfile = NamedTempFile::new()?;
+    let path = tmpfile.path().to_str().unwrap().to_string();
+
+    for tx in 1..=2 {
+        let (outcome_sender, outcome_receiver) = mpsc::channel(10);
+        let report = tokio::spawn(write_audit_report(
+            outcome_receiver,
+            open_audit_trail(&path).await?,
+        ));
+        let handle = EngineHandle::spawn(Engine::new(), 10, Some(outcome_sender));
+        let lock = TransactionMessage {
+            operator: Some("ops-7".to_owned()),
+            line: Some(2),
+            ..TransactionMessage::new(TransactionType::Lock, tx, tx.into(), None)
+        };
+        handle.sender().send(lock).await.unwrap();
+        handle.join().await.unwrap();
+        assert_eq!(report.await.unwrap()?, 1);
+    }
+
+    let contents = std::fs::read_to_string(tmpfile.path())?;
+    assert_eq!(
+        contents,
+        "operator,type,client,tx,amount,currency,reason,input,line,status,rejection,available,held,total,locked\n\
+         ops-7,lock,1,1,,EUR,,0,2,applied,,0.0000,0.0000,0.0000,true\n\
+         ops-7,lock,2,2,,EUR,,0,2,applied,,0.0000,0.0000,0.0000,true\n"
+    );
+    Ok(())
+}
+
+/// @brief Recovering an interrupted run, twice, completes the audit trail with the
+/// replayed entries it misses without repeating the ones it already has.
+///
+/// The first run is interrupted before its last audit row reaches the file, the second
+/// one right after processing another administrative message.
+#[tokio::test]
+async fn test_recovered_audit_trail_has_no_duplicates() -> std::io::Result<()> {
+    let messages = admin_messages();
+    let dir = TempDir::new()?;
+    let wal = dir.path().join("engine.wal");
+
+    let expected_path = dir.path().join("expected.csv");
+    let expected_path = expected_path.to_str().unwrap();
+    run_until(
+        &messages,
+        u64::MAX,
+        &dir.path().join("other.wal"),
+        expected_path,
+    )
+    .await?;
+    let expected = std::fs::read_to_string(expected_path)?;
+
+    let trail = dir.path().join("audit.csv");
+    let trail = trail.to_str().unwrap();
+    run_until(&messages, 4, &wal, trail).await?;
+    let contents = std::fs::read_to_string(trail)?;
+    let without_last_row = contents.trim_end().rsplit_once('\n').unwrap().0;
+    std::fs::write(trail, format!("{without_last_row}\n"))?;
+
+    run_until(&messages, 6, &wal, trail).await?;
+    run_until(&messages, u64::MAX, &wal, trail).await?;
+
+    assert_eq!(std::fs::read_to_string(trail)?, expected);
+    Ok(())
+}
@@ -0,0 +1,157 @@
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use payments_engine::compact_store::CompactTransactionStore;
+use payments_engine::engine::Engine;
+use payments_engine::storage::TransactionStore;
+use payments_engine::structures::{
+    Currency, EngineConfig, TransactionMessage, TransactionRecord, TransactionState,
+    TransactionType, TransactionsMap, WithdrawalDisputePolicy,
+};
+use rust_decimal::Decimal;
+
+/// Helper collecting every record of a store in tx id order.
+fn records<S: TransactionStore>(store: &S) -> Vec<(u32, TransactionRecord)> {
+    let mut records = Vec::new();
+    store
+        .for_each_record(&mut |tx, record| {
+            records.push((tx, record));
+            Ok(())
+        })
+        .unwrap();
+    records.sort_by_key(|(tx, _)| *tx);
+    records
+}
+
+fn record(client_id: u16, amount: Decimal, tx_type: TransactionType) -> TransactionRecord {
+    TransactionRecord {
+        client_id,
+        amount,
+        state: TransactionState::Processed,
+        tx_type,
+        destination: None,
+        currency: Currency::Eur,
+    }
+}
+
+/// @brief Test that the compact store behaves exactly like the map for mixed workloads.
+///
+/// Covers dense ranges that switch chunks from the sparse to the dense layout, scattered
+/// ids up to `u32::MAX`, replaced records, state updates (including states the tag cannot
+/// encode for the record's type), transfer destinations, currencies and amounts that only
+/// fit the overflow map.
+#[test]
+fn test_compact_store_matches_map() {
+    let compact = CompactTransactionStore::new();
+    let map: TransactionsMap = Arc::new(DashMap::new());
+
+    let amounts = [
+        Decimal::new(12345, 4),
+        Decimal::new(-7, 0),
+        Decimal::ZERO,
+        Decimal::new(1, 9),
+        Decimal::MAX,
+        Decimal::new(i64::MIN, 2),
+    ];
+    let dense = 1..=600u32;
+    let scattered = (0..50u32)
+        .map(|i| i.wrapping_mul(2_654_435_761))
+        .chain([u32::MAX]);
+
+    for tx in dense.chain(scattered) {
+        let tx_type = if tx % 50 == 49 {
+            TransactionType::Dispute
+        } else if tx % 5 == 2 {
+            TransactionType::Authorize
+        } else if tx % 3 == 0 {
+            TransactionType::Withdrawal
+        } else if tx % 4 == 1 {
+            TransactionType::Transfer
+        } else {
+            TransactionType::Deposit
+        };
+        let rec = TransactionRecord {
+            destination: (tx_type == TransactionType::Transfer).then_some(tx as u16 ^ 1),
+            currency: [Currency::Eur, Currency::Usd, Currency::Pln][tx as usize % 3],
+            ..record(tx as u16, amounts[tx as usize % amounts.len()], tx_type)
+        };
+        compact.insert_record(tx, rec.clone()).unwrap();
+        map.insert_record(tx, rec).unwrap();
+    }
+
+    let states = [
+        TransactionState::Disputed,
+        TransactionState::Captured,
+        TransactionState::Expired,
+        TransactionState::ChargedBack,
+        TransactionState::Voided,
+        TransactionState::Processed,
+    ];
+    for (i, tx) in (1..=600u32).step_by(7).enumerate() {
+        compact.set_state(tx, states[i % states.len()]).unwrap();
+        map.set_state(tx, states[i % states.len()]).unwrap();
+    }
+    for tx in [14, 21, u32::MAX] {
+        let rec = record(9, Decimal::new(5, 1), TransactionType::Deposit);
+        compact.insert_record(tx, rec.clone()).unwrap();
+        map.insert_record(tx, rec).unwrap();
+    }
+    // Updating the state of an unknown record is a no-op
+    compact
+        .set_state(100_000, TransactionState::Resolved)
+        .unwrap();
+
+    assert_eq!(compact.record_count().unwrap(), map.record_count().unwrap());
+    assert_eq!(records(&compact), records(&map));
+    for tx in [0, 1, 7, 256, 600, 601, 100_000, u32::MAX] {
+        assert_eq!(compact.record(tx).unwrap(), map.record(tx).unwrap());
+    }
+}
+
+/// @brief Test that amounts keep their exact value and scale when stored compactly.
+#[test]
+fn test_compact_store_preserves_amount_scale() {
+    let store = CompactTransactionStore::new();
+    for (tx, amount) in [
+        (1, "10.0"),
+        (2, "0.0001"),
+        (3, "12345678901.1234567"),
+        (4, "1e-20"),
+    ] {
+        let amount: Decimal = amount.parse().unwrap();
+        store
+            .insert_record(tx, record(1, amount, TransactionType::Deposit))
+            .unwrap();
+        let stored = store.record(tx).unwrap().unwrap().amount;
+        assert_eq!(stored.to_string(), amount.to_string());
+    }
+}
+
+/// @brief Test that an engine on the compact store produces the same accounts as on the map.
+#[test]
+fn test_engine_on_compact_store() {
+    let config = EngineConfig {
+        withdrawal_disputes: WithdrawalDisputePolicy::ProvisionalCredit,
+        ..Default::default()
+    };
+    let mut on_map = Engine::new().with_config(config.clone());
+    let mut on_compact =
+        Engine::with_stores(Arc::new(DashMap::new()), CompactTransactionStore::new())
+            .with_config(config);
+
+    let messages = [
+        TransactionMessage::new(TransactionType::Deposit, 1, 1, Some(Decimal::new(100, 1))),
+        TransactionMessage::new(TransactionType::Withdrawal, 1, 2, Some(Decimal::new(25, 1))),
+        TransactionMessage::new(TransactionType::Dispute, 1, 2, None),
+        TransactionMessage::new(TransactionType::Chargeback, 1, 2, None),
+        TransactionMessage::new(TransactionType::Deposit, 2, 1_000_000, Some(Decimal::ONE)),
+        TransactionMessage::new(TransactionType::Deposit, 2, 1_000_000, Some(Decimal::ONE)),
+        TransactionMessage::new(TransactionType::Dispute, 2, 1_000_000, None),
+        TransactionMessage::new(TransactionType::Resolve, 2, 1_000_000, None),
+        TransactionMessage::new(TransactionType::Dispute, 2, 1, None),
+    ];
+    for msg in messages {
+        assert_eq!(on_compact.apply(msg.clone()), on_map.apply(msg));
+    }
+    assert_eq!(on_compact.accounts(), on_map.accounts());
+}
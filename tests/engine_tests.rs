@@ -1,7 +1,9 @@
 use dashmap::DashMap;
-use payments_engine::engine::process_transaction;
+use payments_engine::engine::{Engine, EngineHandle, process_transaction};
 use payments_engine::structures::{
-    ClientsMap, TransactionMessage, TransactionType, TransactionsMap,
+    ClientsMap, Currency, EngineConfig, Outcome, RedisputePolicy, TransactionError,
+    TransactionMessage, TransactionState, TransactionType, TransactionsMap,
+    WithdrawalDisputePolicy,
 };
 use rust_decimal::Decimal;
 use std::sync::Arc;
@@ -10,13 +12,13 @@ use tokio::sync::mpsc;
 /// @brief Asynchronous test for basic transaction processing flow.
 ///
 /// This test verifies that the transaction processor correctly handles
-/// a simple sequence of deposit and withdrawal transactions, followed
-/// by a termination message.
+/// a simple sequence of deposit and withdrawal transactions, and stops
+/// once the sending side of the channel is dropped.
 ///
 /// Steps tested:
 /// - Sending a deposit transaction (client 1 deposits 10.0).
 /// - Sending a withdrawal transaction (client 1 withdraws 5.0).
-/// - Sending a terminate transaction to stop the processor.
+/// - Dropping the sender to stop the processor.
 ///
 /// After processing:
 /// - The client's available balance and total balance are correctly updated.
@@ -29,30 +31,21 @@ async fn test_process_transaction_basic_flow() {
     let (tx, rx) = mpsc::channel(10);
 
     let send_task = tokio::spawn(async move {
-        tx.send(TransactionMessage {
-            tx_type: TransactionType::Deposit,
-            client: 1,
-            tx: 1,
-            amount: Some(Decimal::new(100, 1)), // 10.0
-        })
+        tx.send(TransactionMessage::new(
+            TransactionType::Deposit,
+            1,
+            1,
+            Some(Decimal::new(100, 1)), // 10.0
+        ))
         .await
         .unwrap();
 
-        tx.send(TransactionMessage {
-            tx_type: TransactionType::Withdrawal,
-            client: 1,
-            tx: 2,
-            amount: Some(Decimal::new(50, 1)), // 5.0
-        })
-        .await
-        .unwrap();
-
-        tx.send(TransactionMessage {
-            tx_type: TransactionType::Terminate,
-            client: 0,
-            tx: 0,
-            amount: None,
-        })
+        tx.send(TransactionMessage::new(
+            TransactionType::Withdrawal,
+            1,
+            2,
+            Some(Decimal::new(50, 1)), // 5.0
+        ))
         .await
         .unwrap();
     });
@@ -60,13 +53,15 @@ async fn test_process_transaction_basic_flow() {
     let transactions_clone = transactions.clone();
     let clients_clone = clients.clone();
     let processor_task = tokio::spawn(async move {
-        process_transaction(rx, clients_clone, transactions_clone).await;
+        process_transaction(rx, clients_clone, transactions_clone, None).await;
     });
 
     send_task.await.unwrap();
     processor_task.await.unwrap();
 
-    let client1 = clients.get(&1).expect("Client 1 should exist");
+    let client1 = clients
+        .get(&(1, Currency::Eur))
+        .expect("Client 1 should exist");
     let client1 = client1.value();
 
     assert_eq!(client1.available, Decimal::new(50, 1)); // 10 - 5 = 5.0
@@ -85,7 +80,7 @@ async fn test_process_transaction_basic_flow() {
 /// - Resolve
 /// - Chargeback
 /// - Attempted withdrawal after account is locked
-/// - Termination of processing
+/// - Stopping the processor by dropping the sender
 ///
 /// It verifies correct state transitions of client balances and transaction dispute flags,
 /// as well as account locking after chargeback.#[tokio::test]
@@ -98,82 +93,72 @@ async fn test_process_transaction_all_types() {
 
     let send_task = tokio::spawn(async move {
         // Deposit 10.0
-        tx.send(TransactionMessage {
-            tx_type: TransactionType::Deposit,
-            client: 1,
-            tx: 1,
-            amount: Some(Decimal::new(100, 1)), // 10.0
-        })
+        tx.send(TransactionMessage::new(
+            TransactionType::Deposit,
+            1,
+            1,
+            Some(Decimal::new(100, 1)), // 10.0
+        ))
         .await
         .unwrap();
 
         // Withdrawal 5.0 (should succeed)
-        tx.send(TransactionMessage {
-            tx_type: TransactionType::Withdrawal,
-            client: 1,
-            tx: 2,
-            amount: Some(Decimal::new(50, 1)), // 5.0
-        })
+        tx.send(TransactionMessage::new(
+            TransactionType::Withdrawal,
+            1,
+            2,
+            Some(Decimal::new(50, 1)), // 5.0
+        ))
         .await
         .unwrap();
 
         // Dispute on Deposit tx=1
-        tx.send(TransactionMessage {
-            tx_type: TransactionType::Dispute,
-            client: 1,
-            tx: 1,
-            amount: None,
-        })
+        tx.send(TransactionMessage::new(
+            TransactionType::Dispute,
+            1,
+            1,
+            None,
+        ))
         .await
         .unwrap();
 
         // Resolve dispute on tx=1
-        tx.send(TransactionMessage {
-            tx_type: TransactionType::Resolve,
-            client: 1,
-            tx: 1,
-            amount: None,
-        })
+        tx.send(TransactionMessage::new(
+            TransactionType::Resolve,
+            1,
+            1,
+            None,
+        ))
         .await
         .unwrap();
 
         // Dispute again on tx=1
-        tx.send(TransactionMessage {
-            tx_type: TransactionType::Dispute,
-            client: 1,
-            tx: 1,
-            amount: None,
-        })
+        tx.send(TransactionMessage::new(
+            TransactionType::Dispute,
+            1,
+            1,
+            None,
+        ))
         .await
         .unwrap();
 
         // Chargeback on tx=1 (freezes account)
-        tx.send(TransactionMessage {
-            tx_type: TransactionType::Chargeback,
-            client: 1,
-            tx: 1,
-            amount: None,
-        })
+        tx.send(TransactionMessage::new(
+            TransactionType::Chargeback,
+            1,
+            1,
+            None,
+        ))
         .await
         .unwrap();
 
         // Attempt withdrawal after chargeback (should be ignored because account locked)
-        tx.send(TransactionMessage {
-            tx_type: TransactionType::Withdrawal,
-            client: 1,
-            tx: 3,
-            amount: Some(Decimal::new(10, 1)),
-        })
-        .await
-        .unwrap();
-
-        // Terminate processor
-        tx.send(TransactionMessage {
-            tx_type: TransactionType::Terminate,
-            client: 0,
-            tx: 0,
-            amount: None,
-        })
+        tx.send(TransactionMessage::new(
+            TransactionType::Withdrawal,
+            1,
+            3,
+            Some(Decimal::new(10, 1)),
+        ))
         .await
         .unwrap();
     });
@@ -181,18 +166,20 @@ async fn test_process_transaction_all_types() {
     let clients_clone = clients.clone();
     let transactions_clone = transactions.clone();
     let processor_task = tokio::spawn(async move {
-        process_transaction(rx, clients_clone, transactions_clone).await;
+        process_transaction(rx, clients_clone, transactions_clone, None).await;
     });
 
     send_task.await.unwrap();
     processor_task.await.unwrap();
 
-    let client = clients.get(&1).expect("Client 1 should exist");
+    let client = clients
+        .get(&(1, Currency::Eur))
+        .expect("Client 1 should exist");
     let client = client.value();
 
-    // After deposit 10, withdrawal 5, dispute holds 10, resolve returns 10, dispute again holds 10,
-    // chargeback deducts 10 and locks account
-    // Available = 0, held = 0, total = 0, locked = true
+    // After deposit 10 and withdrawal 5 only 5 is available, so disputing the 10.0 deposit
+    // is rejected; resolve and chargeback are rejected as the deposit was never disputed.
+    // The final withdrawal of 1 succeeds: available = 4, held = 0, total = 4, locked = false
 
     assert_eq!(client.available, Decimal::new(4, 0));
     assert_eq!(client.held, Decimal::new(0, 0));
@@ -205,15 +192,1237 @@ async fn test_process_transaction_all_types() {
 
     assert_eq!(tx1.client_id, 1);
     assert_eq!(tx1.amount, Decimal::new(100, 1)); // 10.0
-    assert!(
-        !tx1.disputed,
-        "Transaction 1 should no longer be disputed after chargeback"
+    assert_eq!(
+        tx1.state,
+        TransactionState::Processed,
+        "Transaction 1 should never have entered a dispute"
     );
     assert_eq!(tx1.tx_type, TransactionType::Deposit);
 
     // Withdrawal tx=2 should exist
     assert!(transactions.contains_key(&2));
 
-    // Withdrawal tx=3 should NOT exist, because account locked prevented it
-    assert!(!transactions.contains_key(&3));
+    // Withdrawal tx=3 should exist: the disputes were rejected, so the account never locked
+    assert!(transactions.contains_key(&3));
+}
+
+/// @brief Asynchronous test verifying that rejected transactions are reported with a reason.
+///
+/// This test sends a deposit followed by messages that must all be rejected:
+/// - A withdrawal exceeding the available balance.
+/// - A dispute referencing an unknown transaction.
+/// - A dispute by a client that does not own the referenced transaction.
+/// - A resolve on a transaction that is not disputed.
+///
+/// It verifies that every message is reported on the outcomes channel, in order,
+/// with `Ok(Outcome::Applied)` or an `Err` carrying the expected `TransactionError`.
+#[tokio::test]
+async fn test_process_transaction_reports_rejections() {
+    let clients: ClientsMap = Arc::new(DashMap::new());
+    let transactions: TransactionsMap = Arc::new(DashMap::new());
+
+    let (tx, rx) = mpsc::channel(10);
+    let (outcome_tx, mut outcome_rx) = mpsc::channel(10);
+
+    let messages = vec![
+        (TransactionType::Deposit, 1, 1, Some(Decimal::new(100, 1))),
+        (
+            TransactionType::Withdrawal,
+            1,
+            2,
+            Some(Decimal::new(200, 1)),
+        ),
+        (TransactionType::Dispute, 1, 99, None),
+        (TransactionType::Dispute, 2, 1, None),
+        (TransactionType::Resolve, 1, 1, None),
+    ];
+
+    let send_task = tokio::spawn(async move {
+        for (tx_type, client, tx_id, amount) in messages {
+            tx.send(TransactionMessage::new(tx_type, client, tx_id, amount))
+                .await
+                .unwrap();
+        }
+    });
+
+    let processor_task = tokio::spawn(process_transaction(
+        rx,
+        clients.clone(),
+        transactions.clone(),
+        Some(outcome_tx),
+    ));
+
+    send_task.await.unwrap();
+    processor_task.await.unwrap();
+
+    let mut outcomes = Vec::new();
+    while let Some(processed) = outcome_rx.recv().await {
+        outcomes.push(processed.result);
+    }
+
+    assert_eq!(
+        outcomes,
+        vec![
+            Ok(Outcome::Applied),
+            Err(TransactionError::InsufficientFunds {
+                client: 1,
+                available: Decimal::new(100, 1),
+                requested: Decimal::new(200, 1),
+            }),
+            Err(TransactionError::TransactionNotFound { tx: 99 }),
+            Err(TransactionError::ClientMismatch {
+                tx: 1,
+                owner: 1,
+                client: 2,
+            }),
+            Err(TransactionError::NotDisputed { tx: 1 }),
+        ]
+    );
+
+    let client1 = clients
+        .get(&(1, Currency::Eur))
+        .expect("Client 1 should exist");
+    assert_eq!(client1.available, Decimal::new(100, 1));
+    assert_eq!(client1.held, Decimal::new(0, 0));
+}
+
+/// @brief Synchronous test of the embeddable `Engine` API.
+///
+/// This test drives `Engine::apply` directly, without a tokio runtime or channel,
+/// through a deposit, a withdrawal, a dispute, a resolve and a rejected withdrawal.
+///
+/// It verifies:
+/// - `apply` returns `Ok(Outcome::Applied)` or the expected `TransactionError`.
+/// - `account` reflects the balances after each step and is `None` for unknown clients.
+/// - `accounts` lists every account sorted by client ID.
+#[test]
+fn test_engine_apply_sync() {
+    let mut engine = Engine::new();
+
+    assert_eq!(
+        engine.apply(TransactionMessage::new(
+            TransactionType::Deposit,
+            2,
+            1,
+            Some(Decimal::new(100, 1)), // 10.0
+        )),
+        Ok(Outcome::Applied)
+    );
+    assert_eq!(
+        engine.apply(TransactionMessage::new(
+            TransactionType::Deposit,
+            1,
+            2,
+            Some(Decimal::new(30, 1)), // 3.0
+        )),
+        Ok(Outcome::Applied)
+    );
+    assert_eq!(
+        engine.apply(TransactionMessage::new(
+            TransactionType::Withdrawal,
+            2,
+            3,
+            Some(Decimal::new(25, 1)), // 2.5
+        )),
+        Ok(Outcome::Applied)
+    );
+    assert_eq!(
+        engine.apply(TransactionMessage::new(
+            TransactionType::Dispute,
+            1,
+            2,
+            None
+        )),
+        Ok(Outcome::Applied)
+    );
+
+    let client1 = engine.account(1).expect("Client 1 should exist");
+    assert_eq!(client1.available, Decimal::new(0, 0));
+    assert_eq!(client1.held, Decimal::new(30, 1));
+    assert_eq!(client1.total, Decimal::new(30, 1));
+
+    assert_eq!(
+        engine.apply(TransactionMessage::new(
+            TransactionType::Withdrawal,
+            1,
+            4,
+            Some(Decimal::new(10, 1)),
+        )),
+        Err(TransactionError::InsufficientFunds {
+            client: 1,
+            available: Decimal::new(0, 0),
+            requested: Decimal::new(10, 1),
+        })
+    );
+    assert_eq!(
+        engine.apply(TransactionMessage::new(
+            TransactionType::Resolve,
+            1,
+            2,
+            None
+        )),
+        Ok(Outcome::Applied)
+    );
+
+    assert!(engine.account(3).is_none());
+
+    let accounts = engine.accounts();
+    assert_eq!(accounts.len(), 2);
+    assert_eq!(accounts[0].client, 1);
+    assert_eq!(accounts[0].available, Decimal::new(30, 1));
+    assert_eq!(accounts[0].held, Decimal::new(0, 0));
+    assert_eq!(accounts[1].client, 2);
+    assert_eq!(accounts[1].available, Decimal::new(75, 1));
+    assert_eq!(accounts[1].total, Decimal::new(75, 1));
+}
+
+/// @brief Asynchronous test for several concurrent producers feeding one engine.
+///
+/// Three producers each send deposits for their own client through senders obtained
+/// from the same `EngineHandle`. The engine stops on its own once all of them (and the
+/// handle) have dropped their senders.
+///
+/// It verifies that every deposit from every producer was applied before `join` returned.
+#[tokio::test]
+async fn test_engine_handle_multiple_producers() {
+    let handle = EngineHandle::spawn(Engine::new(), 4, None);
+
+    let producers: Vec<_> = (1..=3u16)
+        .map(|client| {
+            let sender = handle.sender();
+            tokio::spawn(async move {
+                for i in 0..50u32 {
+                    let tx_id = u32::from(client) * 1000 + i;
+                    sender
+                        .send(TransactionMessage::new(
+                            TransactionType::Deposit,
+                            client,
+                            tx_id,
+                            Some(Decimal::new(1, 0)),
+                        ))
+                        .await
+                        .unwrap();
+                }
+            })
+        })
+        .collect();
+
+    for producer in producers {
+        producer.await.unwrap();
+    }
+    let engine = handle.join().await.unwrap();
+
+    let accounts = engine.accounts();
+    assert_eq!(accounts.len(), 3);
+    for account in accounts {
+        assert_eq!(account.available, Decimal::new(50, 0));
+        assert_eq!(account.total, Decimal::new(50, 0));
+    }
+}
+
+/// @brief Asynchronous test for graceful shutdown draining in-flight messages.
+///
+/// A producer fills the channel and keeps its sender alive. `shutdown` is then called,
+/// which must close the channel to new messages while still applying everything that
+/// was already buffered.
+///
+/// It verifies:
+/// - All buffered deposits are reflected in the returned engine.
+/// - The still-connected producer observes a send error after shutdown.
+#[tokio::test]
+async fn test_engine_handle_shutdown_drains_in_flight() {
+    let handle = EngineHandle::spawn(Engine::new(), 16, None);
+    let sender = handle.sender();
+
+    for tx_id in 1..=10u32 {
+        sender
+            .send(TransactionMessage::new(
+                TransactionType::Deposit,
+                1,
+                tx_id,
+                Some(Decimal::new(1, 0)),
+            ))
+            .await
+            .unwrap();
+    }
+
+    let engine = handle.shutdown().await.unwrap();
+
+    let account = engine.account(1).expect("Client 1 should exist");
+    assert_eq!(account.available, Decimal::new(10, 0));
+
+    let late = TransactionMessage::new(TransactionType::Deposit, 1, 11, Some(Decimal::ONE));
+    assert!(sender.send(late).await.is_err());
+}
+
+/// Helper creating an engine with the given withdrawal dispute policy, a 10.0 deposit
+/// (tx 1) and a 4.0 withdrawal (tx 2) for client 1.
+fn engine_with_withdrawal(policy: WithdrawalDisputePolicy) -> Engine {
+    let mut engine = Engine::new().with_config(EngineConfig {
+        withdrawal_disputes: policy,
+        ..Default::default()
+    });
+    engine
+        .apply(TransactionMessage::new(
+            TransactionType::Deposit,
+            1,
+            1,
+            Some(Decimal::new(100, 1)),
+        ))
+        .unwrap();
+    engine
+        .apply(TransactionMessage::new(
+            TransactionType::Withdrawal,
+            1,
+            2,
+            Some(Decimal::new(40, 1)),
+        ))
+        .unwrap();
+    engine
+}
+
+/// @brief Test that disputes on withdrawals are rejected under the default policy.
+#[test]
+fn test_withdrawal_dispute_rejected_by_default() {
+    let mut engine = engine_with_withdrawal(WithdrawalDisputePolicy::Reject);
+
+    assert_eq!(
+        engine.apply(TransactionMessage::new(
+            TransactionType::Dispute,
+            1,
+            2,
+            None
+        )),
+        Err(TransactionError::NotDisputable {
+            tx: 2,
+            tx_type: TransactionType::Withdrawal,
+        })
+    );
+
+    let account = engine.account(1).unwrap();
+    assert_eq!(account.available, Decimal::new(60, 1));
+    assert_eq!(account.held, Decimal::new(0, 0));
+    assert_eq!(account.total, Decimal::new(60, 1));
+}
+
+/// @brief Test a disputed withdrawal that is resolved under the provisional-credit policy.
+///
+/// Disputing the 4.0 withdrawal holds a provisional credit of 4.0 (held and total grow,
+/// available is untouched). Resolving the dispute reverses the credit, restoring the
+/// balances from before the dispute.
+#[test]
+fn test_withdrawal_dispute_resolve_reverses_credit() {
+    let mut engine = engine_with_withdrawal(WithdrawalDisputePolicy::ProvisionalCredit);
+
+    assert_eq!(
+        engine.apply(TransactionMessage::new(
+            TransactionType::Dispute,
+            1,
+            2,
+            None
+        )),
+        Ok(Outcome::Applied)
+    );
+    let account = engine.account(1).unwrap();
+    assert_eq!(account.available, Decimal::new(60, 1));
+    assert_eq!(account.held, Decimal::new(40, 1));
+    assert_eq!(account.total, Decimal::new(100, 1));
+
+    assert_eq!(
+        engine.apply(TransactionMessage::new(
+            TransactionType::Resolve,
+            1,
+            2,
+            None
+        )),
+        Ok(Outcome::Applied)
+    );
+    let account = engine.account(1).unwrap();
+    assert_eq!(account.available, Decimal::new(60, 1));
+    assert_eq!(account.held, Decimal::new(0, 0));
+    assert_eq!(account.total, Decimal::new(60, 1));
+    assert!(!account.locked);
+}
+
+/// @brief Test a disputed withdrawal that is charged back under the provisional-credit policy.
+///
+/// The chargeback makes the provisional credit permanent: the held 4.0 moves to available,
+/// the total keeps the credited amount and the account is locked.
+#[test]
+fn test_withdrawal_dispute_chargeback_makes_credit_permanent() {
+    let mut engine = engine_with_withdrawal(WithdrawalDisputePolicy::ProvisionalCredit);
+
+    engine
+        .apply(TransactionMessage::new(
+            TransactionType::Dispute,
+            1,
+            2,
+            None,
+        ))
+        .unwrap();
+    assert_eq!(
+        engine.apply(TransactionMessage::new(
+            TransactionType::Chargeback,
+            1,
+            2,
+            None
+        )),
+        Ok(Outcome::Applied)
+    );
+
+    let account = engine.account(1).unwrap();
+    assert_eq!(account.available, Decimal::new(100, 1));
+    assert_eq!(account.held, Decimal::new(0, 0));
+    assert_eq!(account.total, Decimal::new(100, 1));
+    assert!(account.locked);
+}
+
+/// @brief Test that deposit disputes keep their direction under the provisional-credit policy.
+///
+/// Disputing and charging back the 10.0 deposit still removes funds, so with only 6.0
+/// available after the withdrawal the dispute is rejected for insufficient funds.
+#[test]
+fn test_deposit_dispute_unaffected_by_withdrawal_policy() {
+    let mut engine = engine_with_withdrawal(WithdrawalDisputePolicy::ProvisionalCredit);
+
+    assert_eq!(
+        engine.apply(TransactionMessage::new(
+            TransactionType::Dispute,
+            1,
+            1,
+            None
+        )),
+        Err(TransactionError::InsufficientFunds {
+            client: 1,
+            available: Decimal::new(60, 1),
+            requested: Decimal::new(100, 1),
+        })
+    );
+}
+
+/// @brief Test that reusing a transaction ID is rejected and does not credit twice.
+///
+/// A second deposit with the same tx id (even an identical one) must be rejected as a
+/// duplicate when idempotent replays are disabled, leaving both the account balance and
+/// the originally recorded transaction untouched.
+#[test]
+fn test_duplicate_transaction_id_rejected() {
+    let mut engine = Engine::new();
+    let deposit = TransactionMessage::new(TransactionType::Deposit, 1, 1, Some(Decimal::ONE));
+
+    assert_eq!(engine.apply(deposit.clone()), Ok(Outcome::Applied));
+    assert_eq!(
+        engine.apply(deposit),
+        Err(TransactionError::DuplicateTransaction { tx: 1 })
+    );
+    assert_eq!(
+        engine.apply(TransactionMessage::new(
+            TransactionType::Withdrawal,
+            1,
+            1,
+            Some(Decimal::ONE),
+        )),
+        Err(TransactionError::DuplicateTransaction { tx: 1 })
+    );
+
+    let account = engine.account(1).unwrap();
+    assert_eq!(account.available, Decimal::ONE);
+    assert_eq!(account.total, Decimal::ONE);
+}
+
+/// @brief Test that a deposit overflowing the balance is rejected instead of panicking.
+///
+/// Balances are `Decimal`s with 96-bit mantissas; two huge deposits would overflow
+/// `available`, which must be reported as `BalanceOverflow` and leave the account unchanged.
+#[test]
+fn test_balance_overflow_rejected() {
+    let mut engine = Engine::new();
+    let huge = Decimal::MAX / Decimal::TWO + Decimal::ONE;
+    let deposit = |tx| TransactionMessage::new(TransactionType::Deposit, 1, tx, Some(huge));
+
+    assert_eq!(engine.apply(deposit(1)), Ok(Outcome::Applied));
+    assert_eq!(
+        engine.apply(deposit(2)),
+        Err(TransactionError::BalanceOverflow { client: 1, tx: 2 })
+    );
+    assert_eq!(engine.account(1).unwrap().total, huge);
+}
+
+/// @brief Test idempotent replays of already applied transactions.
+///
+/// With `idempotent_replays` enabled, an exact replay (same type, client, tx and amount)
+/// is acknowledged as `Outcome::Replayed` without changing balances, while a message
+/// reusing the id with a different amount or client is still rejected as a duplicate.
+#[test]
+fn test_idempotent_replay_is_noop() {
+    let mut engine = Engine::new().with_config(EngineConfig {
+        idempotent_replays: true,
+        ..Default::default()
+    });
+    let deposit = TransactionMessage::new(TransactionType::Deposit, 1, 1, Some(Decimal::ONE));
+
+    assert_eq!(engine.apply(deposit.clone()), Ok(Outcome::Applied));
+    assert_eq!(engine.apply(deposit), Ok(Outcome::Replayed));
+    assert_eq!(
+        engine.apply(TransactionMessage::new(
+            TransactionType::Deposit,
+            1,
+            1,
+            Some(Decimal::TWO),
+        )),
+        Err(TransactionError::DuplicateTransaction { tx: 1 })
+    );
+    assert_eq!(
+        engine.apply(TransactionMessage::new(
+            TransactionType::Deposit,
+            2,
+            1,
+            Some(Decimal::ONE),
+        )),
+        Err(TransactionError::DuplicateTransaction { tx: 1 })
+    );
+
+    let account = engine.account(1).unwrap();
+    assert_eq!(account.available, Decimal::ONE);
+    assert_eq!(account.total, Decimal::ONE);
+}
+
+/// Helper applying a dispute-related message for client 1 and returning the result.
+fn apply_dispute_step(
+    engine: &mut Engine,
+    tx_type: TransactionType,
+    tx: u32,
+) -> Result<Outcome, TransactionError> {
+    engine.apply(TransactionMessage::new(tx_type, 1, tx, None))
+}
+
+/// @brief Test the dispute lifecycle transitions and their rejections.
+///
+/// Walks a deposit through `Processed -> Disputed -> Resolved -> Disputed -> ChargedBack`
+/// (re-disputes allowed by default) and checks that every out-of-order message is
+/// rejected with the matching error, including any message after the final chargeback.
+#[test]
+fn test_dispute_state_machine() {
+    let mut engine = Engine::new();
+    engine
+        .apply(TransactionMessage::new(
+            TransactionType::Deposit,
+            1,
+            1,
+            Some(Decimal::TEN),
+        ))
+        .unwrap();
+
+    assert_eq!(
+        apply_dispute_step(&mut engine, TransactionType::Resolve, 1),
+        Err(TransactionError::NotDisputed { tx: 1 })
+    );
+    assert_eq!(
+        apply_dispute_step(&mut engine, TransactionType::Dispute, 1),
+        Ok(Outcome::Applied)
+    );
+    assert_eq!(
+        apply_dispute_step(&mut engine, TransactionType::Dispute, 1),
+        Err(TransactionError::AlreadyDisputed { tx: 1 })
+    );
+    assert_eq!(
+        apply_dispute_step(&mut engine, TransactionType::Resolve, 1),
+        Ok(Outcome::Applied)
+    );
+    assert_eq!(
+        apply_dispute_step(&mut engine, TransactionType::Chargeback, 1),
+        Err(TransactionError::NotDisputed { tx: 1 })
+    );
+    assert_eq!(
+        apply_dispute_step(&mut engine, TransactionType::Dispute, 1),
+        Ok(Outcome::Applied)
+    );
+    assert_eq!(
+        apply_dispute_step(&mut engine, TransactionType::Chargeback, 1),
+        Ok(Outcome::Applied)
+    );
+
+    let account = engine.account(1).unwrap();
+    assert_eq!(account.total, Decimal::ZERO);
+    assert!(account.locked);
+}
+
+/// @brief Test that a chargeback moves the transaction into the final `ChargedBack` state.
+///
+/// The account is locked after the chargeback, so a further dispute is rejected for the
+/// lock; the transaction state itself must report `ChargedBack`.
+#[test]
+fn test_charged_back_state_is_final() {
+    let transactions: TransactionsMap = Arc::new(DashMap::new());
+    let mut engine = Engine::with_maps(Arc::new(DashMap::new()), transactions.clone());
+    engine
+        .apply(TransactionMessage::new(
+            TransactionType::Deposit,
+            1,
+            1,
+            Some(Decimal::TEN),
+        ))
+        .unwrap();
+    apply_dispute_step(&mut engine, TransactionType::Dispute, 1).unwrap();
+    apply_dispute_step(&mut engine, TransactionType::Chargeback, 1).unwrap();
+
+    assert_eq!(
+        transactions.get(&1).unwrap().state,
+        TransactionState::ChargedBack
+    );
+    assert_eq!(
+        apply_dispute_step(&mut engine, TransactionType::Dispute, 1),
+        Err(TransactionError::AccountLocked { client: 1 })
+    );
+}
+
+/// @brief Test that re-disputes after resolve can be forbidden.
+#[test]
+fn test_redispute_forbidden() {
+    let mut engine = Engine::new().with_config(EngineConfig {
+        redisputes: RedisputePolicy::Forbid,
+        ..Default::default()
+    });
+    engine
+        .apply(TransactionMessage::new(
+            TransactionType::Deposit,
+            1,
+            1,
+            Some(Decimal::TEN),
+        ))
+        .unwrap();
+
+    apply_dispute_step(&mut engine, TransactionType::Dispute, 1).unwrap();
+    apply_dispute_step(&mut engine, TransactionType::Resolve, 1).unwrap();
+    assert_eq!(
+        apply_dispute_step(&mut engine, TransactionType::Dispute, 1),
+        Err(TransactionError::RedisputeNotAllowed { tx: 1 })
+    );
+
+    let account = engine.account(1).unwrap();
+    assert_eq!(account.available, Decimal::TEN);
+    assert_eq!(account.held, Decimal::ZERO);
+}
+
+/// Helper building a transfer of `amount` tenths from `client` to `destination`.
+fn transfer(client: u16, tx: u32, amount: i64, destination: Option<u16>) -> TransactionMessage {
+    TransactionMessage {
+        destination,
+        ..TransactionMessage::new(
+            TransactionType::Transfer,
+            client,
+            tx,
+            Some(Decimal::new(amount, 1)),
+        )
+    }
+}
+
+/// Helper returning `(available, held, total, locked)` of an existing account.
+fn balances(engine: &Engine, client: u16) -> (Decimal, Decimal, Decimal, bool) {
+    let account = engine.account(client).unwrap();
+    (
+        account.available,
+        account.held,
+        account.total,
+        account.locked,
+    )
+}
+
+/// @brief Test that a transfer debits the sender and credits the destination.
+///
+/// The transfer is recorded with its destination, and a transfer exceeding the sender's
+/// available funds changes neither account.
+#[test]
+fn test_transfer_moves_funds() {
+    let mut engine = Engine::new();
+    let deposit = TransactionMessage::new(TransactionType::Deposit, 1, 1, Some(Decimal::TEN));
+    engine.apply(deposit).unwrap();
+
+    assert_eq!(
+        engine.apply(transfer(1, 2, 40, Some(2))),
+        Ok(Outcome::Applied)
+    );
+    assert_eq!(
+        engine.apply(transfer(1, 3, 70, Some(2))),
+        Err(TransactionError::InsufficientFunds {
+            client: 1,
+            available: Decimal::new(60, 1),
+            requested: Decimal::new(70, 1),
+        })
+    );
+    assert_eq!(
+        engine.apply(transfer(1, 2, 40, Some(2))),
+        Err(TransactionError::DuplicateTransaction { tx: 2 })
+    );
+
+    let four = Decimal::new(40, 1);
+    let six = Decimal::new(60, 1);
+    assert_eq!(balances(&engine, 1), (six, Decimal::ZERO, six, false));
+    assert_eq!(balances(&engine, 2), (four, Decimal::ZERO, four, false));
+}
+
+/// @brief Test that transfers without a valid destination or touching a locked account
+/// are rejected.
+#[test]
+fn test_transfer_rejections() {
+    let mut engine = Engine::new();
+    for (client, tx) in [(1, 1), (3, 2)] {
+        let deposit =
+            TransactionMessage::new(TransactionType::Deposit, client, tx, Some(Decimal::TEN));
+        engine.apply(deposit).unwrap();
+    }
+    // Lock client 3
+    engine
+        .apply(TransactionMessage::new(
+            TransactionType::Dispute,
+            3,
+            2,
+            None,
+        ))
+        .unwrap();
+    engine
+        .apply(TransactionMessage::new(
+            TransactionType::Chargeback,
+            3,
+            2,
+            None,
+        ))
+        .unwrap();
+
+    assert_eq!(
+        engine.apply(transfer(1, 10, 10, None)),
+        Err(TransactionError::MissingDestination { tx: 10 })
+    );
+    assert_eq!(
+        engine.apply(transfer(1, 11, 10, Some(1))),
+        Err(TransactionError::SelfTransfer { tx: 11 })
+    );
+    assert_eq!(
+        engine.apply(transfer(1, 12, 10, Some(3))),
+        Err(TransactionError::AccountLocked { client: 3 })
+    );
+    assert_eq!(
+        engine.apply(transfer(3, 13, 10, Some(1))),
+        Err(TransactionError::AccountLocked { client: 3 })
+    );
+
+    assert_eq!(engine.account(1).unwrap().total, Decimal::TEN);
+    assert_eq!(engine.account(3).unwrap().total, Decimal::ZERO);
+}
+
+/// @brief Test that a disputed transfer is held on, and reversed from, the destination.
+///
+/// The sender disputes the 4.0 transfer: the amount is held on the destination account and
+/// released by a resolve. After a second dispute, the chargeback returns the amount to the
+/// sender and locks the destination account, leaving the sender's account unlocked.
+#[test]
+fn test_transfer_dispute_reverses_both_legs() {
+    let mut engine = Engine::new();
+    let deposit = TransactionMessage::new(TransactionType::Deposit, 1, 1, Some(Decimal::TEN));
+    engine.apply(deposit).unwrap();
+    engine.apply(transfer(1, 2, 40, Some(2))).unwrap();
+    let (zero, four, six) = (Decimal::ZERO, Decimal::new(40, 1), Decimal::new(60, 1));
+
+    assert_eq!(
+        engine.apply(TransactionMessage::new(
+            TransactionType::Dispute,
+            2,
+            2,
+            None
+        )),
+        Err(TransactionError::ClientMismatch {
+            tx: 2,
+            owner: 1,
+            client: 2,
+        })
+    );
+    let dispute = TransactionMessage::new(TransactionType::Dispute, 1, 2, None);
+    assert_eq!(engine.apply(dispute.clone()), Ok(Outcome::Applied));
+    assert_eq!(balances(&engine, 1), (six, zero, six, false));
+    assert_eq!(balances(&engine, 2), (zero, four, four, false));
+
+    let resolve = TransactionMessage::new(TransactionType::Resolve, 1, 2, None);
+    assert_eq!(engine.apply(resolve), Ok(Outcome::Applied));
+    assert_eq!(balances(&engine, 2), (four, zero, four, false));
+
+    assert_eq!(engine.apply(dispute), Ok(Outcome::Applied));
+    let chargeback = TransactionMessage::new(TransactionType::Chargeback, 1, 2, None);
+    assert_eq!(engine.apply(chargeback), Ok(Outcome::Applied));
+    assert_eq!(
+        balances(&engine, 1),
+        (Decimal::TEN, zero, Decimal::TEN, false)
+    );
+    assert_eq!(balances(&engine, 2), (zero, zero, zero, true));
+    assert_eq!(engine.invariant_violation(), None);
+}
+
+/// Helper building a message in `currency`.
+fn in_currency(msg: TransactionMessage, currency: Currency) -> TransactionMessage {
+    TransactionMessage {
+        currency: Some(currency),
+        ..msg
+    }
+}
+
+/// @brief Test that every currency of a client is a separate account.
+///
+/// A USD withdrawal cannot spend EUR funds, messages without a currency use the configured
+/// default, and a chargeback locks only the account in the currency of the transaction.
+#[test]
+fn test_currencies_are_separate_accounts() {
+    let mut engine = Engine::new().with_config(EngineConfig {
+        default_currency: Currency::Pln,
+        ..Default::default()
+    });
+    let deposit = |tx, currency| {
+        let msg = TransactionMessage::new(TransactionType::Deposit, 1, tx, Some(Decimal::TEN));
+        in_currency(msg, currency)
+    };
+    engine.apply(deposit(1, Currency::Eur)).unwrap();
+    engine.apply(deposit(2, Currency::Usd)).unwrap();
+    let plain = TransactionMessage::new(TransactionType::Deposit, 1, 3, Some(Decimal::TWO));
+    engine.apply(plain).unwrap();
+
+    let withdrawal =
+        TransactionMessage::new(TransactionType::Withdrawal, 1, 4, Some(Decimal::new(15, 0)));
+    assert_eq!(
+        engine.apply(in_currency(withdrawal, Currency::Usd)),
+        Err(TransactionError::InsufficientFunds {
+            client: 1,
+            available: Decimal::TEN,
+            requested: Decimal::new(15, 0),
+        })
+    );
+    assert_eq!(engine.account(1).unwrap().total, Decimal::TWO);
+    let dispute = TransactionMessage::new(TransactionType::Dispute, 1, 3, None);
+    assert_eq!(engine.currency_of(&dispute), Currency::Pln);
+
+    engine
+        .apply(TransactionMessage::new(
+            TransactionType::Dispute,
+            1,
+            2,
+            None,
+        ))
+        .unwrap();
+    engine
+        .apply(TransactionMessage::new(
+            TransactionType::Chargeback,
+            1,
+            2,
+            None,
+        ))
+        .unwrap();
+    assert!(engine.account_in(1, Currency::Usd).unwrap().locked);
+    assert_eq!(
+        engine.apply(deposit(5, Currency::Usd)),
+        Err(TransactionError::AccountLocked { client: 1 })
+    );
+    assert_eq!(
+        engine.apply(deposit(5, Currency::Eur)),
+        Ok(Outcome::Applied)
+    );
+
+    let currencies: Vec<_> = engine
+        .accounts()
+        .iter()
+        .map(|summary| (summary.currency, summary.total, summary.locked))
+        .collect();
+    assert_eq!(
+        currencies,
+        vec![
+            (Currency::Eur, Decimal::new(20, 0), false),
+            (Currency::Pln, Decimal::TWO, false),
+            (Currency::Usd, Decimal::ZERO, true),
+        ]
+    );
+}
+
+/// @brief Test that disputes settle in the currency of the disputed transaction.
+///
+/// A dispute without a currency applies in the USD of the deposit even though the default
+/// is EUR; a dispute naming another currency is rejected without touching any account.
+#[test]
+fn test_dispute_uses_transaction_currency() {
+    let mut engine = Engine::new();
+    let deposit = TransactionMessage::new(TransactionType::Deposit, 1, 1, Some(Decimal::TEN));
+    engine.apply(in_currency(deposit, Currency::Usd)).unwrap();
+
+    let dispute = TransactionMessage::new(TransactionType::Dispute, 1, 1, None);
+    assert_eq!(
+        engine.apply(in_currency(dispute.clone(), Currency::Eur)),
+        Err(TransactionError::CurrencyMismatch {
+            tx: 1,
+            expected: Currency::Usd,
+            actual: Currency::Eur,
+        })
+    );
+    assert_eq!(engine.account(1), None);
+
+    assert_eq!(engine.apply(dispute), Ok(Outcome::Applied));
+    let usd = engine.account_in(1, Currency::Usd).unwrap();
+    assert_eq!((usd.available, usd.held), (Decimal::ZERO, Decimal::TEN));
+    let resolve = TransactionMessage::new(TransactionType::Resolve, 1, 1, None);
+    assert_eq!(
+        engine.apply(in_currency(resolve, Currency::Usd)),
+        Ok(Outcome::Applied)
+    );
+    assert_eq!(
+        engine.account_in(1, Currency::Usd).unwrap().held,
+        Decimal::ZERO
+    );
+    assert_eq!(engine.account(1), None);
+    assert_eq!(engine.invariant_violation(), None);
+}
+
+/// Helper building an authorization, capture or void with an optional amount in tenths.
+fn card(tx_type: TransactionType, client: u16, tx: u32, amount: Option<i64>) -> TransactionMessage {
+    TransactionMessage::new(tx_type, client, tx, amount.map(|a| Decimal::new(a, 1)))
+}
+
+/// @brief Test that authorizations hold funds until they are captured or voided.
+///
+/// A partial capture settles the captured amount and releases the rest, a capture without
+/// an amount settles the whole authorization, and a void releases it. A closed
+/// authorization cannot be captured or voided again.
+#[test]
+fn test_authorization_capture_and_void() {
+    let mut engine = Engine::new();
+    let deposit = TransactionMessage::new(TransactionType::Deposit, 1, 1, Some(Decimal::TEN));
+    engine.apply(deposit).unwrap();
+    let d = |tenths| Decimal::new(tenths, 1);
+
+    let authorize = card(TransactionType::Authorize, 1, 2, Some(40));
+    assert_eq!(engine.apply(authorize), Ok(Outcome::Applied));
+    assert_eq!(balances(&engine, 1), (d(60), d(40), d(100), false));
+    let capture = card(TransactionType::Capture, 1, 2, Some(15));
+    assert_eq!(engine.apply(capture), Ok(Outcome::Applied));
+    assert_eq!(balances(&engine, 1), (d(85), d(0), d(85), false));
+    assert_eq!(
+        engine.apply(card(TransactionType::Void, 1, 2, None)),
+        Err(TransactionError::AuthorizationClosed {
+            tx: 2,
+            state: TransactionState::Captured,
+        })
+    );
+
+    engine
+        .apply(card(TransactionType::Authorize, 1, 3, Some(30)))
+        .unwrap();
+    assert_eq!(
+        engine.apply(card(TransactionType::Void, 1, 3, None)),
+        Ok(Outcome::Applied)
+    );
+    assert_eq!(balances(&engine, 1), (d(85), d(0), d(85), false));
+
+    engine
+        .apply(card(TransactionType::Authorize, 1, 4, Some(20)))
+        .unwrap();
+    assert_eq!(
+        engine.apply(card(TransactionType::Capture, 1, 4, None)),
+        Ok(Outcome::Applied)
+    );
+    assert_eq!(balances(&engine, 1), (d(65), d(0), d(65), false));
+    assert_eq!(
+        engine.apply(card(TransactionType::Capture, 1, 3, None)),
+        Err(TransactionError::AuthorizationClosed {
+            tx: 3,
+            state: TransactionState::Voided,
+        })
+    );
+    assert_eq!(engine.invariant_violation(), None);
+}
+
+/// @brief Test that invalid authorizations, captures and voids leave the account untouched.
+#[test]
+fn test_authorization_rejections() {
+    let mut engine = Engine::new();
+    let deposit = TransactionMessage::new(TransactionType::Deposit, 1, 1, Some(Decimal::TEN));
+    engine.apply(deposit).unwrap();
+    engine
+        .apply(card(TransactionType::Authorize, 1, 2, Some(40)))
+        .unwrap();
+    let before = balances(&engine, 1);
+
+    assert_eq!(
+        engine.apply(card(TransactionType::Authorize, 1, 3, Some(70))),
+        Err(TransactionError::InsufficientFunds {
+            client: 1,
+            available: Decimal::new(60, 1),
+            requested: Decimal::new(70, 1),
+        })
+    );
+    assert_eq!(
+        engine.apply(card(TransactionType::Authorize, 1, 2, Some(10))),
+        Err(TransactionError::DuplicateTransaction { tx: 2 })
+    );
+    assert_eq!(
+        engine.apply(card(TransactionType::Capture, 1, 1, None)),
+        Err(TransactionError::NotAnAuthorization {
+            tx: 1,
+            tx_type: TransactionType::Deposit,
+        })
+    );
+    assert_eq!(
+        engine.apply(card(TransactionType::Capture, 1, 2, Some(41))),
+        Err(TransactionError::CaptureExceedsAuthorization {
+            tx: 2,
+            authorized: Decimal::new(40, 1),
+            requested: Decimal::new(41, 1),
+        })
+    );
+    assert_eq!(
+        engine.apply(card(TransactionType::Void, 2, 2, None)),
+        Err(TransactionError::ClientMismatch {
+            tx: 2,
+            owner: 1,
+            client: 2,
+        })
+    );
+    assert_eq!(
+        engine.apply(card(TransactionType::Void, 1, 9, None)),
+        Err(TransactionError::TransactionNotFound { tx: 9 })
+    );
+    assert_eq!(
+        engine.apply(TransactionMessage::new(
+            TransactionType::Dispute,
+            1,
+            2,
+            None
+        )),
+        Err(TransactionError::NotDisputable {
+            tx: 2,
+            tx_type: TransactionType::Authorize,
+        })
+    );
+
+    assert_eq!(balances(&engine, 1), before);
+    assert_eq!(engine.invariant_violation(), None);
+}
+
+/// @brief Test that an authorization expires after the configured number of transactions.
+///
+/// With a TTL of 2 the authorization can still be captured by the client's second
+/// transaction after it; the third finds it expired, its funds released, and the expiry
+/// is reported as a void with `Outcome::Expired`. Transactions of other clients do not
+/// count.
+#[test]
+fn test_authorization_expires_after_ttl() {
+    let mut engine = Engine::new().with_config(EngineConfig {
+        authorization_ttl: Some(2),
+        ..Default::default()
+    });
+    let deposit = |client, tx| {
+        TransactionMessage::new(TransactionType::Deposit, client, tx, Some(Decimal::ONE))
+    };
+    engine.apply(deposit(1, 1)).unwrap();
+    engine
+        .apply(card(TransactionType::Authorize, 1, 2, Some(10)))
+        .unwrap();
+    engine.apply(deposit(2, 3)).unwrap();
+    engine.apply(deposit(1, 4)).unwrap();
+    engine.apply(deposit(1, 5)).unwrap();
+    assert!(engine.expired().is_empty());
+    assert_eq!(engine.account(1).unwrap().held, Decimal::ONE);
+
+    let capture = TransactionMessage {
+        line: Some(7),
+        ..card(TransactionType::Capture, 1, 2, None)
+    };
+    assert_eq!(
+        engine.apply(capture),
+        Err(TransactionError::AuthorizationExpired { tx: 2 })
+    );
+    assert_eq!(balances(&engine, 1).0, Decimal::new(3, 0));
+    assert_eq!(balances(&engine, 1).1, Decimal::ZERO);
+
+    let [expired] = engine.expired() else {
+        panic!("expected one expired authorization: {:?}", engine.expired());
+    };
+    assert_eq!(
+        (
+            &expired.message.tx_type,
+            expired.message.tx,
+            expired.message.line
+        ),
+        (&TransactionType::Void, 2, Some(7))
+    );
+    assert_eq!(expired.result, Ok(Outcome::Expired));
+    assert_eq!(expired.account.as_ref().unwrap().held, Decimal::ZERO);
+    assert_eq!(engine.invariant_violation(), None);
+}
+
+/// @brief Test that an authorization expires once the client's transactions are
+/// timestamped more than the configured window after it.
+///
+/// An authorization without a timestamp is timed from the client's next timestamped
+/// transaction. Messages without a timestamp never expire anything by window.
+#[test]
+fn test_authorization_expires_after_window() {
+    let mut engine = Engine::new().with_config(EngineConfig {
+        authorization_window: Some(60),
+        ..Default::default()
+    });
+    let at = |msg: TransactionMessage, timestamp| TransactionMessage { timestamp, ..msg };
+    let deposit = TransactionMessage::new(TransactionType::Deposit, 1, 1, Some(Decimal::TEN));
+    engine.apply(at(deposit, Some(100))).unwrap();
+    engine
+        .apply(at(
+            card(TransactionType::Authorize, 1, 2, Some(10)),
+            Some(100),
+        ))
+        .unwrap();
+    engine
+        .apply(card(TransactionType::Authorize, 1, 3, Some(10)))
+        .unwrap();
+
+    let withdrawal = |tx, timestamp| {
+        at(
+            TransactionMessage::new(TransactionType::Withdrawal, 1, tx, Some(Decimal::ONE)),
+            timestamp,
+        )
+    };
+    engine.apply(withdrawal(4, None)).unwrap();
+    engine.apply(withdrawal(5, Some(160))).unwrap();
+    assert!(engine.expired().is_empty());
+
+    engine.apply(withdrawal(6, Some(161))).unwrap();
+    let expired: Vec<u32> = engine.expired().iter().map(|p| p.message.tx).collect();
+    assert_eq!(expired, vec![2]);
+
+    assert_eq!(
+        engine.apply(at(card(TransactionType::Capture, 1, 3, None), Some(220))),
+        Ok(Outcome::Applied)
+    );
+    assert_eq!(
+        balances(&engine, 1),
+        (Decimal::new(6, 0), Decimal::ZERO, Decimal::new(6, 0), false)
+    );
+    assert_eq!(engine.invariant_violation(), None);
+}
+
+/// Helper building an administrative message issued by an operator.
+fn admin(
+    tx_type: TransactionType,
+    client: u16,
+    tx: u32,
+    amount: Option<i64>,
+) -> TransactionMessage {
+    TransactionMessage {
+        operator: Some("ops-1".to_owned()),
+        reason: Some("correction".to_owned()),
+        ..TransactionMessage::new(tx_type, client, tx, amount.map(Decimal::from))
+    }
+}
+
+/// @brief Test that an operator can unlock an account frozen by a chargeback, and lock it
+/// again.
+///
+/// Administrative messages are accepted on the locked account while every other message
+/// is rejected; without an operator they are rejected as well.
+#[test]
+fn test_unlock_reopens_charged_back_account() {
+    let mut engine = Engine::new();
+    for msg in [
+        TransactionMessage::new(TransactionType::Deposit, 1, 1, Some(Decimal::TEN)),
+        TransactionMessage::new(TransactionType::Deposit, 1, 2, Some(Decimal::TWO)),
+        TransactionMessage::new(TransactionType::Dispute, 1, 1, None),
+        TransactionMessage::new(TransactionType::Chargeback, 1, 1, None),
+    ] {
+        engine.apply(msg).unwrap();
+    }
+    let deposit = |tx| TransactionMessage::new(TransactionType::Deposit, 1, tx, Some(Decimal::ONE));
+    assert_eq!(
+        engine.apply(deposit(3)),
+        Err(TransactionError::AccountLocked { client: 1 })
+    );
+    assert_eq!(
+        engine.apply(TransactionMessage::new(TransactionType::Unlock, 1, 4, None)),
+        Err(TransactionError::MissingOperator { tx: 4 })
+    );
+    assert!(engine.account(1).unwrap().locked);
+
+    assert_eq!(
+        engine.apply(admin(TransactionType::Unlock, 1, 4, None)),
+        Ok(Outcome::Applied)
+    );
+    assert_eq!(engine.apply(deposit(3)), Ok(Outcome::Applied));
+    assert_eq!(
+        balances(&engine, 1),
+        (Decimal::new(3, 0), Decimal::ZERO, Decimal::new(3, 0), false)
+    );
+
+    assert_eq!(
+        engine.apply(admin(TransactionType::Lock, 1, 5, None)),
+        Ok(Outcome::Applied)
+    );
+    assert_eq!(
+        engine.apply(deposit(6)),
+        Err(TransactionError::AccountLocked { client: 1 })
+    );
+    assert_eq!(engine.invariant_violation(), None);
+}
+
+/// @brief Test that signed adjustments credit or debit available funds, also on a locked
+/// account, and are recorded like deposits for duplicate detection but not disputable.
+#[test]
+fn test_adjustments() {
+    let mut engine = Engine::new();
+    engine
+        .apply(TransactionMessage::new(
+            TransactionType::Deposit,
+            1,
+            1,
+            Some(Decimal::TEN),
+        ))
+        .unwrap();
+    engine
+        .apply(admin(TransactionType::Lock, 1, 2, None))
+        .unwrap();
+
+    assert_eq!(
+        engine.apply(admin(TransactionType::Adjustment, 1, 3, Some(5))),
+        Ok(Outcome::Applied)
+    );
+    assert_eq!(
+        engine.apply(admin(TransactionType::Adjustment, 1, 4, Some(-12))),
+        Ok(Outcome::Applied)
+    );
+    assert_eq!(
+        balances(&engine, 1),
+        (Decimal::new(3, 0), Decimal::ZERO, Decimal::new(3, 0), true)
+    );
+
+    assert_eq!(
+        engine.apply(admin(TransactionType::Adjustment, 1, 5, Some(-4))),
+        Err(TransactionError::InsufficientFunds {
+            client: 1,
+            available: Decimal::new(3, 0),
+            requested: Decimal::new(4, 0),
+        })
+    );
+    assert_eq!(
+        engine.apply(admin(TransactionType::Adjustment, 1, 3, Some(1))),
+        Err(TransactionError::DuplicateTransaction { tx: 3 })
+    );
+    engine
+        .apply(admin(TransactionType::Unlock, 1, 6, None))
+        .unwrap();
+    assert_eq!(
+        engine.apply(TransactionMessage::new(
+            TransactionType::Dispute,
+            1,
+            3,
+            None
+        )),
+        Err(TransactionError::NotDisputable {
+            tx: 3,
+            tx_type: TransactionType::Adjustment,
+        })
+    );
+    assert_eq!(
+        balances(&engine, 1),
+        (Decimal::new(3, 0), Decimal::ZERO, Decimal::new(3, 0), false)
+    );
+    assert_eq!(engine.invariant_violation(), None);
 }
@@ -0,0 +1,205 @@
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use payments_engine::engine::Engine;
+use payments_engine::invariants::{InvariantChecker, check_account};
+use payments_engine::structures::{
+    ClientAccount, ClientsMap, Currency, EngineConfig, Outcome, TransactionMessage,
+    TransactionRecord, TransactionState, TransactionType, TransactionsMap,
+};
+use rust_decimal::Decimal;
+
+/// @brief Helper building an account from amounts given in whole units.
+fn account(available: i64, held: i64, total: i64) -> ClientAccount {
+    ClientAccount {
+        available: Decimal::from(available),
+        held: Decimal::from(held),
+        total: Decimal::from(total),
+        locked: false,
+    }
+}
+
+/// @brief `check_account` requires `total == available + held` and `held == disputed`.
+#[test]
+fn test_check_account() {
+    assert_eq!(check_account(&account(3, 2, 5), Decimal::from(2)), Ok(()));
+
+    let err = check_account(&account(3, 2, 6), Decimal::from(2)).unwrap_err();
+    assert!(err.contains("total 6 != available 3 + held 2"), "{err}");
+
+    let err = check_account(&account(3, 2, 5), Decimal::ZERO).unwrap_err();
+    assert!(err.contains("held 2 != 0 disputed"), "{err}");
+}
+
+/// @brief The checker reports the first message after which an account is inconsistent.
+///
+/// Disputed records already in the store (e.g. restored from a snapshot) count towards
+/// the expected held amount.
+#[test]
+fn test_checker_reports_first_violation() {
+    let clients: ClientsMap = Arc::new(DashMap::new());
+    let transactions: TransactionsMap = Arc::new(DashMap::new());
+    transactions.insert(
+        1,
+        TransactionRecord {
+            client_id: 1,
+            amount: Decimal::from(2),
+            state: TransactionState::Disputed,
+            tx_type: TransactionType::Deposit,
+            destination: None,
+            currency: Currency::Eur,
+        },
+    );
+    clients.insert((1, Currency::Eur), account(3, 2, 5));
+    clients.insert((2, Currency::Eur), account(1, 0, 2));
+
+    let mut checker = InvariantChecker::new();
+    let deposit = TransactionMessage::new(TransactionType::Deposit, 1, 2, Some(Decimal::ONE));
+    assert_eq!(
+        checker.check(&clients, &transactions, &deposit, Currency::Eur),
+        Ok(())
+    );
+    assert_eq!(checker.first_violation(), None);
+
+    let broken = TransactionMessage {
+        line: Some(7),
+        ..TransactionMessage::new(TransactionType::Deposit, 2, 3, Some(Decimal::ONE))
+    };
+    let violation = checker
+        .check(&clients, &transactions, &broken, Currency::Eur)
+        .unwrap_err();
+    assert_eq!(
+        (violation.client, violation.tx, violation.line),
+        (2, 3, Some(7))
+    );
+    assert_eq!(
+        violation.to_string(),
+        "account 2 (EUR) violates an invariant after transaction 3 (line 7): total 2 != available 1 + held 0"
+    );
+
+    // Later violations are returned but the first one is kept
+    let later = TransactionMessage::new(TransactionType::Deposit, 2, 4, Some(Decimal::ONE));
+    assert!(
+        checker
+            .check(&clients, &transactions, &later, Currency::Eur)
+            .is_err()
+    );
+    assert_eq!(checker.first_violation(), Some(&violation));
+
+    // Resolving the restored dispute releases the held amount
+    clients.insert((1, Currency::Eur), account(5, 0, 5));
+    transactions.alter(&1, |_, record| TransactionRecord {
+        state: TransactionState::Resolved,
+        ..record
+    });
+    let resolve = TransactionMessage::new(TransactionType::Resolve, 1, 1, None);
+    assert_eq!(
+        checker.check(&clients, &transactions, &resolve, Currency::Eur),
+        Ok(())
+    );
+}
+
+/// @brief Checkers of engines sharing their stores see the disputes the other one applies
+/// once they share the held-funds index, without rescanning the store.
+#[test]
+fn test_checkers_share_held_index() {
+    let clients: ClientsMap = Arc::new(DashMap::new());
+    let transactions: TransactionsMap = Arc::new(DashMap::new());
+    transactions.insert(
+        1,
+        TransactionRecord {
+            client_id: 1,
+            amount: Decimal::from(5),
+            state: TransactionState::Processed,
+            tx_type: TransactionType::Transfer,
+            destination: Some(2),
+            currency: Currency::Eur,
+        },
+    );
+    clients.insert((1, Currency::Eur), account(0, 0, 0));
+    clients.insert((2, Currency::Eur), account(5, 0, 5));
+
+    let mut owner = InvariantChecker::new();
+    let mut shared = InvariantChecker::new();
+    shared.share_index(&owner);
+    let mut separate = InvariantChecker::new();
+    let deposit = TransactionMessage::new(TransactionType::Deposit, 2, 2, Some(Decimal::ONE));
+    for checker in [&mut owner, &mut shared, &mut separate] {
+        assert_eq!(
+            checker.check(&clients, &transactions, &deposit, Currency::Eur),
+            Ok(())
+        );
+    }
+
+    // The owner's shard disputes the transfer, holding the funds of its destination
+    transactions.alter(&1, |_, record| TransactionRecord {
+        state: TransactionState::Disputed,
+        ..record
+    });
+    clients.insert((2, Currency::Eur), account(0, 5, 5));
+    let dispute = TransactionMessage::new(TransactionType::Dispute, 1, 1, None);
+    assert_eq!(
+        owner.check(&clients, &transactions, &dispute, Currency::Eur),
+        Ok(())
+    );
+
+    let later = TransactionMessage::new(TransactionType::Deposit, 2, 3, Some(Decimal::ONE));
+    assert_eq!(
+        shared.check(&clients, &transactions, &later, Currency::Eur),
+        Ok(())
+    );
+    let violation = separate
+        .check(&clients, &transactions, &later, Currency::Eur)
+        .unwrap_err();
+    assert_eq!(violation.detail, "held 5 != 0 disputed");
+}
+
+/// @brief A full dispute lifecycle passes the checks, which are on by default in tests.
+#[test]
+fn test_engine_checks_dispute_lifecycle() {
+    let mut engine = Engine::new();
+    let amount = Some(Decimal::new(15, 1));
+    for msg in [
+        TransactionMessage::new(TransactionType::Deposit, 1, 1, amount),
+        TransactionMessage::new(TransactionType::Deposit, 1, 2, amount),
+        TransactionMessage::new(TransactionType::Dispute, 1, 1, None),
+        TransactionMessage::new(TransactionType::Dispute, 1, 2, None),
+        TransactionMessage::new(TransactionType::Resolve, 1, 1, None),
+        TransactionMessage::new(TransactionType::Chargeback, 1, 2, None),
+    ] {
+        assert_eq!(engine.apply(msg), Ok(Outcome::Applied));
+    }
+    assert_eq!(engine.invariant_violation(), None);
+}
+
+/// @brief In debug builds, an applied message leaving an inconsistent account panics.
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "invariant violated")]
+fn test_engine_panics_on_violation_in_debug() {
+    let clients: ClientsMap = Arc::new(DashMap::new());
+    clients.insert((1, Currency::Eur), account(1, 1, 1));
+    let mut engine = Engine::with_maps(clients, Arc::new(DashMap::new()));
+
+    let deposit = TransactionMessage::new(TransactionType::Deposit, 1, 1, Some(Decimal::ONE));
+    let _ = engine.apply(deposit);
+}
+
+/// @brief Without `panic_on_violation`, a violation is only recorded for the caller.
+#[test]
+fn test_engine_records_violation_without_panicking() {
+    let clients: ClientsMap = Arc::new(DashMap::new());
+    clients.insert((1, Currency::Eur), account(1, 1, 1));
+    let mut engine =
+        Engine::with_maps(clients, Arc::new(DashMap::new())).with_config(EngineConfig {
+            check_invariants: true,
+            panic_on_violation: false,
+            ..EngineConfig::default()
+        });
+
+    let mut deposit = TransactionMessage::new(TransactionType::Deposit, 1, 1, Some(Decimal::ONE));
+    deposit.line = Some(2);
+    assert_eq!(engine.apply(deposit), Ok(Outcome::Applied));
+    let violation = engine.invariant_violation().expect("violation recorded");
+    assert_eq!((violation.client, violation.line), (1, Some(2)));
+}
@@ -0,0 +1,557 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use payments_engine::engine::Engine;
+use payments_engine::structures::{
+    Currency, EngineConfig, Outcome, RedisputePolicy, TransactionMessage, TransactionType,
+    ValidationMode, WithdrawalDisputePolicy,
+};
+use proptest::prelude::*;
+use rust_decimal::Decimal;
+
+/// Transactions of a generated test case, shown as the equivalent input CSV.
+///
+/// proptest prints the shrunk, minimal failing case with `Debug`, so a failure reads as a
+/// CSV file that can be fed to the binary directly.
+#[derive(Clone)]
+struct Csv(Vec<TransactionMessage>);
+
+impl fmt::Debug for Csv {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "\ntype,client,tx,amount,destination,currency,operator,reason"
+        )?;
+        for msg in &self.0 {
+            let amount = msg.amount.map(|a| a.to_string()).unwrap_or_default();
+            let destination = msg.destination.map(|d| d.to_string()).unwrap_or_default();
+            let currency = msg.currency.map(|c| c.to_string()).unwrap_or_default();
+            let operator = msg.operator.as_deref().unwrap_or_default();
+            let reason = msg.reason.as_deref().unwrap_or_default();
+            writeln!(
+                f,
+                "{},{},{},{},{},{},{},{}",
+                msg.tx_type, msg.client, msg.tx, amount, destination, currency, operator, reason
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Lifecycle state of a transaction in the reference model.
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Processed,
+    Disputed,
+    Resolved,
+    ChargedBack,
+    Closed,
+}
+
+/// A recorded deposit, withdrawal, transfer, authorization or adjustment in the reference
+/// model.
+struct Record {
+    client: u16,
+    amount: Decimal,
+    tx_type: TransactionType,
+    destination: Option<u16>,
+    currency: Currency,
+    state: State,
+}
+
+/// Account balances in the reference model; `total` is always `available + held`.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Balance {
+    available: Decimal,
+    held: Decimal,
+    locked: bool,
+}
+
+/// Balances of every account, keyed by client and currency.
+type Accounts = BTreeMap<(u16, Currency), Balance>;
+
+/// Straightforward model of the engine's rules under `config`: every rejected message
+/// leaves balances untouched, deposits and transfers (and withdrawals with provisional
+/// credit) can be disputed, a disputed transfer is held on its destination account,
+/// authorizations are held until captured (at most the authorized amount) or voided,
+/// disputes, captures and voids apply in the currency of the referenced transaction, and
+/// administrative locks, unlocks and adjustments also apply to locked accounts. Each
+/// message yields `None` if rejected, like `Engine::apply(..).ok()`.
+struct Model {
+    config: EngineConfig,
+    accounts: Accounts,
+    records: HashMap<u32, Record>,
+}
+
+impl Model {
+    fn new(config: EngineConfig) -> Self {
+        Self {
+            config,
+            accounts: Accounts::new(),
+            records: HashMap::new(),
+        }
+    }
+
+    fn apply(&mut self, msg: &TransactionMessage) -> Option<Outcome> {
+        // Invalid messages are rejected before any account is opened
+        let msg = validate(msg.clone(), self.config.validation)?;
+        let currency = self.currency(&msg)?;
+        // Accounts are opened by any valid message, even a rejected one
+        if self.account(msg.client, currency).locked && !msg.tx_type.is_admin() {
+            return None;
+        }
+        if creates_record(&msg.tx_type)
+            && let Some(record) = self.records.get(&msg.tx)
+        {
+            return self.replay(record, &msg, currency);
+        }
+
+        match msg.tx_type {
+            TransactionType::Deposit | TransactionType::Withdrawal | TransactionType::Transfer => {
+                self.payment(&msg, currency)
+            }
+            TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback => {
+                self.dispute(&msg, currency)
+            }
+            TransactionType::Authorize | TransactionType::Capture | TransactionType::Void => {
+                self.authorization(&msg, currency)
+            }
+            TransactionType::Lock | TransactionType::Unlock | TransactionType::Adjustment => {
+                self.admin(&msg, currency)
+            }
+        }
+    }
+
+    /// Currency of `msg`: that of the referenced transaction, or `None` if it names another.
+    fn currency(&self, msg: &TransactionMessage) -> Option<Currency> {
+        let references = matches!(
+            msg.tx_type,
+            TransactionType::Dispute
+                | TransactionType::Resolve
+                | TransactionType::Chargeback
+                | TransactionType::Capture
+                | TransactionType::Void
+        );
+        match self.records.get(&msg.tx) {
+            Some(record) if references && record.client == msg.client => match msg.currency {
+                Some(currency) if currency != record.currency => None,
+                _ => Some(record.currency),
+            },
+            _ => Some(msg.currency.unwrap_or_default()),
+        }
+    }
+
+    fn account(&mut self, client: u16, currency: Currency) -> &mut Balance {
+        self.accounts.entry((client, currency)).or_default()
+    }
+
+    /// A reused tx id is only acknowledged as an exact replay with idempotent replays.
+    fn replay(
+        &self,
+        record: &Record,
+        msg: &TransactionMessage,
+        currency: Currency,
+    ) -> Option<Outcome> {
+        let exact = record.tx_type == msg.tx_type
+            && record.client == msg.client
+            && Some(record.amount) == msg.amount
+            && record.destination == msg.destination
+            && record.currency == currency;
+        (self.config.idempotent_replays && exact).then_some(Outcome::Replayed)
+    }
+
+    fn record(&mut self, msg: &TransactionMessage, amount: Decimal, currency: Currency) {
+        self.records.insert(
+            msg.tx,
+            Record {
+                client: msg.client,
+                amount,
+                tx_type: msg.tx_type.clone(),
+                destination: msg.destination,
+                currency,
+                state: State::Processed,
+            },
+        );
+    }
+
+    fn payment(&mut self, msg: &TransactionMessage, currency: Currency) -> Option<Outcome> {
+        let amount = msg.amount.expect("generated with an amount");
+        if let Some(destination) = msg.destination
+            && self.account(destination, currency).locked
+        {
+            return None;
+        }
+        let account = self.account(msg.client, currency);
+        if msg.tx_type == TransactionType::Deposit {
+            account.available += amount;
+        } else if account.available >= amount {
+            account.available -= amount;
+        } else {
+            return None;
+        }
+        if let Some(destination) = msg.destination {
+            self.account(destination, currency).available += amount;
+        }
+        self.record(msg, amount, currency);
+        Some(Outcome::Applied)
+    }
+
+    /// State a dispute, resolve or chargeback moves a transaction in `state` to.
+    fn next_state(&self, tx_type: &TransactionType, state: State) -> Option<State> {
+        match (tx_type, state) {
+            (TransactionType::Dispute, State::Processed) => Some(State::Disputed),
+            (TransactionType::Dispute, State::Resolved) => {
+                (self.config.redisputes == RedisputePolicy::Allow).then_some(State::Disputed)
+            }
+            (TransactionType::Resolve, State::Disputed) => Some(State::Resolved),
+            (TransactionType::Chargeback, State::Disputed) => Some(State::ChargedBack),
+            _ => None,
+        }
+    }
+
+    fn dispute(&mut self, msg: &TransactionMessage, currency: Currency) -> Option<Outcome> {
+        let record = self
+            .records
+            .get(&msg.tx)
+            .filter(|r| r.client == msg.client)?;
+        let next = self.next_state(&msg.tx_type, record.state)?;
+        let (tx_type, destination, amount) =
+            (record.tx_type.clone(), record.destination, record.amount);
+        match tx_type {
+            TransactionType::Deposit | TransactionType::Transfer => {
+                // A transfer's amount is held on, and charged back from, its destination
+                let holder = destination.unwrap_or(msg.client);
+                self.hold(msg, holder, amount, currency)?;
+            }
+            TransactionType::Withdrawal
+                if self.config.withdrawal_disputes
+                    == WithdrawalDisputePolicy::ProvisionalCredit =>
+            {
+                self.provisional_credit(msg, amount, currency);
+            }
+            _ => return None,
+        }
+        self.records.get_mut(&msg.tx).unwrap().state = next;
+        Some(Outcome::Applied)
+    }
+
+    /// Holds, releases or charges back a deposit or transfer held by `holder`.
+    fn hold(
+        &mut self,
+        msg: &TransactionMessage,
+        holder: u16,
+        amount: Decimal,
+        currency: Currency,
+    ) -> Option<()> {
+        let account = self.account(holder, currency);
+        if account.locked {
+            return None;
+        }
+        match msg.tx_type {
+            TransactionType::Dispute if account.available >= amount => {
+                account.available -= amount;
+                account.held += amount;
+            }
+            TransactionType::Dispute => return None,
+            TransactionType::Resolve => {
+                account.held -= amount;
+                account.available += amount;
+            }
+            _ => {
+                account.held -= amount;
+                account.locked = true;
+                if holder != msg.client {
+                    self.account(msg.client, currency).available += amount;
+                }
+            }
+        }
+        Some(())
+    }
+
+    /// Credits a disputed withdrawal as held funds, reverses the credit on resolve and
+    /// makes it available on chargeback.
+    fn provisional_credit(
+        &mut self,
+        msg: &TransactionMessage,
+        amount: Decimal,
+        currency: Currency,
+    ) {
+        let account = self.account(msg.client, currency);
+        match msg.tx_type {
+            TransactionType::Dispute => account.held += amount,
+            TransactionType::Resolve => account.held -= amount,
+            _ => {
+                account.held -= amount;
+                account.available += amount;
+                account.locked = true;
+            }
+        }
+    }
+
+    fn authorization(&mut self, msg: &TransactionMessage, currency: Currency) -> Option<Outcome> {
+        if msg.tx_type == TransactionType::Authorize {
+            let amount = msg.amount.expect("generated with an amount");
+            let account = self.account(msg.client, currency);
+            if account.available < amount {
+                return None;
+            }
+            account.available -= amount;
+            account.held += amount;
+            self.record(msg, amount, currency);
+            return Some(Outcome::Applied);
+        }
+
+        let record = self.records.get_mut(&msg.tx)?;
+        if record.client != msg.client
+            || record.tx_type != TransactionType::Authorize
+            || record.state != State::Processed
+        {
+            return None;
+        }
+        let captured = match msg.tx_type {
+            TransactionType::Capture => msg.amount.unwrap_or(record.amount),
+            _ => Decimal::ZERO,
+        };
+        if captured > record.amount {
+            return None;
+        }
+        record.state = State::Closed;
+        let authorized = record.amount;
+        let account = self.account(msg.client, currency);
+        account.held -= authorized;
+        account.available += authorized - captured;
+        Some(Outcome::Applied)
+    }
+
+    fn admin(&mut self, msg: &TransactionMessage, currency: Currency) -> Option<Outcome> {
+        let account = self.account(msg.client, currency);
+        if msg.tx_type != TransactionType::Adjustment {
+            account.locked = msg.tx_type == TransactionType::Lock;
+            return Some(Outcome::Applied);
+        }
+        let amount = msg.amount.expect("generated with an amount");
+        if account.available + amount < Decimal::ZERO {
+            return None;
+        }
+        account.available += amount;
+        self.record(msg, amount, currency);
+        Some(Outcome::Applied)
+    }
+}
+
+/// Applies the validation rules of `mode` to `msg`, returning `None` if it is rejected.
+///
+/// Only adjustments are generated with a negative amount; their magnitude is validated.
+fn validate(mut msg: TransactionMessage, mode: ValidationMode) -> Option<TransactionMessage> {
+    let strict = mode == ValidationMode::Strict;
+    if msg.tx_type == TransactionType::Transfer && msg.destination == Some(msg.client) {
+        return None;
+    }
+    if creates_record(&msg.tx_type) || msg.tx_type == TransactionType::Capture {
+        if let Some(amount) = msg.amount {
+            let magnitude = amount.abs();
+            let rounded = magnitude.round_dp(4);
+            if strict && (rounded != magnitude || rounded.is_zero()) {
+                return None;
+            }
+            msg.amount = Some(if amount.is_sign_negative() {
+                -rounded
+            } else {
+                rounded
+            });
+        }
+    } else if msg.amount.is_some() {
+        if strict {
+            return None;
+        }
+        msg.amount = None;
+    }
+    Some(msg)
+}
+
+/// Returns whether a message of `tx_type` records a transaction under its tx id.
+fn creates_record(tx_type: &TransactionType) -> bool {
+    matches!(
+        tx_type,
+        TransactionType::Deposit
+            | TransactionType::Withdrawal
+            | TransactionType::Transfer
+            | TransactionType::Authorize
+            | TransactionType::Adjustment
+    )
+}
+
+/// Applies `messages` to an engine configured with `config` and returns the result of each
+/// message and the resulting accounts.
+fn run_engine(
+    config: &EngineConfig,
+    messages: &[TransactionMessage],
+) -> (Vec<Option<Outcome>>, Accounts) {
+    let mut engine = Engine::new().with_config(config.clone());
+    let outcomes = messages
+        .iter()
+        .map(|msg| engine.apply(msg.clone()).ok())
+        .collect();
+
+    let accounts = engine
+        .accounts()
+        .into_iter()
+        .map(|summary| {
+            assert_eq!(summary.total, summary.available + summary.held);
+            (
+                (summary.client, summary.currency),
+                Balance {
+                    available: summary.available,
+                    held: summary.held,
+                    locked: summary.locked,
+                },
+            )
+        })
+        .collect();
+    (outcomes, accounts)
+}
+
+/// Strategy for the rules the engine and the model follow.
+fn config() -> impl Strategy<Value = EngineConfig> {
+    let withdrawal_disputes = prop_oneof![
+        Just(WithdrawalDisputePolicy::Reject),
+        Just(WithdrawalDisputePolicy::ProvisionalCredit),
+    ];
+    let redisputes = prop_oneof![Just(RedisputePolicy::Allow), Just(RedisputePolicy::Forbid)];
+    let validation = prop_oneof![Just(ValidationMode::Lenient), Just(ValidationMode::Strict)];
+    (withdrawal_disputes, redisputes, validation, any::<bool>()).prop_map(
+        |(withdrawal_disputes, redisputes, validation, idempotent_replays)| EngineConfig {
+            withdrawal_disputes,
+            redisputes,
+            validation,
+            idempotent_replays,
+            ..EngineConfig::default()
+        },
+    )
+}
+
+/// Strategy for a test case: random messages, some of them repeated later on, as an
+/// upstream retry would, and a few disputes of a transaction after its dispute was resolved.
+fn messages() -> impl Strategy<Value = Csv> {
+    let messages = prop::collection::vec(
+        prop_oneof![19 => message().prop_map(|msg| vec![msg]), 1 => redispute()],
+        0..60,
+    )
+    .prop_map(|chunks| chunks.concat());
+    let replays = prop::collection::vec(any::<(prop::sample::Index, prop::sample::Index)>(), 0..8);
+    (messages, replays).prop_map(|(mut messages, replays)| {
+        for (original, offset) in replays {
+            if messages.is_empty() {
+                break;
+            }
+            let original = original.index(messages.len());
+            let at = original + 1 + offset.index(messages.len() - original);
+            messages.insert(at, messages[original].clone());
+        }
+        Csv(messages)
+    })
+}
+
+/// Strategy for a dispute, resolve and second dispute of one transaction, a sequence
+/// independent messages rarely form.
+fn redispute() -> impl Strategy<Value = Vec<TransactionMessage>> {
+    (1u16..=3, 1u32..=12).prop_map(|(client, tx)| {
+        [
+            TransactionType::Dispute,
+            TransactionType::Resolve,
+            TransactionType::Dispute,
+        ]
+        .map(|tx_type| TransactionMessage::new(tx_type, client, tx, None))
+        .to_vec()
+    })
+}
+
+/// Strategy for a single message over few clients, tx ids and currencies, so that disputes
+/// often hit existing transactions, other clients' transactions, duplicates and
+/// transactions in another currency.
+fn message() -> impl Strategy<Value = TransactionMessage> {
+    let currency = prop_oneof![
+        2 => Just(None),
+        1 => Just(Some(Currency::Eur)),
+        1 => Just(Some(Currency::Usd)),
+    ];
+    (kind(), currency).prop_map(|(msg, currency)| TransactionMessage { currency, ..msg })
+}
+
+/// Strategy for the type, client, tx id, amount and destination of a message.
+fn kind() -> impl Strategy<Value = TransactionMessage> {
+    // Zero and over-precise amounts exercise the validation modes
+    let amount = prop_oneof![
+        19 => (1i64..=50_000, 0u32..=6).prop_map(|(mantissa, scale)| Decimal::new(mantissa, scale)),
+        1 => Just(Decimal::ZERO),
+    ];
+    // Amounts on messages that take none are dropped or rejected
+    let stray = prop::option::weighted(0.1, amount.clone());
+    let client = 1u16..=3;
+    let tx = 1u32..=12;
+    prop_oneof![
+        3 => (client.clone(), tx.clone(), amount.clone()).prop_map(|(client, tx, amount)| {
+            TransactionMessage::new(TransactionType::Deposit, client, tx, Some(amount))
+        }),
+        2 => (client.clone(), tx.clone(), amount.clone()).prop_map(|(client, tx, amount)| {
+            TransactionMessage::new(TransactionType::Withdrawal, client, tx, Some(amount))
+        }),
+        2 => (client.clone(), tx.clone(), stray.clone()).prop_map(|(client, tx, amount)| {
+            TransactionMessage::new(TransactionType::Dispute, client, tx, amount)
+        }),
+        1 => (client.clone(), tx.clone(), stray.clone()).prop_map(|(client, tx, amount)| {
+            TransactionMessage::new(TransactionType::Resolve, client, tx, amount)
+        }),
+        1 => (client.clone(), tx.clone(), stray.clone()).prop_map(|(client, tx, amount)| {
+            TransactionMessage::new(TransactionType::Chargeback, client, tx, amount)
+        }),
+        2 => (client.clone(), tx.clone(), amount.clone()).prop_map(|(client, tx, amount)| {
+            TransactionMessage::new(TransactionType::Authorize, client, tx, Some(amount))
+        }),
+        1 => (client.clone(), tx.clone(), prop::option::of(amount.clone())).prop_map(
+            |(client, tx, amount)| TransactionMessage::new(TransactionType::Capture, client, tx, amount)
+        ),
+        1 => (client.clone(), tx.clone(), stray.clone()).prop_map(|(client, tx, amount)| {
+            TransactionMessage::new(TransactionType::Void, client, tx, amount)
+        }),
+        1 => (client.clone(), any::<bool>()).prop_map(|(client, lock)| {
+            let tx_type = if lock { TransactionType::Lock } else { TransactionType::Unlock };
+            admin(TransactionMessage::new(tx_type, client, 0, None))
+        }),
+        1 => (client.clone(), tx.clone(), amount.clone(), any::<bool>()).prop_map(
+            |(client, tx, amount, debit)| {
+                let amount = if debit { -amount } else { amount };
+                admin(TransactionMessage {
+                    reason: Some("correction".to_owned()),
+                    ..TransactionMessage::new(TransactionType::Adjustment, client, tx, Some(amount))
+                })
+            }
+        ),
+        2 => (client.clone(), tx, amount, client).prop_map(|(client, tx, amount, destination)| {
+            TransactionMessage {
+                destination: Some(destination),
+                ..TransactionMessage::new(TransactionType::Transfer, client, tx, Some(amount))
+            }
+        }),
+    ]
+}
+
+/// Sets the operator of an administrative message.
+fn admin(msg: TransactionMessage) -> TransactionMessage {
+    TransactionMessage {
+        operator: Some("ops-1".to_owned()),
+        ..msg
+    }
+}
+
+proptest! {
+    /// @brief The engine's result for every message and its final accounts match the
+    /// reference model for random inputs and configurations.
+    #[test]
+    fn test_engine_matches_model(config in config(), csv in messages()) {
+        let mut model = Model::new(config.clone());
+        let outcomes: Vec<_> = csv.0.iter().map(|msg| model.apply(msg)).collect();
+
+        prop_assert_eq!(run_engine(&config, &csv.0), (outcomes, model.accounts));
+    }
+}
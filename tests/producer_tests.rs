@@ -1,9 +1,14 @@
-use payments_engine::producer::process_file;
-use payments_engine::structures::{Args, TransactionType};
+use async_compression::tokio::write::{GzipEncoder, ZstdEncoder};
+use clap::Parser;
+use payments_engine::producer::{Compression, process_file, process_file_from};
+use payments_engine::structures::{
+    Args, Currency, PARSE_ERROR, RejectedRecord, ResumePoints, TransactionMessage, TransactionType,
+};
 use rust_decimal::Decimal;
 use std::io::Write;
-use tempfile::NamedTempFile;
-use tokio::io::{self};
+use std::path::Path;
+use tempfile::{NamedTempFile, TempDir};
+use tokio::io::{self, AsyncWrite, AsyncWriteExt};
 use tokio::sync::mpsc;
 
 /// Basic integration test for the `process_file` function.
@@ -13,7 +18,7 @@ use tokio::sync::mpsc;
 /// collects all transaction messages sent over the channel.
 ///
 /// The test verifies:
-/// - That the final message is a termination signal (`TransactionType::Terminate`).
+/// - That exactly the two transactions are sent and the channel is then closed.
 /// - That the first transaction corresponds to a deposit with the expected client ID,
 ///   transaction ID, type, and amount.
 /// - That the second transaction corresponds to a withdrawal with the expected details.
@@ -35,9 +40,7 @@ async fn test_process_file_basic() -> io::Result<()> {
 
     let (tx, mut rx) = mpsc::channel(10);
 
-    let args = Args {
-        input_file: tmpfile.path().to_str().unwrap().to_string(),
-    };
+    let args = Args::parse_from(["payments_engine", tmpfile.path().to_str().unwrap()]);
 
     process_file(args, tx).await?;
 
@@ -46,20 +49,492 @@ async fn test_process_file_basic() -> io::Result<()> {
         received_messages.push(msg);
     }
 
-    assert!(matches!(
-        received_messages.last().unwrap().tx_type,
-        TransactionType::Terminate
-    ));
+    // End of input is signalled by closing the channel, not by a sentinel message
+    assert_eq!(received_messages.len(), 2);
 
     assert_eq!(received_messages[0].client, 1);
     assert_eq!(received_messages[0].tx, 1);
     assert_eq!(received_messages[0].tx_type, TransactionType::Deposit);
     assert_eq!(received_messages[0].amount.unwrap(), Decimal::new(10, 1)); // 1.0
+    assert_eq!(received_messages[0].line, Some(2)); // header is line 1
 
     assert_eq!(received_messages[1].client, 1);
     assert_eq!(received_messages[1].tx, 2);
     assert_eq!(received_messages[1].tx_type, TransactionType::Withdrawal);
     assert_eq!(received_messages[1].amount.unwrap(), Decimal::new(5, 1)); // 0.5
+    assert_eq!(received_messages[1].line, Some(3));
+
+    Ok(())
+}
+
+/// @brief Helper writing a CSV file with the given lines.
+fn write_csv(lines: &[&str]) -> io::Result<NamedTempFile> {
+    let mut file = NamedTempFile::new()?;
+    for line in lines {
+        writeln!(file, "{line}")?;
+    }
+    file.flush()?;
+    Ok(file)
+}
+
+/// @brief Multiple inputs are processed one after another in the given order.
+///
+/// Every message is tagged with the index of its input and its line within that input.
+#[tokio::test]
+async fn test_process_file_multiple_inputs_in_order() -> io::Result<()> {
+    let first = write_csv(&[
+        "type,client,tx,amount",
+        "deposit,1,1,1.0",
+        "deposit,2,2,2.0",
+    ])?;
+    let second = write_csv(&["type,client,tx,amount", "withdrawal,1,3,0.5"])?;
+
+    let (tx, mut rx) = mpsc::channel(10);
+    let args = Args::parse_from([
+        "payments_engine",
+        first.path().to_str().unwrap(),
+        second.path().to_str().unwrap(),
+    ]);
+    process_file(args, tx).await?;
+
+    let mut received = Vec::new();
+    while let Some(msg) = rx.recv().await {
+        received.push((msg.tx, msg.source, msg.line));
+    }
+    assert_eq!(
+        received,
+        vec![(1, 0, Some(2)), (2, 0, Some(3)), (3, 1, Some(2))]
+    );
+    Ok(())
+}
+
+/// @brief With `--merge-by`, rows of all inputs are interleaved by the timestamp column.
+///
+/// Numeric timestamps compare as numbers (`9` before `10`), and ties go to the earlier input.
+#[tokio::test]
+async fn test_process_file_merge_by_timestamp() -> io::Result<()> {
+    let first = write_csv(&[
+        "type,client,tx,amount,ts",
+        "deposit,1,1,1.0,1",
+        "deposit,1,3,1.0,10",
+    ])?;
+    let second = write_csv(&[
+        "ts,type,client,tx,amount",
+        "2,deposit,2,2,1.0",
+        "9,deposit,2,4,1.0",
+        "10,deposit,2,5,1.0",
+    ])?;
+
+    let (tx, mut rx) = mpsc::channel(10);
+    let args = Args::parse_from([
+        "payments_engine",
+        first.path().to_str().unwrap(),
+        second.path().to_str().unwrap(),
+        "--merge-by",
+        "ts",
+    ]);
+    process_file(args, tx).await?;
+
+    let mut received = Vec::new();
+    while let Some(msg) = rx.recv().await {
+        received.push(msg.tx);
+    }
+    assert_eq!(received, vec![1, 2, 4, 3, 5]);
+    Ok(())
+}
+
+/// @brief Merging fails if an input lacks the timestamp column.
+#[tokio::test]
+async fn test_process_file_merge_by_missing_column() -> io::Result<()> {
+    let input = write_csv(&["type,client,tx,amount", "deposit,1,1,1.0"])?;
+
+    let (tx, _rx) = mpsc::channel(10);
+    let args = Args::parse_from([
+        "payments_engine",
+        input.path().to_str().unwrap(),
+        "--merge-by",
+        "ts",
+    ]);
+    let err = process_file(args, tx).await.unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    Ok(())
+}
+
+/// @brief `-` reads the input from standard input.
+#[test]
+fn test_binary_reads_stdin() -> std::io::Result<()> {
+    use std::process::{Command, Stdio};
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_payments_engine"))
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"type,client,tx,amount\ndeposit,1,1,1.5\n")?;
+    let output = child.wait_with_output()?;
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("1,1.5000,0.0000,1.5000,false"), "{stdout}");
+    Ok(())
+}
+
+/// @brief Helper collecting the messages produced for the given inputs.
+async fn collect(paths: &[&str]) -> io::Result<Vec<TransactionMessage>> {
+    let (tx, mut rx) = mpsc::channel(100);
+    let args = Args::parse_from(std::iter::once("payments_engine").chain(paths.iter().copied()));
+    let producer = tokio::spawn(process_file(args, tx));
+
+    let mut received = Vec::new();
+    while let Some(msg) = rx.recv().await {
+        received.push(msg);
+    }
+    producer.await??;
+    Ok(received)
+}
+
+/// @brief Helper writing `data` compressed with `compression` to `path`.
+async fn write_compressed(path: &Path, data: &[u8], compression: Compression) -> io::Result<()> {
+    let file = tokio::fs::File::create(path).await?;
+    let mut encoder: Box<dyn AsyncWrite + Unpin> = match compression {
+        Compression::Gzip => Box::new(GzipEncoder::new(file)),
+        Compression::Zstd => Box::new(ZstdEncoder::new(file)),
+        Compression::None => Box::new(file),
+    };
+    encoder.write_all(data).await?;
+    encoder.shutdown().await
+}
+
+/// @brief Gzip and zstd compressed versions of the sample sets produce the same messages as
+/// the plain files, whether detected by extension or by magic bytes.
+#[tokio::test]
+async fn test_process_file_compressed_sets() -> io::Result<()> {
+    let dir = TempDir::new()?;
+    for set in ["sets/input_000.csv", "sets/input_basic.csv"] {
+        let plain = collect(&[set]).await?;
+        assert!(!plain.is_empty());
+        let data = tokio::fs::read(set).await?;
+
+        for (compression, extension) in [(Compression::Gzip, "gz"), (Compression::Zstd, "zst")] {
+            let named = dir.path().join(format!("input.csv.{extension}"));
+            let unnamed = dir.path().join(format!("input_{extension}"));
+            write_compressed(&named, &data, compression).await?;
+            write_compressed(&unnamed, &data, compression).await?;
+
+            assert_eq!(collect(&[named.to_str().unwrap()]).await?, plain);
+            assert_eq!(collect(&[unnamed.to_str().unwrap()]).await?, plain);
+        }
+    }
+    Ok(())
+}
 
+/// @brief Concatenated gzip members decode as a single input.
+#[tokio::test]
+async fn test_process_file_concatenated_gzip() -> io::Result<()> {
+    let dir = TempDir::new()?;
+    let first = dir.path().join("first.gz");
+    let second = dir.path().join("second.gz");
+    write_compressed(
+        &first,
+        b"type,client,tx,amount\ndeposit,1,1,1.0\n",
+        Compression::Gzip,
+    )
+    .await?;
+    write_compressed(&second, b"deposit,1,2,2.0\n", Compression::Gzip).await?;
+
+    let joined = dir.path().join("joined.csv.gz");
+    let mut data = tokio::fs::read(&first).await?;
+    data.extend(tokio::fs::read(&second).await?);
+    tokio::fs::write(&joined, data).await?;
+
+    let received = collect(&[joined.to_str().unwrap()]).await?;
+    assert_eq!(
+        received.iter().map(|msg| msg.tx).collect::<Vec<_>>(),
+        vec![1, 2]
+    );
+    Ok(())
+}
+
+/// @brief Compression is detected from extension or magic bytes.
+#[test]
+fn test_compression_detect() {
+    assert_eq!(Compression::detect("a.csv.gz", b""), Compression::Gzip);
+    assert_eq!(Compression::detect("a.csv.zst", b""), Compression::Zstd);
+    assert_eq!(
+        Compression::detect("-", &[0x1f, 0x8b, 8]),
+        Compression::Gzip
+    );
+    assert_eq!(
+        Compression::detect("a", &[0x28, 0xb5, 0x2f, 0xfd]),
+        Compression::Zstd
+    );
+    assert_eq!(
+        Compression::detect("a.csv", b"type,client"),
+        Compression::None
+    );
+}
+
+/// @brief NDJSON inputs produce the same messages as the equivalent CSV input.
+///
+/// The format is selected by extension, by contents or explicitly; NDJSON has no header, so
+/// each message's line number is one less than in the CSV version.
+#[tokio::test]
+async fn test_process_file_ndjson_matches_csv() -> io::Result<()> {
+    let csv = write_csv(&[
+        "type,client,tx,amount",
+        "deposit,1,1,1.2345",
+        "withdrawal,1,2,0.5",
+        "dispute,1,1,",
+        "deposit,2,3,-1.0",
+    ])?;
+    let json = [
+        r#"{"type":"deposit","client":1,"tx":1,"amount":1.2345}"#,
+        r#"{"type":"withdrawal","client":1,"tx":2,"amount":"0.5"}"#,
+        r#"{"type":" dispute ","client":1,"tx":1}"#,
+        r#"{"type":"deposit","client":2,"tx":3,"amount":-1.0,"ts":7}"#,
+    ];
+    let dir = TempDir::new()?;
+    let named = dir.path().join("input.ndjson");
+    let unnamed = dir.path().join("input");
+    let data = json.join("\n") + "\n";
+    tokio::fs::write(&named, &data).await?;
+    tokio::fs::write(&unnamed, &data).await?;
+
+    let expected: Vec<TransactionMessage> = collect(&[csv.path().to_str().unwrap()])
+        .await?
+        .into_iter()
+        .map(|msg| TransactionMessage {
+            line: msg.line.map(|line| line - 1),
+            ..msg
+        })
+        .collect();
+    assert_eq!(expected[0].amount, Some(Decimal::new(12345, 4)));
+
+    assert_eq!(collect(&[named.to_str().unwrap()]).await?, expected);
+    assert_eq!(collect(&[unnamed.to_str().unwrap()]).await?, expected);
+    let forced = collect(&[unnamed.to_str().unwrap(), "--input-format", "ndjson"]).await?;
+    assert_eq!(forced, expected);
+    Ok(())
+}
+
+/// @brief Malformed NDJSON lines are reported as parse errors with their line numbers,
+/// with the fields that could be read, and the following lines are still processed.
+#[tokio::test]
+async fn test_process_file_ndjson_invalid_line() -> io::Result<()> {
+    let dir = TempDir::new()?;
+    let path = dir.path().join("input.jsonl");
+    tokio::fs::write(
+        &path,
+        "{\"type\":\"deposit\",\"client\":1,\"tx\":1,\"amount\":1}\n\n{\"type\":\"deposit\",\n\
+         {\"type\":\"deposit\",\"client\":2,\"tx\":2,\"amount\":\"x\"}\n\
+         {\"type\":\"deposit\",\"client\":1,\"tx\":3,\"amount\":2}\n",
+    )
+    .await?;
+
+    let (tx, mut rx) = mpsc::channel(10);
+    let (parse_errors, mut parse_error_rx) = mpsc::channel(10);
+    let args = Args::parse_from(["payments_engine", path.to_str().unwrap()]);
+    process_file_from(args, tx, ResumePoints::new(), Some(parse_errors)).await?;
+
+    assert_eq!(rx.recv().await.unwrap().line, Some(1));
+    assert_eq!(rx.recv().await.unwrap().line, Some(5));
+    assert_eq!(rx.recv().await, None);
+    let parse_error = |tx_type: &str, client, tx, line| RejectedRecord {
+        tx_type: tx_type.to_string(),
+        client,
+        tx,
+        amount: None,
+        input: 0,
+        line: Some(line),
+        reason: PARSE_ERROR,
+    };
+    assert_eq!(
+        parse_error_rx.recv().await,
+        Some(parse_error("", None, None, 3))
+    );
+    assert_eq!(
+        parse_error_rx.recv().await,
+        Some(parse_error("deposit", Some(2), Some(2), 4))
+    );
+    assert_eq!(parse_error_rx.recv().await, None);
+    Ok(())
+}
+
+/// @brief CRLF line endings yield the same messages and line numbers as LF endings.
+#[tokio::test]
+async fn test_process_file_crlf_line_numbers() -> io::Result<()> {
+    let dir = TempDir::new()?;
+    let crlf = dir.path().join("crlf.csv");
+    let lf = dir.path().join("lf.csv");
+    tokio::fs::write(
+        &crlf,
+        "type,client,tx,amount\r\ndeposit,1,1,1.0\r\ndispute,1,1,\r\n",
+    )
+    .await?;
+    tokio::fs::write(
+        &lf,
+        "type,client,tx,amount\ndeposit,1,1,1.0\ndispute,1,1,\n",
+    )
+    .await?;
+
+    let received = collect(&[crlf.to_str().unwrap()]).await?;
+    assert_eq!(
+        received.iter().map(|msg| msg.line).collect::<Vec<_>>(),
+        vec![Some(2), Some(3)]
+    );
+    assert_eq!(received[1].amount, None);
+    assert_eq!(received, collect(&[lf.to_str().unwrap()]).await?);
+    Ok(())
+}
+
+/// @brief The optional currency column is parsed case-insensitively, an empty value is
+/// left to the engine's default and rows with an unknown currency are skipped.
+#[tokio::test]
+async fn test_process_file_currency_column() -> io::Result<()> {
+    let file = write_csv(&[
+        "type,client,tx,amount,currency",
+        "deposit,1,1,1.0, usd",
+        "deposit,1,2,1.0,",
+        "deposit,1,3,1.0,GBP",
+        "dispute,1,1,,PLN",
+    ])?;
+
+    let received = collect(&[file.path().to_str().unwrap()]).await?;
+    assert_eq!(
+        received
+            .iter()
+            .map(|msg| (msg.tx, msg.currency))
+            .collect::<Vec<_>>(),
+        vec![
+            (1, Some(Currency::Usd)),
+            (2, None),
+            (1, Some(Currency::Pln))
+        ]
+    );
+    Ok(())
+}
+
+/// @brief Test that the optional `timestamp` column is parsed for authorization expiry.
+///
+/// An empty value means no timestamp.
+#[tokio::test]
+async fn test_process_file_timestamp_column() -> io::Result<()> {
+    let file = write_csv(&[
+        "type,client,tx,amount,timestamp",
+        "authorize,1,1,1.0,1700000000",
+        "capture,1,1,,",
+    ])?;
+
+    let received = collect(&[file.path().to_str().unwrap()]).await?;
+    assert_eq!(
+        received
+            .iter()
+            .map(|msg| (msg.tx_type.clone(), msg.timestamp))
+            .collect::<Vec<_>>(),
+        vec![
+            (TransactionType::Authorize, Some(1_700_000_000)),
+            (TransactionType::Capture, None),
+        ]
+    );
+    Ok(())
+}
+
+/// @brief Rows skipped for an unknown type or currency are reported as parse errors.
+#[tokio::test]
+async fn test_process_file_reports_parse_errors() -> io::Result<()> {
+    let file = write_csv(&[
+        "type,client,tx,amount,currency",
+        "deposit,1,1,1.0,",
+        "refund,2,2,3.0,",
+        "deposit,1,3,1.0,GBP",
+    ])?;
+
+    let (tx, mut rx) = mpsc::channel(10);
+    let (parse_errors, mut parse_error_rx) = mpsc::channel(10);
+    let args = Args::parse_from(["payments_engine", file.path().to_str().unwrap()]);
+    process_file_from(args, tx, ResumePoints::new(), Some(parse_errors)).await?;
+
+    assert_eq!(rx.recv().await.map(|msg| msg.tx), Some(1));
+    assert_eq!(rx.recv().await, None);
+    let mut reported = Vec::new();
+    while let Some(record) = parse_error_rx.recv().await {
+        reported.push(record);
+    }
+    assert_eq!(
+        reported,
+        vec![
+            RejectedRecord {
+                tx_type: "refund".to_string(),
+                client: Some(2),
+                tx: Some(2),
+                amount: Some(Decimal::new(30, 1)),
+                input: 0,
+                line: Some(3),
+                reason: PARSE_ERROR,
+            },
+            RejectedRecord {
+                tx_type: "deposit".to_string(),
+                client: Some(1),
+                tx: Some(3),
+                amount: Some(Decimal::new(10, 1)),
+                input: 0,
+                line: Some(4),
+                reason: PARSE_ERROR,
+            },
+        ]
+    );
+    Ok(())
+}
+
+/// @brief Rows that fail to deserialize, such as a malformed amount, an out-of-range
+/// client id or a wrong number of columns, are reported as parse errors with their line
+/// numbers and the readable columns, and the rest of the file is still processed.
+#[tokio::test]
+async fn test_process_file_reports_malformed_rows() -> io::Result<()> {
+    let file = write_csv(&[
+        "type,client,tx,amount",
+        "deposit,1,1,1.0",
+        "deposit,1,2,1.x",
+        "deposit,70000,3,2.0",
+        "withdrawal,1,4",
+        "deposit,1,5,1.0,extra",
+        "deposit,2,6,3.0",
+    ])?;
+
+    let (tx, mut rx) = mpsc::channel(10);
+    let (parse_errors, mut parse_error_rx) = mpsc::channel(10);
+    let args = Args::parse_from(["payments_engine", file.path().to_str().unwrap()]);
+    process_file_from(args, tx, ResumePoints::new(), Some(parse_errors)).await?;
+
+    assert_eq!(rx.recv().await.map(|msg| msg.tx), Some(1));
+    assert_eq!(rx.recv().await.map(|msg| msg.tx), Some(6));
+    assert_eq!(rx.recv().await, None);
+    let mut reported = Vec::new();
+    while let Some(record) = parse_error_rx.recv().await {
+        reported.push(record);
+    }
+    let parse_error = |tx_type: &str, client, tx, amount, line| RejectedRecord {
+        tx_type: tx_type.to_string(),
+        client,
+        tx,
+        amount,
+        input: 0,
+        line: Some(line),
+        reason: PARSE_ERROR,
+    };
+    assert_eq!(
+        reported,
+        vec![
+            parse_error("deposit", Some(1), Some(2), None, 3),
+            parse_error("deposit", None, Some(3), Some(Decimal::new(20, 1)), 4),
+            parse_error("", None, None, None, 5),
+            parse_error("", None, None, None, 6),
+        ]
+    );
     Ok(())
 }
@@ -0,0 +1,238 @@
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use payments_engine::reports::{
+    account_summaries, write_final_report, write_rejected_report, write_report,
+};
+use payments_engine::structures::{
+    ClientAccount, ClientsMap, Currency, Outcome, OutputFormat, PARSE_ERROR, ProcessedTransaction,
+    RejectedRecord, TransactionError, TransactionMessage, TransactionType,
+};
+use rust_decimal::Decimal;
+use tempfile::NamedTempFile;
+use tokio::sync::mpsc;
+
+/// Integration test for the `write_rejected_report` function.
+///
+/// This test feeds one applied and two rejected transactions into the outcomes channel,
+/// then a row that failed to parse into the parse errors channel, and verifies that only
+/// the rejected ones are written, in order, with their original type, client, tx, amount,
+/// input line number and reason code.
+#[tokio::test]
+async fn test_write_rejected_report() -> std::io::Result<()> {
+    let tmpfile = NamedTempFile::new()?;
+    let path = tmpfile.path().to_str().unwrap().to_string();
+
+    let (tx, rx) = mpsc::channel(10);
+    let (parse_errors, parse_error_rx) = mpsc::channel(10);
+
+    let mut deposit =
+        TransactionMessage::new(TransactionType::Deposit, 1, 1, Some(Decimal::new(10, 1)));
+    deposit.line = Some(2);
+    let mut withdrawal =
+        TransactionMessage::new(TransactionType::Withdrawal, 1, 2, Some(Decimal::new(50, 1)));
+    withdrawal.line = Some(3);
+    let mut dispute = TransactionMessage::new(TransactionType::Dispute, 2, 7, None);
+    dispute.line = Some(4);
+    dispute.source = 1;
+
+    tx.send(ProcessedTransaction {
+        message: deposit,
+        result: Ok(Outcome::Applied),
+        currency: Currency::Eur,
+        account: None,
+        counterparty: None,
+    })
+    .await
+    .unwrap();
+    tx.send(ProcessedTransaction {
+        message: withdrawal,
+        result: Err(TransactionError::InsufficientFunds {
+            client: 1,
+            available: Decimal::new(10, 1),
+            requested: Decimal::new(50, 1),
+        }),
+        currency: Currency::Eur,
+        account: None,
+        counterparty: None,
+    })
+    .await
+    .unwrap();
+    tx.send(ProcessedTransaction {
+        message: dispute,
+        result: Err(TransactionError::TransactionNotFound { tx: 7 }),
+        currency: Currency::Eur,
+        account: None,
+        counterparty: None,
+    })
+    .await
+    .unwrap();
+    drop(tx);
+    parse_errors
+        .send(RejectedRecord {
+            tx_type: "refund".to_string(),
+            client: Some(3),
+            tx: Some(8),
+            amount: Some(Decimal::new(20, 1)),
+            input: 1,
+            line: Some(5),
+            reason: PARSE_ERROR,
+        })
+        .await
+        .unwrap();
+    drop(parse_errors);
+
+    let file = tokio::fs::File::create(&path).await?;
+    let written = write_rejected_report(rx, parse_error_rx, file).await?;
+    assert_eq!(written, 3);
+
+    let content = std::fs::read_to_string(&path)?;
+    assert_eq!(
+        content,
+        "type,client,tx,amount,input,line,reason\n\
+         withdrawal,1,2,5.0,0,3,insufficient_funds\n\
+         dispute,2,7,,1,4,transaction_not_found\n\
+         refund,3,8,2.0,1,5,parse_error\n"
+    );
+
+    Ok(())
+}
+
+/// @brief Helper building a store with a client holding two currencies and a locked account.
+fn sample_clients() -> ClientsMap {
+    let clients: ClientsMap = Arc::new(DashMap::new());
+    clients.insert(
+        (12, Currency::Eur),
+        ClientAccount {
+            available: Decimal::new(-5, 1),
+            held: Decimal::new(100, 0),
+            total: Decimal::new(995, 1),
+            locked: true,
+        },
+    );
+    clients.insert(
+        (1, Currency::Usd),
+        ClientAccount {
+            available: Decimal::new(15, 1),
+            held: Decimal::ZERO,
+            total: Decimal::new(15, 1),
+            locked: false,
+        },
+    );
+    clients.insert(
+        (1, Currency::Eur),
+        ClientAccount {
+            available: Decimal::TWO,
+            held: Decimal::ZERO,
+            total: Decimal::TWO,
+            locked: false,
+        },
+    );
+    clients
+}
+
+/// @brief Helper rendering the sample accounts in `format`.
+fn render(format: OutputFormat) -> String {
+    let accounts = account_summaries(&sample_clients()).unwrap();
+    let mut out = Vec::new();
+    write_report(&accounts, Currency::Eur, format, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+/// @brief Every output format lists the accounts sorted by client and currency with four
+/// decimal places.
+#[test]
+fn test_write_report_formats() {
+    assert_eq!(
+        render(OutputFormat::Csv),
+        "client,currency,available,held,total,locked\n\
+         1,EUR,2.0000,0.0000,2.0000,false\n\
+         1,USD,1.5000,0.0000,1.5000,false\n\
+         12,EUR,-0.5000,100.0000,99.5000,true\n"
+    );
+    assert_eq!(
+        render(OutputFormat::Json),
+        "[{\"client\":1,\"currency\":\"EUR\",\"available\":\"2.0000\",\"held\":\"0.0000\",\"total\":\"2.0000\",\"locked\":false},\
+         {\"client\":1,\"currency\":\"USD\",\"available\":\"1.5000\",\"held\":\"0.0000\",\"total\":\"1.5000\",\"locked\":false},\
+         {\"client\":12,\"currency\":\"EUR\",\"available\":\"-0.5000\",\"held\":\"100.0000\",\"total\":\"99.5000\",\"locked\":true}]\n"
+    );
+    assert_eq!(
+        render(OutputFormat::Ndjson),
+        "{\"client\":1,\"currency\":\"EUR\",\"available\":\"2.0000\",\"held\":\"0.0000\",\"total\":\"2.0000\",\"locked\":false}\n\
+         {\"client\":1,\"currency\":\"USD\",\"available\":\"1.5000\",\"held\":\"0.0000\",\"total\":\"1.5000\",\"locked\":false}\n\
+         {\"client\":12,\"currency\":\"EUR\",\"available\":\"-0.5000\",\"held\":\"100.0000\",\"total\":\"99.5000\",\"locked\":true}\n"
+    );
+    assert_eq!(
+        render(OutputFormat::Table),
+        "client  currency  available      held    total  locked\n\
+         \x20    1  EUR          2.0000    0.0000   2.0000  false\n\
+         \x20    1  USD          1.5000    0.0000   1.5000  false\n\
+         \x20   12  EUR         -0.5000  100.0000  99.5000  true\n"
+    );
+}
+
+/// @brief Accounts all in the default currency are reported without a currency column, in
+/// every format; any other currency brings the column back.
+#[test]
+fn test_write_report_single_currency() {
+    let clients = sample_clients();
+    clients.remove(&(1, Currency::Usd));
+    let accounts = account_summaries(&clients).unwrap();
+    let write = |currency, format| {
+        let mut out = Vec::new();
+        write_report(&accounts, currency, format, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    };
+
+    assert_eq!(
+        write(Currency::Eur, OutputFormat::Csv),
+        "client,available,held,total,locked\n\
+         1,2.0000,0.0000,2.0000,false\n\
+         12,-0.5000,100.0000,99.5000,true\n"
+    );
+    assert_eq!(
+        write(Currency::Eur, OutputFormat::Json),
+        "[{\"client\":1,\"available\":\"2.0000\",\"held\":\"0.0000\",\"total\":\"2.0000\",\"locked\":false},\
+         {\"client\":12,\"available\":\"-0.5000\",\"held\":\"100.0000\",\"total\":\"99.5000\",\"locked\":true}]\n"
+    );
+    assert_eq!(
+        write(Currency::Eur, OutputFormat::Table),
+        "client  available      held    total  locked\n\
+         \x20    1     2.0000    0.0000   2.0000  false\n\
+         \x20   12    -0.5000  100.0000  99.5000  true\n"
+    );
+    assert!(write(Currency::Usd, OutputFormat::Csv).starts_with("client,currency,"));
+}
+
+/// @brief An empty store still yields a header in CSV and a valid empty JSON array.
+#[test]
+fn test_write_report_empty() {
+    let clients: ClientsMap = Arc::new(DashMap::new());
+    let accounts = account_summaries(&clients).unwrap();
+
+    let mut csv = Vec::new();
+    write_report(&accounts, Currency::Eur, OutputFormat::Csv, &mut csv).unwrap();
+    assert_eq!(csv, b"client,available,held,total,locked\n");
+
+    let mut json = Vec::new();
+    write_report(&accounts, Currency::Eur, OutputFormat::Json, &mut json).unwrap();
+    assert_eq!(json, b"[]\n");
+}
+
+/// @brief `write_final_report` writes to the given output file.
+#[test]
+fn test_write_final_report_to_file() -> std::io::Result<()> {
+    let tmpfile = NamedTempFile::new()?;
+    let path = tmpfile.path().to_str().unwrap();
+
+    write_final_report(
+        sample_clients(),
+        Currency::Eur,
+        OutputFormat::Ndjson,
+        Some(path),
+    )?;
+
+    let content = std::fs::read_to_string(path)?;
+    assert_eq!(content, render(OutputFormat::Ndjson));
+    Ok(())
+}
@@ -0,0 +1,255 @@
+use std::sync::Arc;
+
+use clap::Parser;
+use dashmap::DashMap;
+use payments_engine::engine::{Engine, EngineHandle};
+use payments_engine::producer::process_file;
+use payments_engine::sharded::ShardedEngineHandle;
+use payments_engine::structures::{
+    AccountSummary, Args, ClientsMap, TransactionMessage, TransactionType, TransactionsMap,
+};
+use rust_decimal::Decimal;
+use tokio::sync::mpsc;
+
+/// Helper building `count` engines sharing one pair of maps.
+fn shared_engines(count: usize) -> Vec<Engine> {
+    let clients: ClientsMap = Arc::new(DashMap::new());
+    let transactions: TransactionsMap = Arc::new(DashMap::new());
+    (0..count)
+        .map(|_| Engine::with_maps(clients.clone(), transactions.clone()))
+        .collect()
+}
+
+/// Helper feeding `messages` to a sharded engine and returning the final accounts.
+async fn run_sharded(shards: usize, messages: Vec<TransactionMessage>) -> Vec<AccountSummary> {
+    let handle = ShardedEngineHandle::spawn(shared_engines(shards), 8, None);
+    let sender = handle.sender();
+    for msg in messages {
+        sender.send(msg).await.unwrap();
+    }
+    drop(sender);
+    handle.join().await.unwrap()[0].accounts()
+}
+
+/// Helper feeding `messages` to the single-consumer engine and returning the final accounts.
+async fn run_single(messages: Vec<TransactionMessage>) -> Vec<AccountSummary> {
+    let handle = EngineHandle::spawn(Engine::new(), 8, None);
+    let sender = handle.sender();
+    for msg in messages {
+        sender.send(msg).await.unwrap();
+    }
+    drop(sender);
+    handle.join().await.unwrap().accounts()
+}
+
+/// @brief Test that the sharded engine matches the single-consumer engine on every set.
+#[tokio::test]
+async fn test_sharded_matches_single_consumer_on_sets() {
+    let mut paths: Vec<_> = std::fs::read_dir("sets")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("input_"))
+        })
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty());
+
+    for path in paths {
+        let (tx, mut rx) = mpsc::channel(1000);
+        let args = Args::parse_from(["payments_engine", path.to_str().unwrap()]);
+        process_file(args, tx).await.unwrap();
+        let mut messages = Vec::new();
+        while let Some(msg) = rx.recv().await {
+            messages.push(msg);
+        }
+
+        let expected = run_single(messages.clone()).await;
+        for shards in [1, 2, 3, 8] {
+            assert_eq!(
+                run_sharded(shards, messages.clone()).await,
+                expected,
+                "{path:?} with {shards} shards"
+            );
+        }
+    }
+}
+
+/// @brief Test that per-client ordering is preserved across many interleaved clients.
+///
+/// Every withdrawal spends exactly the preceding deposit and every second deposit is
+/// disputed and resolved, so any reordering within a client changes the final balances.
+#[tokio::test]
+async fn test_sharded_preserves_per_client_order() {
+    let mut messages = Vec::new();
+    let mut tx = 0;
+    for round in 0..200i64 {
+        for client in 1..=16u16 {
+            let amount = Decimal::new(round + i64::from(client), 2);
+            tx += 1;
+            messages.push(TransactionMessage::new(
+                TransactionType::Deposit,
+                client,
+                tx,
+                Some(amount),
+            ));
+            if round % 2 == 0 {
+                messages.push(TransactionMessage::new(
+                    TransactionType::Dispute,
+                    client,
+                    tx,
+                    None,
+                ));
+                messages.push(TransactionMessage::new(
+                    TransactionType::Resolve,
+                    client,
+                    tx,
+                    None,
+                ));
+            }
+            tx += 1;
+            messages.push(TransactionMessage::new(
+                TransactionType::Withdrawal,
+                client,
+                tx,
+                Some(amount),
+            ));
+        }
+    }
+    messages.push(TransactionMessage::new(
+        TransactionType::Dispute,
+        5,
+        1,
+        None,
+    ));
+
+    let expected = run_single(messages.clone()).await;
+    assert!(expected.iter().all(|summary| summary.total.is_zero()));
+    assert_eq!(run_sharded(4, messages).await, expected);
+}
+
+/// @brief Test that tx IDs reused across shards are resolved in input order.
+///
+/// Clients 1 and 2 live in different shards and reuse each other's tx IDs; the first use
+/// in the input must win, and disputes naming another client's transaction must fail.
+#[tokio::test]
+async fn test_sharded_cross_shard_tx_ids_follow_input_order() {
+    let mut messages = Vec::new();
+    for tx in 1..=300u32 {
+        let (first, second) = if tx % 2 == 0 { (1, 2) } else { (2, 1) };
+        messages.push(TransactionMessage::new(
+            TransactionType::Deposit,
+            first,
+            tx,
+            Some(Decimal::ONE),
+        ));
+        messages.push(TransactionMessage::new(
+            TransactionType::Deposit,
+            second,
+            tx,
+            Some(Decimal::TEN),
+        ));
+        messages.push(TransactionMessage::new(
+            TransactionType::Dispute,
+            second,
+            tx,
+            None,
+        ));
+    }
+
+    let expected = run_single(messages.clone()).await;
+    assert_eq!(expected[0].total, Decimal::new(150, 0));
+    assert_eq!(expected[1].total, Decimal::new(150, 0));
+    assert_eq!(run_sharded(2, messages).await, expected);
+}
+
+/// @brief Test that transfers between clients of different shards match a single engine.
+///
+/// Every client repeatedly pays its neighbour, each payment spending most of what the
+/// client just received, and every third transfer is disputed and then resolved or charged
+/// back. Lost updates on the credited account of another shard would change the balances.
+#[tokio::test]
+async fn test_sharded_cross_shard_transfers_match_single_consumer() {
+    let clients = 12u16;
+    let mut messages = Vec::new();
+    let mut tx = 0;
+    for client in 1..=clients {
+        tx += 1;
+        messages.push(TransactionMessage::new(
+            TransactionType::Deposit,
+            client,
+            tx,
+            Some(Decimal::TEN),
+        ));
+    }
+    for round in 0..100i64 {
+        for client in 1..=clients {
+            tx += 1;
+            messages.push(TransactionMessage {
+                destination: Some(client % clients + 1),
+                ..TransactionMessage::new(
+                    TransactionType::Transfer,
+                    client,
+                    tx,
+                    Some(Decimal::new(90 + round % 7, 1)),
+                )
+            });
+            if tx % 3 == 0 {
+                let settle = if tx % 2 == 0 {
+                    TransactionType::Resolve
+                } else {
+                    TransactionType::Chargeback
+                };
+                for tx_type in [TransactionType::Dispute, settle] {
+                    messages.push(TransactionMessage::new(tx_type, client, tx, None));
+                }
+            }
+        }
+    }
+
+    let expected = run_single(messages.clone()).await;
+    assert!(expected.iter().any(|summary| summary.locked));
+    for shards in [2, 5] {
+        assert_eq!(run_sharded(shards, messages.clone()).await, expected);
+    }
+}
+
+/// @brief Test that shutdown still applies messages routed before it was requested.
+#[tokio::test]
+async fn test_sharded_shutdown_drains_in_flight_messages() {
+    let (outcome_tx, mut outcome_rx) = mpsc::channel(100);
+    let handle = ShardedEngineHandle::spawn(shared_engines(3), 100, Some(outcome_tx));
+    let sender = handle.sender();
+    for client in 1..=30u16 {
+        let msg = TransactionMessage::new(
+            TransactionType::Deposit,
+            client,
+            u32::from(client),
+            Some(Decimal::ONE),
+        );
+        sender.send(msg).await.unwrap();
+    }
+
+    let engines = handle.shutdown().await.unwrap();
+    assert_eq!(engines.len(), 3);
+    assert_eq!(engines[2].accounts().len(), 30);
+    assert!(
+        sender
+            .send(TransactionMessage::new(
+                TransactionType::Deposit,
+                1,
+                99,
+                None
+            ))
+            .await
+            .is_err()
+    );
+
+    let mut outcomes = 0;
+    while outcome_rx.recv().await.is_some() {
+        outcomes += 1;
+    }
+    assert_eq!(outcomes, 30);
+}
@@ -0,0 +1,155 @@
+use std::io::Write;
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use payments_engine::engine::Engine;
+use payments_engine::snapshot::{SnapshotStats, load_snapshot, save_snapshot};
+use payments_engine::structures::{
+    ClientsMap, TransactionMessage, TransactionState, TransactionType, TransactionsMap,
+};
+use rust_decimal::Decimal;
+use tempfile::{NamedTempFile, TempDir};
+
+/// Helper returning a day-one and a day-two batch of messages for two clients.
+///
+/// Day two disputes and resolves a deposit made on day one, so it can only be applied
+/// correctly on top of day one's transaction records.
+fn batches() -> (Vec<TransactionMessage>, Vec<TransactionMessage>) {
+    let day_one = vec![
+        TransactionMessage::new(TransactionType::Deposit, 1, 1, Some(Decimal::new(100, 1))),
+        TransactionMessage::new(TransactionType::Deposit, 2, 2, Some(Decimal::new(55, 1))),
+        TransactionMessage::new(TransactionType::Withdrawal, 1, 3, Some(Decimal::new(25, 1))),
+        TransactionMessage::new(TransactionType::Dispute, 2, 2, None),
+    ];
+    let day_two = vec![
+        TransactionMessage::new(TransactionType::Resolve, 2, 2, None),
+        TransactionMessage::new(TransactionType::Deposit, 1, 4, Some(Decimal::new(1, 4))),
+        TransactionMessage::new(TransactionType::Dispute, 1, 4, None),
+        TransactionMessage::new(TransactionType::Chargeback, 1, 4, None),
+    ];
+    (day_one, day_two)
+}
+
+fn new_maps() -> (ClientsMap, TransactionsMap) {
+    (Arc::new(DashMap::new()), Arc::new(DashMap::new()))
+}
+
+fn apply_all(engine: &mut Engine, messages: Vec<TransactionMessage>) {
+    for msg in messages {
+        let _ = engine.apply(msg);
+    }
+}
+
+/// @brief Test that a snapshot round-trips every account and transaction record.
+#[test]
+fn test_snapshot_round_trip() -> std::io::Result<()> {
+    let dir = TempDir::new()?;
+    let path = dir.path().join("state.snapshot");
+
+    let (clients, transactions) = new_maps();
+    let mut engine = Engine::with_maps(clients.clone(), transactions.clone());
+    apply_all(&mut engine, batches().0);
+
+    let written = save_snapshot(&path, &clients, &transactions, None)?;
+    assert_eq!(
+        written,
+        SnapshotStats {
+            accounts: 2,
+            transactions: 3,
+            wal_seq: None,
+        }
+    );
+
+    let (restored_clients, restored_transactions) = new_maps();
+    let read = load_snapshot(&path, &restored_clients, &restored_transactions)?;
+    assert_eq!(read, written);
+
+    for entry in clients.iter() {
+        assert_eq!(
+            *restored_clients.get(entry.key()).unwrap().value(),
+            *entry.value()
+        );
+    }
+    for entry in transactions.iter() {
+        assert_eq!(
+            *restored_transactions.get(entry.key()).unwrap().value(),
+            *entry.value()
+        );
+    }
+    assert_eq!(
+        restored_transactions.get(&2).unwrap().state,
+        TransactionState::Disputed
+    );
+
+    Ok(())
+}
+
+/// @brief Test that processing day two on top of day one's snapshot matches a full run.
+#[test]
+fn test_snapshot_continuation_matches_full_run() -> std::io::Result<()> {
+    let dir = TempDir::new()?;
+    let path = dir.path().join("day_one.snapshot");
+    let (day_one, day_two) = batches();
+
+    let mut full = Engine::new();
+    apply_all(&mut full, day_one.clone());
+    apply_all(&mut full, day_two.clone());
+
+    let (clients, transactions) = new_maps();
+    let mut first_run = Engine::with_maps(clients.clone(), transactions.clone());
+    apply_all(&mut first_run, day_one);
+    save_snapshot(&path, &clients, &transactions, None)?;
+
+    let (clients, transactions) = new_maps();
+    load_snapshot(&path, &clients, &transactions)?;
+    let mut second_run = Engine::with_maps(clients, transactions);
+    apply_all(&mut second_run, day_two);
+
+    assert_eq!(second_run.accounts(), full.accounts());
+    assert!(second_run.account(1).unwrap().locked);
+
+    Ok(())
+}
+
+/// @brief Test that the write-ahead log position round-trips through the snapshot header.
+#[test]
+fn test_snapshot_records_wal_position() -> std::io::Result<()> {
+    let dir = TempDir::new()?;
+    let path = dir.path().join("state.snapshot");
+    let (clients, transactions) = new_maps();
+    apply_all(
+        &mut Engine::with_maps(clients.clone(), transactions.clone()),
+        batches().0,
+    );
+
+    let written = save_snapshot(&path, &clients, &transactions, Some(42))?;
+    assert_eq!(written.wal_seq, Some(42));
+
+    let (clients, transactions) = new_maps();
+    let read = load_snapshot(&path, &clients, &transactions)?;
+    assert_eq!(read, written);
+
+    Ok(())
+}
+
+/// @brief Test that snapshots of an unknown version or without header are refused.
+#[test]
+fn test_snapshot_version_mismatch_rejected() -> std::io::Result<()> {
+    let (clients, transactions) = new_maps();
+
+    let mut future = NamedTempFile::new()?;
+    writeln!(future, r#"{{"kind":"header","version":999}}"#)?;
+    let err = load_snapshot(future.path(), &clients, &transactions).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+    let mut headerless = NamedTempFile::new()?;
+    writeln!(
+        headerless,
+        r#"{{"kind":"account","client":1,"available":"1","held":"0","total":"1","locked":false}}"#
+    )?;
+    let err = load_snapshot(headerless.path(), &clients, &transactions).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert!(clients.is_empty());
+
+    Ok(())
+}
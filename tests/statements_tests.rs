@@ -0,0 +1,254 @@
+use payments_engine::engine::{Engine, EngineHandle};
+use payments_engine::statements::{
+    StatementWriter, counterparty_entry, statement_entry, write_statement_report,
+};
+use payments_engine::structures::{
+    EngineConfig, ProcessedTransaction, StatementEntry, StatementFormat, TransactionMessage,
+    TransactionType,
+};
+use rust_decimal::Decimal;
+use tokio::sync::mpsc;
+
+/// @brief Helper processing `messages` and collecting the statement of `client` (or all).
+async fn statement(messages: Vec<TransactionMessage>, client: Option<u16>) -> Vec<StatementEntry> {
+    statement_of(Engine::new(), messages, client).await
+}
+
+/// @brief Helper processing `messages` on `engine` and collecting the statement of `client`
+/// (or all), in processing order.
+async fn statement_of(
+    engine: Engine,
+    messages: Vec<TransactionMessage>,
+    client: Option<u16>,
+) -> Vec<StatementEntry> {
+    let (outcome_sender, outcome_receiver) = mpsc::channel(10);
+    let collector = tokio::spawn(collect(outcome_receiver));
+
+    let handle = EngineHandle::spawn(engine, 10, Some(outcome_sender));
+    let sender = handle.sender();
+    for (line, msg) in messages.into_iter().enumerate() {
+        let msg = TransactionMessage {
+            line: Some(line as u64 + 2),
+            ..msg
+        };
+        sender.send(msg).await.unwrap();
+    }
+    drop(sender);
+    handle.join().await.unwrap();
+
+    collector
+        .await
+        .unwrap()
+        .iter()
+        .flat_map(|processed| {
+            [
+                Some(statement_entry(processed)),
+                counterparty_entry(processed),
+            ]
+        })
+        .flatten()
+        .filter(|entry| client.is_none_or(|client| client == entry.client))
+        .collect()
+}
+
+/// @brief Helper collecting every outcome received until the channel is closed.
+async fn collect(mut outcomes: mpsc::Receiver<ProcessedTransaction>) -> Vec<ProcessedTransaction> {
+    let mut processed = Vec::new();
+    while let Some(outcome) = outcomes.recv().await {
+        processed.push(outcome);
+    }
+    processed
+}
+
+/// @brief Helper building a message with an amount given in ten-thousandths.
+fn msg(tx_type: TransactionType, client: u16, tx: u32, amount: Option<i64>) -> TransactionMessage {
+    TransactionMessage::new(tx_type, client, tx, amount.map(|a| Decimal::new(a, 4)))
+}
+
+/// @brief A statement lists applied and rejected transactions with running balances.
+///
+/// Entries are listed in processing order; rejected messages repeat the balances of the
+/// client's previous entry.
+#[tokio::test]
+async fn test_statement_running_balances() {
+    let entries = statement(
+        vec![
+            msg(TransactionType::Deposit, 2, 1, Some(20_000)),
+            msg(TransactionType::Deposit, 1, 2, Some(15_000)),
+            msg(TransactionType::Withdrawal, 1, 3, Some(50_000)),
+            msg(TransactionType::Dispute, 1, 2, None),
+            msg(TransactionType::Chargeback, 1, 2, None),
+        ],
+        None,
+    )
+    .await;
+
+    let summary: Vec<String> = entries
+        .iter()
+        .map(|e| {
+            format!(
+                "{} {} {:?} {} {} {} {} {}",
+                e.client,
+                e.tx,
+                e.line,
+                e.status,
+                e.reason.unwrap_or("-"),
+                e.available,
+                e.held,
+                e.locked
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            "2 1 Some(2) applied - 2.0000 0.0000 false",
+            "1 2 Some(3) applied - 1.5000 0.0000 false",
+            "1 3 Some(4) rejected insufficient_funds 1.5000 0.0000 false",
+            "1 2 Some(5) applied - 0.0000 1.5000 false",
+            "1 2 Some(6) applied - 0.0000 0.0000 true",
+        ]
+    );
+}
+
+/// @brief A client filter keeps only that client's entries.
+#[tokio::test]
+async fn test_statement_single_client() {
+    let entries = statement(
+        vec![
+            msg(TransactionType::Deposit, 2, 1, Some(20_000)),
+            msg(TransactionType::Deposit, 1, 2, Some(15_000)),
+            msg(TransactionType::Withdrawal, 2, 3, Some(5_000)),
+        ],
+        Some(2),
+    )
+    .await;
+
+    assert_eq!(entries.len(), 2);
+    assert!(entries.iter().all(|e| e.client == 2));
+    assert_eq!(entries[1].total, Decimal::new(15_000, 4));
+}
+
+/// @brief Statements are written as CSV and JSON with the same fields.
+#[tokio::test]
+async fn test_write_statement_formats() {
+    let entries = statement(
+        vec![
+            msg(TransactionType::Deposit, 1, 1, Some(15_000)),
+            msg(TransactionType::Withdrawal, 1, 2, Some(50_000)),
+        ],
+        None,
+    )
+    .await;
+
+    let mut csv = Vec::new();
+    let mut writer = StatementWriter::new(&mut csv, StatementFormat::Csv)
+        .await
+        .unwrap();
+    for entry in &entries {
+        writer.write(entry).await.unwrap();
+    }
+    assert_eq!(writer.finish().await.unwrap(), 2);
+    assert_eq!(
+        String::from_utf8(csv).unwrap(),
+        "client,type,tx,amount,currency,input,line,status,reason,available,held,total,locked\n\
+         1,deposit,1,1.5000,EUR,0,2,applied,,1.5000,0.0000,1.5000,false\n\
+         1,withdrawal,2,5.0000,EUR,0,3,rejected,insufficient_funds,1.5000,0.0000,1.5000,false\n"
+    );
+
+    let mut json = Vec::new();
+    let mut writer = StatementWriter::new(&mut json, StatementFormat::Json)
+        .await
+        .unwrap();
+    writer.write(&entries[0]).await.unwrap();
+    writer.finish().await.unwrap();
+    assert_eq!(
+        String::from_utf8(json).unwrap(),
+        "[{\"client\":1,\"type\":\"deposit\",\"tx\":1,\"amount\":\"1.5000\",\"currency\":\"EUR\",\"input\":0,\"line\":2,\
+         \"status\":\"applied\",\"reason\":null,\"available\":\"1.5000\",\"held\":\"0.0000\",\
+         \"total\":\"1.5000\",\"locked\":false}]\n"
+    );
+
+    let (_, outcomes) = mpsc::channel(1);
+    let mut empty = Vec::new();
+    let written = write_statement_report(outcomes, None, StatementFormat::Csv, &mut empty)
+        .await
+        .unwrap();
+    assert_eq!(written, 0);
+    assert_eq!(
+        empty,
+        b"client,type,tx,amount,currency,input,line,status,reason,available,held,total,locked\n"
+    );
+}
+
+/// @brief A transfer appears in the statements of both clients with their own balances.
+#[tokio::test]
+async fn test_statement_lists_transfers_for_both_clients() {
+    let transfer = TransactionMessage {
+        destination: Some(2),
+        ..msg(TransactionType::Transfer, 1, 2, Some(5_000))
+    };
+    let messages = vec![
+        msg(TransactionType::Deposit, 1, 1, Some(20_000)),
+        transfer.clone(),
+        TransactionMessage { tx: 3, ..transfer },
+        msg(TransactionType::Dispute, 1, 2, None),
+    ];
+
+    let receiver = statement(messages.clone(), Some(2)).await;
+    let summary: Vec<String> = receiver
+        .iter()
+        .map(|e| format!("{:?} {} {} {}", e.tx_type, e.tx, e.available, e.held))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            "Transfer 2 0.5000 0.0000",
+            "Transfer 3 1.0000 0.0000",
+            "Dispute 2 0.5000 0.5000",
+        ]
+    );
+
+    let sender = statement(messages, Some(1)).await;
+    assert_eq!(sender.len(), 4);
+    assert_eq!(sender[3].available, Decimal::new(10_000, 4));
+}
+
+/// @brief An expired authorization is listed as a void with status `expired` just before
+/// the message that triggered the expiry, on that message's line.
+#[tokio::test]
+async fn test_statement_lists_expired_authorizations() {
+    let engine = Engine::new().with_config(EngineConfig {
+        authorization_ttl: Some(0),
+        ..Default::default()
+    });
+    let entries = statement_of(
+        engine,
+        vec![
+            msg(TransactionType::Deposit, 1, 1, Some(20_000)),
+            msg(TransactionType::Authorize, 1, 2, Some(5_000)),
+            msg(TransactionType::Withdrawal, 1, 3, Some(20_000)),
+        ],
+        None,
+    )
+    .await;
+
+    let summary: Vec<String> = entries
+        .iter()
+        .map(|e| {
+            format!(
+                "{:?} {} {:?} {} {} {}",
+                e.tx_type, e.tx, e.line, e.status, e.available, e.held
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            "Deposit 1 Some(2) applied 2.0000 0.0000",
+            "Authorize 2 Some(3) applied 1.5000 0.5000",
+            "Void 2 Some(4) expired 2.0000 0.0000",
+            "Withdrawal 3 Some(4) applied 0.0000 0.0000",
+        ]
+    );
+}
@@ -0,0 +1,228 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use dashmap::DashMap;
+use payments_engine::engine::Engine;
+use payments_engine::snapshot::{load_snapshot, save_snapshot};
+use payments_engine::storage::{AccountStore, SqliteStore, TransactionStore};
+use payments_engine::structures::{
+    ClientAccount, ClientsMap, Currency, EngineConfig, TransactionError, TransactionMessage,
+    TransactionRecord, TransactionState, TransactionType, TransactionsMap, WithdrawalDisputePolicy,
+};
+use rust_decimal::Decimal;
+use tempfile::TempDir;
+
+/// Helper returning a history exercising every transaction type, a second currency and
+/// several rejections.
+fn history() -> Vec<TransactionMessage> {
+    vec![
+        TransactionMessage::new(TransactionType::Deposit, 1, 1, Some(Decimal::new(100, 1))),
+        TransactionMessage::new(TransactionType::Deposit, 2, 2, Some(Decimal::new(12345, 4))),
+        TransactionMessage::new(TransactionType::Withdrawal, 1, 3, Some(Decimal::new(25, 1))),
+        TransactionMessage::new(TransactionType::Withdrawal, 2, 4, Some(Decimal::new(99, 0))),
+        TransactionMessage::new(TransactionType::Dispute, 1, 3, None),
+        TransactionMessage::new(TransactionType::Resolve, 1, 3, None),
+        TransactionMessage::new(TransactionType::Dispute, 2, 2, None),
+        TransactionMessage::new(TransactionType::Chargeback, 2, 2, None),
+        TransactionMessage::new(TransactionType::Deposit, 2, 5, Some(Decimal::ONE)),
+        TransactionMessage::new(TransactionType::Dispute, 3, 1, None),
+        TransactionMessage::new(TransactionType::Deposit, 1, 1, Some(Decimal::ONE)),
+        TransactionMessage {
+            currency: Some(Currency::Usd),
+            ..TransactionMessage::new(TransactionType::Deposit, 1, 6, Some(Decimal::new(7, 1)))
+        },
+        TransactionMessage::new(TransactionType::Dispute, 1, 6, None),
+        TransactionMessage::new(TransactionType::Authorize, 1, 7, Some(Decimal::TWO)),
+        TransactionMessage::new(TransactionType::Capture, 1, 7, Some(Decimal::ONE)),
+        TransactionMessage::new(TransactionType::Authorize, 1, 8, Some(Decimal::ONE)),
+    ]
+}
+
+fn config() -> EngineConfig {
+    EngineConfig {
+        withdrawal_disputes: WithdrawalDisputePolicy::ProvisionalCredit,
+        ..Default::default()
+    }
+}
+
+/// @brief Test that the SQLite store produces exactly the same results as the in-memory maps.
+#[test]
+fn test_sqlite_store_matches_in_memory_store() {
+    let mut in_memory = Engine::new().with_config(config());
+    let store = SqliteStore::in_memory().unwrap();
+    let mut on_disk = Engine::with_stores(store.clone(), store.clone()).with_config(config());
+
+    for msg in history() {
+        assert_eq!(on_disk.apply(msg.clone()), in_memory.apply(msg));
+    }
+
+    assert_eq!(on_disk.accounts(), in_memory.accounts());
+    assert!(on_disk.account(2).unwrap().locked);
+    assert_eq!(store.record_count().unwrap(), 6);
+    assert_eq!(store.record(6).unwrap().unwrap().currency, Currency::Usd);
+    assert_eq!(
+        store.record(2).unwrap().unwrap().state,
+        TransactionState::ChargedBack
+    );
+    assert_eq!(
+        store.record(3).unwrap().unwrap().state,
+        TransactionState::Resolved
+    );
+    assert_eq!(
+        store.record(7).unwrap().unwrap().state,
+        TransactionState::Captured
+    );
+}
+
+/// Account store on a SQLite database whose account writes fail while `fail` is set.
+#[derive(Debug, Clone)]
+struct FailingAccounts {
+    store: SqliteStore,
+    fail: Arc<AtomicBool>,
+}
+
+impl AccountStore for FailingAccounts {
+    fn account(&self, client: u16, currency: Currency) -> std::io::Result<Option<ClientAccount>> {
+        self.store.account(client, currency)
+    }
+
+    fn put_account(
+        &self,
+        client: u16,
+        currency: Currency,
+        account: ClientAccount,
+    ) -> std::io::Result<()> {
+        if self.fail.load(Ordering::SeqCst) {
+            return Err(std::io::Error::other("disk full"));
+        }
+        self.store.put_account(client, currency, account)
+    }
+
+    fn all_accounts(&self) -> std::io::Result<Vec<(u16, Currency, ClientAccount)>> {
+        self.store.all_accounts()
+    }
+
+    fn begin(&self) -> std::io::Result<()> {
+        AccountStore::begin(&self.store)
+    }
+
+    fn commit(&self) -> std::io::Result<()> {
+        AccountStore::commit(&self.store)
+    }
+
+    fn rollback(&self) -> std::io::Result<()> {
+        AccountStore::rollback(&self.store)
+    }
+}
+
+/// @brief Test that a message failing on a storage error partway through leaves no
+/// partial change behind, and that the store keeps working afterwards.
+#[test]
+fn test_sqlite_store_undoes_failed_message() {
+    let store = SqliteStore::in_memory().unwrap();
+    let fail = Arc::new(AtomicBool::new(false));
+    let accounts = FailingAccounts {
+        store: store.clone(),
+        fail: fail.clone(),
+    };
+    let mut engine = Engine::with_stores(accounts, store.clone());
+    let deposit = |tx| TransactionMessage::new(TransactionType::Deposit, 1, tx, Some(Decimal::TEN));
+
+    assert!(engine.apply(deposit(1)).is_ok());
+    // The deposit's record is inserted before the failing account write
+    fail.store(true, Ordering::SeqCst);
+    assert!(matches!(
+        engine.apply(deposit(2)),
+        Err(TransactionError::Storage { tx: 2, .. })
+    ));
+    assert_eq!(store.record(2).unwrap(), None);
+    assert_eq!(store.record_count().unwrap(), 1);
+    assert_eq!(engine.account(1).unwrap().total, Decimal::TEN);
+
+    fail.store(false, Ordering::SeqCst);
+    assert!(engine.apply(deposit(2)).is_ok());
+    assert_eq!(store.record_count().unwrap(), 2);
+    assert_eq!(engine.account(1).unwrap().total, Decimal::from(20));
+}
+
+/// @brief Test storing and reading back accounts and records through the store traits.
+#[test]
+fn test_sqlite_store_round_trip() -> std::io::Result<()> {
+    let dir = TempDir::new()?;
+    let store = SqliteStore::create(&dir.path().join("state.db"))?;
+
+    let account = ClientAccount {
+        available: Decimal::new(-15, 4),
+        held: Decimal::new(30, 1),
+        total: Decimal::new(29985, 4),
+        locked: true,
+    };
+    store.put_account(7, Currency::Usd, account.clone())?;
+    assert_eq!(store.account(7, Currency::Usd)?, Some(account.clone()));
+    assert_eq!(store.account(7, Currency::Eur)?, None);
+    assert_eq!(store.account(8, Currency::Usd)?, None);
+    assert_eq!(store.all_accounts()?, vec![(7, Currency::Usd, account)]);
+
+    let record = TransactionRecord {
+        client_id: 7,
+        amount: Decimal::new(30, 1),
+        state: TransactionState::Processed,
+        tx_type: TransactionType::Withdrawal,
+        destination: None,
+        currency: Currency::Pln,
+    };
+    store.insert_record(u32::MAX, record.clone())?;
+    store.set_state(u32::MAX, TransactionState::Disputed)?;
+
+    let mut seen = Vec::new();
+    store.for_each_record(&mut |tx, record| {
+        seen.push((tx, record));
+        Ok(())
+    })?;
+    assert_eq!(
+        seen,
+        vec![(
+            u32::MAX,
+            TransactionRecord {
+                state: TransactionState::Disputed,
+                ..record
+            }
+        )]
+    );
+
+    // An existing database is never overwritten
+    drop(store);
+    let err = SqliteStore::create(&dir.path().join("state.db")).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+
+    // An existing empty file is reused
+    std::fs::File::create(dir.path().join("empty.db"))?;
+    let store = SqliteStore::create(&dir.path().join("empty.db"))?;
+    assert_eq!(store.record_count()?, 0);
+
+    Ok(())
+}
+
+/// @brief Test that snapshots can move state between the in-memory and SQLite stores.
+#[test]
+fn test_snapshot_between_stores() -> std::io::Result<()> {
+    let dir = TempDir::new()?;
+    let path = dir.path().join("state.snapshot");
+
+    let clients: ClientsMap = Arc::new(DashMap::new());
+    let transactions: TransactionsMap = Arc::new(DashMap::new());
+    let mut engine = Engine::with_maps(clients.clone(), transactions.clone()).with_config(config());
+    for msg in history() {
+        let _ = engine.apply(msg);
+    }
+    save_snapshot(&path, &clients, &transactions, None)?;
+
+    let store = SqliteStore::in_memory()?;
+    let stats = load_snapshot(&path, &store, &store)?;
+    assert_eq!(stats.transactions, transactions.len());
+
+    let restored = Engine::with_stores(store.clone(), store);
+    assert_eq!(restored.accounts(), engine.accounts());
+
+    Ok(())
+}
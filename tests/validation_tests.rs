@@ -0,0 +1,211 @@
+use payments_engine::engine::Engine;
+use payments_engine::structures::{
+    EngineConfig, Outcome, TransactionError, TransactionMessage, TransactionType, ValidationMode,
+};
+use payments_engine::validation::validate;
+use rust_decimal::Decimal;
+
+/// @brief Test that missing and negative amounts are rejected in both modes.
+#[test]
+fn test_missing_and_negative_amounts_rejected() {
+    for mode in [ValidationMode::Strict, ValidationMode::Lenient] {
+        let missing = TransactionMessage::new(TransactionType::Deposit, 1, 1, None);
+        assert_eq!(
+            validate(missing, mode),
+            Err(TransactionError::MissingAmount { tx: 1 })
+        );
+
+        let negative = TransactionMessage::new(
+            TransactionType::Withdrawal,
+            1,
+            2,
+            Some(Decimal::new(-15, 1)),
+        );
+        assert_eq!(
+            validate(negative, mode),
+            Err(TransactionError::NegativeAmount {
+                tx: 2,
+                amount: Decimal::new(-15, 1),
+            })
+        );
+    }
+}
+
+/// @brief Test strict validation of zero amounts, excess precision and unexpected amounts.
+#[test]
+fn test_strict_mode_rejects() {
+    let zero = TransactionMessage::new(TransactionType::Deposit, 1, 1, Some(Decimal::ZERO));
+    assert_eq!(
+        validate(zero, ValidationMode::Strict),
+        Err(TransactionError::ZeroAmount { tx: 1 })
+    );
+
+    let precise = TransactionMessage::new(
+        TransactionType::Deposit,
+        1,
+        2,
+        Some(Decimal::new(123456, 5)), // 1.23456
+    );
+    assert_eq!(
+        validate(precise, ValidationMode::Strict),
+        Err(TransactionError::ExcessivePrecision {
+            tx: 2,
+            amount: Decimal::new(123456, 5),
+        })
+    );
+
+    let dispute = TransactionMessage::new(TransactionType::Dispute, 1, 2, Some(Decimal::ONE));
+    assert_eq!(
+        validate(dispute, ValidationMode::Strict),
+        Err(TransactionError::UnexpectedAmount { tx: 2 })
+    );
+
+    // Trailing zeros beyond four places are not excess precision
+    let padded = TransactionMessage::new(
+        TransactionType::Deposit,
+        1,
+        3,
+        Some(Decimal::new(1500000, 6)), // 1.500000
+    );
+    assert!(validate(padded, ValidationMode::Strict).is_ok());
+}
+
+/// @brief Test lenient normalization of zero amounts, excess precision and unexpected amounts.
+#[test]
+fn test_lenient_mode_normalizes() {
+    let zero = TransactionMessage::new(TransactionType::Deposit, 1, 1, Some(Decimal::ZERO));
+    assert_eq!(
+        validate(zero, ValidationMode::Lenient).unwrap().amount,
+        Some(Decimal::ZERO)
+    );
+
+    let precise = TransactionMessage::new(
+        TransactionType::Deposit,
+        1,
+        2,
+        Some(Decimal::new(123456, 5)), // 1.23456
+    );
+    assert_eq!(
+        validate(precise, ValidationMode::Lenient).unwrap().amount,
+        Some(Decimal::new(12346, 4)) // 1.2346
+    );
+
+    let dispute = TransactionMessage::new(TransactionType::Dispute, 1, 2, Some(Decimal::ONE));
+    assert_eq!(
+        validate(dispute, ValidationMode::Lenient).unwrap().amount,
+        None
+    );
+}
+
+/// @brief Test that the engine applies validation before touching any state.
+///
+/// A negative deposit must neither credit the client nor create its account, and in
+/// strict mode an over-precise deposit is rejected instead of being rounded.
+#[test]
+fn test_engine_rejects_invalid_messages() {
+    let mut engine = Engine::new().with_config(EngineConfig {
+        validation: ValidationMode::Strict,
+        ..Default::default()
+    });
+
+    let mut negative =
+        TransactionMessage::new(TransactionType::Deposit, 1, 1, Some(Decimal::new(-1, 0)));
+    negative.line = Some(2);
+    assert_eq!(
+        engine.apply(negative),
+        Err(TransactionError::NegativeAmount {
+            tx: 1,
+            amount: Decimal::new(-1, 0),
+        })
+    );
+    assert!(engine.account(1).is_none());
+
+    assert_eq!(
+        engine.apply(TransactionMessage::new(
+            TransactionType::Deposit,
+            1,
+            2,
+            Some(Decimal::new(100001, 5)),
+        )),
+        Err(TransactionError::ExcessivePrecision {
+            tx: 2,
+            amount: Decimal::new(100001, 5),
+        })
+    );
+    assert_eq!(
+        engine.apply(TransactionMessage::new(
+            TransactionType::Deposit,
+            1,
+            3,
+            Some(Decimal::new(10001, 4)),
+        )),
+        Ok(Outcome::Applied)
+    );
+    assert_eq!(engine.account(1).unwrap().total, Decimal::new(10001, 4));
+}
+
+/// @brief Test that administrative messages require an operator, and adjustments a reason
+/// code and an amount whose magnitude follows the deposit rules.
+#[test]
+fn test_admin_messages_validated() {
+    let admin = |msg: TransactionMessage| TransactionMessage {
+        operator: Some("ops-1".to_owned()),
+        reason: Some("correction".to_owned()),
+        ..msg
+    };
+    for mode in [ValidationMode::Strict, ValidationMode::Lenient] {
+        let unlock = TransactionMessage::new(TransactionType::Unlock, 1, 1, None);
+        assert_eq!(
+            validate(unlock.clone(), mode),
+            Err(TransactionError::MissingOperator { tx: 1 })
+        );
+        let blank = TransactionMessage {
+            operator: Some(String::new()),
+            ..unlock.clone()
+        };
+        assert_eq!(
+            validate(blank, mode),
+            Err(TransactionError::MissingOperator { tx: 1 })
+        );
+        assert!(validate(admin(unlock), mode).is_ok());
+
+        let debit = TransactionMessage::new(
+            TransactionType::Adjustment,
+            1,
+            2,
+            Some(Decimal::new(-15, 1)),
+        );
+        assert_eq!(
+            validate(admin(debit.clone()), mode).unwrap().amount,
+            Some(Decimal::new(-15, 1))
+        );
+        let unexplained = TransactionMessage {
+            reason: None,
+            ..admin(debit)
+        };
+        assert_eq!(
+            validate(unexplained, mode),
+            Err(TransactionError::MissingReason { tx: 2 })
+        );
+    }
+
+    let precise = TransactionMessage::new(
+        TransactionType::Adjustment,
+        1,
+        3,
+        Some(Decimal::new(-100005, 5)),
+    );
+    assert_eq!(
+        validate(admin(precise.clone()), ValidationMode::Lenient)
+            .unwrap()
+            .amount,
+        Some(Decimal::new(-10000, 4))
+    );
+    assert_eq!(
+        validate(admin(precise), ValidationMode::Strict),
+        Err(TransactionError::ExcessivePrecision {
+            tx: 3,
+            amount: Decimal::new(100005, 5),
+        })
+    );
+}
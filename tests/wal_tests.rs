@@ -0,0 +1,240 @@
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+use clap::Parser;
+use dashmap::DashMap;
+use payments_engine::engine::{Engine, EngineHandle};
+use payments_engine::producer::process_file_from;
+use payments_engine::snapshot::{load_snapshot, save_snapshot};
+use payments_engine::structures::{
+    Args, ClientsMap, ResumePoints, TransactionMessage, TransactionType, TransactionsMap,
+};
+use payments_engine::wal::{WalWriter, read_wal, recover, recover_with};
+use rust_decimal::Decimal;
+use tempfile::TempDir;
+use tokio::sync::mpsc;
+
+/// Helper reading the messages of an input file, skipping lines up to the resume points.
+async fn read_input(path: &str, resume: ResumePoints) -> Vec<TransactionMessage> {
+    let (tx, mut rx) = mpsc::channel(100);
+    let args = Args::parse_from(["payments_engine", path]);
+    process_file_from(args, tx, resume, None).await.unwrap();
+
+    let mut messages = Vec::new();
+    while let Some(msg) = rx.recv().await {
+        messages.push(msg);
+    }
+    messages
+}
+
+fn new_maps() -> (ClientsMap, TransactionsMap) {
+    (Arc::new(DashMap::new()), Arc::new(DashMap::new()))
+}
+
+/// Helper feeding `messages` to an engine running on its own task.
+async fn run_engine(engine: Engine, messages: Vec<TransactionMessage>) -> Engine {
+    let handle = EngineHandle::spawn(engine, 10, None);
+    let sender = handle.sender();
+    for msg in messages {
+        sender.send(msg).await.unwrap();
+    }
+    drop(sender);
+    handle.join().await.unwrap()
+}
+
+/// @brief Test that recovering from the log of an interrupted run reproduces the full run.
+///
+/// Every possible interruption point of each input file is tried: the first run processes
+/// only a prefix of the rows, the second run replays the log and reads the rest of the file.
+/// The second input has administrative rows, whose operator and reason must survive the log.
+#[tokio::test]
+async fn test_recovery_matches_uninterrupted_run() {
+    for input in ["sets/input_003.csv", "sets/input_011.csv"] {
+        let all = read_input(input, ResumePoints::new()).await;
+        let expected = run_engine(Engine::new(), all.clone()).await.accounts();
+
+        for processed in 0..=all.len() {
+            let dir = TempDir::new().unwrap();
+            let wal_path = dir.path().join("engine.wal");
+
+            let wal = WalWriter::open(&wal_path, 2).unwrap();
+            let interrupted = Engine::new().with_wal(wal);
+            run_engine(interrupted, all[..processed].to_vec()).await;
+
+            let mut engine = Engine::new();
+            let stats = recover(&wal_path, &mut engine).unwrap();
+            assert_eq!(stats.replayed, processed);
+
+            let rest = read_input(input, stats.resume).await;
+            assert_eq!(rest.len(), all.len() - processed);
+
+            let engine = engine.with_wal(WalWriter::open(&wal_path, 2).unwrap());
+            let recovered = run_engine(engine, rest).await;
+            assert_eq!(recovered.accounts(), expected);
+            assert_eq!(read_wal(&wal_path).unwrap(), all);
+        }
+    }
+}
+
+/// @brief Test that a torn trailing entry is ignored and truncated on recovery.
+#[test]
+fn test_torn_tail_discarded() -> std::io::Result<()> {
+    let dir = TempDir::new()?;
+    let path = dir.path().join("engine.wal");
+
+    let deposit = TransactionMessage {
+        line: Some(2),
+        ..TransactionMessage::new(TransactionType::Deposit, 1, 1, Some(Decimal::new(15, 1)))
+    };
+    let mut wal = WalWriter::open(&path, 100)?;
+    wal.append(&deposit)?;
+    wal.sync()?;
+    drop(wal);
+
+    let mut file = std::fs::OpenOptions::new().append(true).open(&path)?;
+    write!(file, r#"{{"type":"withdrawal","client":1,"#)?;
+    drop(file);
+
+    assert_eq!(read_wal(&path)?, vec![deposit.clone()]);
+
+    let mut engine = Engine::new();
+    let stats = recover(&path, &mut engine)?;
+    assert_eq!(stats.replayed, 1);
+    assert_eq!(stats.resume, ResumePoints::from([(0, 2)]));
+    assert_eq!(engine.account(1).unwrap().available, Decimal::new(15, 1));
+
+    // New entries start on a fresh line after the truncated tail
+    let mut wal = WalWriter::open(&path, 1)?;
+    wal.append(&deposit)?;
+    assert_eq!(read_wal(&path)?.len(), 2);
+
+    Ok(())
+}
+
+/// @brief Test that a corrupted complete entry is reported instead of silently skipped.
+#[test]
+fn test_corrupted_entry_rejected() -> std::io::Result<()> {
+    let dir = TempDir::new()?;
+    let path = dir.path().join("engine.wal");
+    std::fs::write(&path, "not a log entry\n")?;
+
+    let err = read_wal(&path).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert!(read_wal(Path::new("does/not/exist.wal"))?.is_empty());
+
+    Ok(())
+}
+
+/// @brief Test that a failed append stops the engine before it processes anything else.
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_append_failure_stops_engine() {
+    // Every write to /dev/full fails with "no space left on device"
+    let wal = WalWriter::open(Path::new("/dev/full"), 1).unwrap();
+    let handle = EngineHandle::spawn(Engine::new().with_wal(wal), 10, None);
+    let sender = handle.sender();
+    for tx in 1..=2 {
+        let msg = TransactionMessage::new(TransactionType::Deposit, 1, tx, Some(Decimal::ONE));
+        let _ = sender.send(msg).await;
+    }
+    drop(sender);
+
+    let engine = handle.join().await.unwrap();
+    assert!(engine.wal_failure().is_some());
+    assert_eq!(engine.account(1).unwrap().total, Decimal::ONE);
+}
+
+/// @brief Test that recovery reports the outcome of every replayed message, as the
+/// processing loop did when the messages were logged.
+#[tokio::test]
+async fn test_recovery_reports_replayed_outcomes() {
+    let all = read_input("sets/input_011.csv", ResumePoints::new()).await;
+    let dir = TempDir::new().unwrap();
+    let wal_path = dir.path().join("engine.wal");
+    let wal = WalWriter::open(&wal_path, 1).unwrap();
+    run_engine(Engine::new().with_wal(wal), all.clone()).await;
+
+    let mut replayed = Vec::new();
+    recover_with(&wal_path, &mut Engine::new(), None, |processed| {
+        replayed.push(processed)
+    })
+    .unwrap();
+
+    let mut engine = Engine::new();
+    let expected: Vec<_> = all.into_iter().map(|msg| engine.apply(msg)).collect();
+    assert_eq!(
+        replayed
+            .into_iter()
+            .map(|processed| processed.result)
+            .collect::<Vec<_>>(),
+        expected
+    );
+}
+
+/// @brief Test that entries a snapshot already includes are not applied again when the
+/// run crashed after writing the snapshot but before emptying the log.
+#[tokio::test]
+async fn test_recovery_skips_entries_in_snapshot() {
+    let all = read_input("sets/input_003.csv", ResumePoints::new()).await;
+    let dir = TempDir::new().unwrap();
+    let wal_path = dir.path().join("engine.wal");
+    let snapshot_path = dir.path().join("state.snapshot");
+
+    let (clients, transactions) = new_maps();
+    let wal = WalWriter::open(&wal_path, 2).unwrap();
+    let engine = Engine::with_maps(clients.clone(), transactions.clone()).with_wal(wal);
+    let completed = run_engine(engine, all.clone()).await;
+    let expected = completed.accounts();
+    assert_eq!(completed.wal_seq(), Some(all.len() as u64));
+    save_snapshot(&snapshot_path, &clients, &transactions, completed.wal_seq()).unwrap();
+
+    let (clients, transactions) = new_maps();
+    let snapshot = load_snapshot(&snapshot_path, &clients, &transactions).unwrap();
+    let mut engine = Engine::with_maps(clients, transactions);
+    let stats = recover_with(&wal_path, &mut engine, snapshot.wal_seq, |_| {}).unwrap();
+    assert_eq!((stats.replayed, stats.skipped), (0, all.len()));
+    assert_eq!(stats.last_seq, all.len() as u64);
+    assert_eq!(engine.accounts(), expected);
+    assert!(
+        read_input("sets/input_003.csv", stats.resume)
+            .await
+            .is_empty()
+    );
+}
+
+/// @brief A run recovering entries from the log refuses to write a rejected report or a
+/// statement, which could not include the rows of the interrupted run, and leaves the log
+/// intact.
+#[test]
+fn test_binary_refuses_reports_on_recovery() {
+    use std::process::{Command, Stdio};
+
+    let dir = TempDir::new().unwrap();
+    let wal_path = dir.path().join("engine.wal");
+    let mut wal = WalWriter::open(&wal_path, 1).unwrap();
+    wal.append(&TransactionMessage {
+        line: Some(2),
+        ..TransactionMessage::new(TransactionType::Deposit, 1, 1, Some(Decimal::ONE))
+    })
+    .unwrap();
+    drop(wal);
+    let logged = std::fs::read(&wal_path).unwrap();
+
+    for report in ["--rejected-output", "--statement"] {
+        let report_path = dir.path().join("report.csv");
+        let status = Command::new(env!("CARGO_BIN_EXE_payments_engine"))
+            .args(["sets/input_003.csv", "--wal"])
+            .arg(&wal_path)
+            .arg(report)
+            .arg(&report_path)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .unwrap();
+
+        assert!(!status.success(), "{report}");
+        assert!(!report_path.exists(), "{report}");
+        assert_eq!(std::fs::read(&wal_path).unwrap(), logged);
+    }
+}